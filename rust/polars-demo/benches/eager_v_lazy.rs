@@ -72,8 +72,8 @@ fn lazy() -> Result<DataFrame, Box<dyn std::error::Error>> {
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("eager", |b| b.iter(|| eager()));
-    c.bench_function("lazy", |b| b.iter(|| lazy()));
+    c.bench_function("eager", |b| b.iter(eager));
+    c.bench_function("lazy", |b| b.iter(lazy));
 }
 
 criterion_group!(
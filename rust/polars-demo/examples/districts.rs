@@ -0,0 +1,37 @@
+use polars::prelude::*;
+use polars_demo::dataset::Dataset;
+use polars_demo::join::{sjoin, SpatialJoinOptions};
+use std::path::PathBuf;
+
+// cargo run --example districts -- <districts.geojson> [name column]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let districts_path = PathBuf::from(
+        args.next()
+            .ok_or("usage: districts <districts.geojson> [name column]")?,
+    );
+    let name_col = args.next().unwrap_or_else(|| "ENAME".to_string());
+
+    let path = std::env::temp_dir().join("hk_buildings.parquet");
+    let buildings = Dataset::scan(&path)?;
+    let districts = Dataset::read_geojson(&districts_path)?;
+
+    // buildings are assigned to the district containing their centroid
+    let joined = sjoin(&buildings, &districts, SpatialJoinOptions::default())?;
+
+    println!("\n=== Buildings per District ===");
+    let per_district = joined
+        .lazy()
+        .group_by([col(&name_col)])
+        .agg([
+            col("OBJECTID").count().alias("building_count"),
+            col("TOPHEIGHT").mean().alias("avg_height"),
+        ])
+        .sort(
+            ["building_count"],
+            SortMultipleOptions::default().with_order_descending(true),
+        )
+        .collect()?;
+    println!("{}", per_district);
+    Ok(())
+}
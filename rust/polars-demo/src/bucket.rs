@@ -0,0 +1,403 @@
+use polars::prelude::*;
+
+/// How bucket edges are chosen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binning {
+    /// Equal-width buckets aligned to multiples of the width.
+    Width(f64),
+    /// This many buckets holding about the same number of values, from the
+    /// column's own quantiles. The minimum and maximum are always included.
+    Quantiles(usize),
+    /// Buckets between consecutive powers of the base, e.g. 10 gives
+    /// 1-10, 10-100, ... Values that are not positive get no bucket.
+    Log(f64),
+    /// Buckets between consecutive user-supplied edges. Values outside the
+    /// first and last edge get no bucket.
+    Breaks(Vec<f64>),
+}
+
+/// Which edge of a bucket is part of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Closed {
+    /// `[lower, upper)`
+    #[default]
+    Left,
+    /// `(lower, upper]`
+    Right,
+}
+
+impl Closed {
+    /// Opening and closing brackets of a bucket label.
+    pub(crate) fn brackets(self) -> (char, char) {
+        match self {
+            Closed::Left => ('[', ')'),
+            Closed::Right => ('(', ']'),
+        }
+    }
+}
+
+/// The `{lower, upper}` struct type returned by [`bucket`].
+pub fn bucket_dtype() -> DataType {
+    DataType::Struct(vec![
+        Field::new("lower".into(), DataType::Float64),
+        Field::new("upper".into(), DataType::Float64),
+    ])
+}
+
+/// The bucket of each value as a `{lower, upper}` struct, with null bounds where
+/// a value falls in no bucket.
+///
+/// Structs sort and group by `lower` first, so buckets come out in numeric
+/// order; use [`bucket_label`] for display. Quantile and log edges depend on the
+/// whole column, so they are aggregated first and only then applied row by row.
+pub fn bucket(value: Expr, binning: Binning, closed: Closed) -> Expr {
+    let value = value.cast(DataType::Float64);
+    let finite = value.clone().filter(value.clone().is_finite());
+    let aggregates: Vec<Expr> = match &binning {
+        Binning::Quantiles(n) => (0..=*n)
+            .map(|i| {
+                finite
+                    .clone()
+                    .quantile(lit(i as f64 / (*n).max(1) as f64), QuantileMethod::Linear)
+            })
+            .collect(),
+        Binning::Log(_) => {
+            let positive = finite.clone().filter(finite.gt(lit(0.0)));
+            vec![positive.clone().min(), positive.max()]
+        }
+        Binning::Width(_) | Binning::Breaks(_) => Vec::new(),
+    };
+    value.map_many(
+        move |s| {
+            let values = s[0].f64()?;
+            let aggregates = s[1..]
+                .iter()
+                .map(|c| Ok(c.cast(&DataType::Float64)?.f64()?.get(0)))
+                .collect::<PolarsResult<Vec<Option<f64>>>>()?;
+            let bounds: Vec<Option<(f64, f64)>> = match &binning {
+                Binning::Width(width) => {
+                    if !width.is_finite() || *width <= 0.0 {
+                        polars_bail!(ComputeError: "bucket width must be positive, got {}", width)
+                    }
+                    values
+                        .into_iter()
+                        .map(|v| v.and_then(|v| width_bucket(v, *width, closed)))
+                        .collect()
+                }
+                Binning::Log(base) => {
+                    if !base.is_finite() || *base <= 1.0 {
+                        polars_bail!(ComputeError: "log bucket base must be above 1, got {}", base)
+                    }
+                    let edges = log_edges(aggregates[0], aggregates[1], *base);
+                    values
+                        .into_iter()
+                        .map(|v| v.and_then(|v| edges_bucket(v, &edges, closed, false)))
+                        .collect()
+                }
+                Binning::Breaks(edges) => {
+                    if edges
+                        .windows(2)
+                        .any(|w| w[0].partial_cmp(&w[1]) != Some(std::cmp::Ordering::Less))
+                    {
+                        polars_bail!(ComputeError: "bucket breaks must be strictly increasing")
+                    }
+                    values
+                        .into_iter()
+                        .map(|v| v.and_then(|v| edges_bucket(v, edges, closed, false)))
+                        .collect()
+                }
+                Binning::Quantiles(n) => {
+                    if *n == 0 {
+                        polars_bail!(ComputeError: "need at least one quantile bucket")
+                    }
+                    let edges = quantile_edges(&aggregates);
+                    values
+                        .into_iter()
+                        .map(|v| v.and_then(|v| edges_bucket(v, &edges, closed, true)))
+                        .collect()
+                }
+            };
+            let lower: Float64Chunked = bounds.iter().map(|b| b.map(|b| b.0)).collect();
+            let upper: Float64Chunked = bounds.iter().map(|b| b.map(|b| b.1)).collect();
+            let fields = [
+                lower.with_name("lower".into()).into_series(),
+                upper.with_name("upper".into()).into_series(),
+            ];
+            let ca = StructChunked::from_series(s[0].name().clone(), bounds.len(), fields.iter())?;
+            Ok(Some(ca.into_column()))
+        },
+        &aggregates,
+        GetOutput::from_type(bucket_dtype()),
+    )
+}
+
+fn width_bucket(v: f64, width: f64, closed: Closed) -> Option<(f64, f64)> {
+    if !v.is_finite() {
+        return None;
+    }
+    let lower = match closed {
+        Closed::Left => (v / width).floor() * width,
+        Closed::Right => ((v / width).ceil() - 1.0) * width,
+    };
+    Some((lower, lower + width))
+}
+
+/// Powers of `base` from the one below `min` to the one above `max`, the
+/// positive extent of the column; none when it has no positive values.
+fn log_edges(min: Option<f64>, max: Option<f64>, base: f64) -> Vec<f64> {
+    let (Some(min), Some(max)) = (min, max) else {
+        return Vec::new();
+    };
+    // the float logarithm can be off by one right at a power of the base
+    let exponent = |v: f64| {
+        let mut k = v.log(base).floor() as i32;
+        while base.powi(k + 1) <= v {
+            k += 1;
+        }
+        while base.powi(k) > v {
+            k -= 1;
+        }
+        k
+    };
+    (exponent(min) - 1..=exponent(max) + 1)
+        .map(|k| base.powi(k))
+        .collect()
+}
+
+fn edges_bucket(v: f64, edges: &[f64], closed: Closed, inclusive: bool) -> Option<(f64, f64)> {
+    if !v.is_finite() {
+        return None;
+    }
+    let i = match closed {
+        Closed::Left => edges.partition_point(|e| *e <= v),
+        Closed::Right => edges.partition_point(|e| *e < v),
+    };
+    // the outermost edges of quantile buckets belong to them whichever side is closed
+    let i = match closed {
+        Closed::Left if inclusive && edges.last() == Some(&v) => i - 1,
+        Closed::Right if inclusive && edges.first() == Some(&v) => 1,
+        _ => i,
+    };
+    if i == 0 || i >= edges.len() {
+        return None;
+    }
+    Some((edges[i - 1], edges[i]))
+}
+
+/// Quantile bucket edges from the column's `n + 1` quantiles, with repeats
+/// removed; none when the column has no finite values.
+fn quantile_edges(quantiles: &[Option<f64>]) -> Vec<f64> {
+    let mut edges: Vec<f64> = quantiles.iter().flatten().copied().collect();
+    if edges.is_empty() {
+        return edges;
+    }
+    edges.dedup();
+    if edges.len() == 1 {
+        // every value is the same, so they all share one zero-width bucket
+        edges.push(edges[0]);
+    }
+    edges
+}
+
+/// Display a `{lower, upper}` bucket as `[1000, 2000)` or `(1000, 2000]`.
+pub fn bucket_label(bucket: Expr, closed: Closed) -> Expr {
+    bucket.map(
+        move |s| {
+            let ca = s.struct_()?;
+            let lower = ca.field_by_name("lower")?;
+            let upper = ca.field_by_name("upper")?;
+            let (open, close) = closed.brackets();
+            let labels: StringChunked = lower
+                .f64()?
+                .into_iter()
+                .zip(upper.f64()?)
+                .map(|(lower, upper)| Some(format!("{}{}, {}{}", open, lower?, upper?, close)))
+                .collect();
+            Ok(Some(labels.with_name(s.name().clone()).into_column()))
+        },
+        GetOutput::from_type(DataType::String),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Bounds = Vec<Option<(f64, f64)>>;
+
+    fn buckets(values: &[Option<f64>], binning: Binning, closed: Closed) -> PolarsResult<Bounds> {
+        let df = df!["v" => values]?;
+        let out = df
+            .lazy()
+            .select([bucket(col("v"), binning, closed)])
+            .collect()?;
+        let ca = out.column("v")?.struct_()?.clone();
+        let lower = ca.field_by_name("lower")?;
+        let upper = ca.field_by_name("upper")?;
+        Ok(lower
+            .f64()?
+            .into_iter()
+            .zip(upper.f64()?)
+            .map(|(l, u)| Some((l?, u?)))
+            .collect())
+    }
+
+    fn every(binning: Binning) -> impl Iterator<Item = (Binning, Closed)> {
+        [Closed::Left, Closed::Right]
+            .into_iter()
+            .map(move |closed| (binning.clone(), closed))
+    }
+
+    fn all_binnings() -> impl Iterator<Item = (Binning, Closed)> {
+        every(Binning::Width(10.0))
+            .chain(every(Binning::Quantiles(4)))
+            .chain(every(Binning::Log(10.0)))
+            .chain(every(Binning::Breaks(vec![0.0, 10.0])))
+    }
+
+    #[test]
+    fn empty_and_null_input() {
+        for (binning, closed) in all_binnings() {
+            assert!(buckets(&[], binning.clone(), closed).unwrap().is_empty());
+            let nulls = buckets(&[None, Some(f64::NAN)], binning, closed).unwrap();
+            assert_eq!(nulls, [None, None]);
+        }
+    }
+
+    #[test]
+    fn values_on_edges_follow_closed() {
+        let values = [Some(0.0), Some(5.0), Some(10.0), Some(20.0)];
+        let left = buckets(&values, Binning::Width(10.0), Closed::Left).unwrap();
+        assert_eq!(
+            left,
+            [
+                Some((0.0, 10.0)),
+                Some((0.0, 10.0)),
+                Some((10.0, 20.0)),
+                Some((20.0, 30.0))
+            ]
+        );
+        let right = buckets(&values, Binning::Width(10.0), Closed::Right).unwrap();
+        assert_eq!(
+            right,
+            [
+                Some((-10.0, 0.0)),
+                Some((0.0, 10.0)),
+                Some((0.0, 10.0)),
+                Some((10.0, 20.0))
+            ]
+        );
+
+        let breaks = Binning::Breaks(vec![0.0, 10.0, 20.0]);
+        let left = buckets(&values, breaks.clone(), Closed::Left).unwrap();
+        assert_eq!(
+            left,
+            [
+                Some((0.0, 10.0)),
+                Some((0.0, 10.0)),
+                Some((10.0, 20.0)),
+                None
+            ]
+        );
+        let right = buckets(&values, breaks, Closed::Right).unwrap();
+        assert_eq!(
+            right,
+            [
+                None,
+                Some((0.0, 10.0)),
+                Some((0.0, 10.0)),
+                Some((10.0, 20.0))
+            ]
+        );
+
+        let powers = [Some(1.0), Some(10.0), Some(100.0)];
+        let left = buckets(&powers, Binning::Log(10.0), Closed::Left).unwrap();
+        assert_eq!(
+            left,
+            [
+                Some((1.0, 10.0)),
+                Some((10.0, 100.0)),
+                Some((100.0, 1000.0))
+            ]
+        );
+        let right = buckets(&powers, Binning::Log(10.0), Closed::Right).unwrap();
+        assert_eq!(
+            right,
+            [Some((0.1, 1.0)), Some((1.0, 10.0)), Some((10.0, 100.0))]
+        );
+    }
+
+    #[test]
+    fn quantile_buckets_include_the_extremes() {
+        let values: Vec<_> = (0..=8).map(|v| Some(v as f64)).collect();
+        for closed in [Closed::Left, Closed::Right] {
+            let out = buckets(&values, Binning::Quantiles(4), closed).unwrap();
+            assert_eq!(out[0], Some((0.0, 2.0)));
+            assert_eq!(out[8], Some((6.0, 8.0)));
+            // an inner edge goes to the bucket on its closed side
+            let two = if closed == Closed::Left {
+                (2.0, 4.0)
+            } else {
+                (0.0, 2.0)
+            };
+            assert_eq!(out[2], Some(two));
+        }
+    }
+
+    #[test]
+    fn all_equal_values() {
+        let values = [Some(7.0); 3];
+        for closed in [Closed::Left, Closed::Right] {
+            let out = buckets(&values, Binning::Quantiles(4), closed).unwrap();
+            assert_eq!(out, [Some((7.0, 7.0)); 3]);
+            let out = buckets(&values, Binning::Log(10.0), closed).unwrap();
+            assert_eq!(out, [Some((1.0, 10.0)); 3]);
+        }
+    }
+
+    #[test]
+    fn log_skips_values_that_are_not_positive() {
+        let values = [Some(-5.0), Some(0.0), Some(50.0)];
+        for closed in [Closed::Left, Closed::Right] {
+            let out = buckets(&values, Binning::Log(10.0), closed).unwrap();
+            assert_eq!(out, [None, None, Some((10.0, 100.0))]);
+        }
+        let out = buckets(&[Some(-1.0)], Binning::Log(2.0), Closed::Left).unwrap();
+        assert_eq!(out, [None]);
+    }
+
+    #[test]
+    fn edges_come_from_the_whole_column() {
+        // split into chunks, so a per-chunk computation would see other extremes
+        let mut a = Series::new("v".into(), [0.0, 1.0, 2.0, 3.0]);
+        a.append(&Series::new("v".into(), [4.0, 5.0, 6.0, 7.0, 8.0]))
+            .unwrap();
+        let df = DataFrame::new(vec![a.into_column()]).unwrap();
+        let out = df
+            .lazy()
+            .select([bucket_label(
+                bucket(col("v"), Binning::Quantiles(2), Closed::Left),
+                Closed::Left,
+            )])
+            .collect()
+            .unwrap();
+        let labels: Vec<_> = out
+            .column("v")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        let (low, high) = ("[0, 4)", "[4, 8)");
+        assert_eq!(labels, [low, low, low, low, high, high, high, high, high]);
+    }
+
+    #[test]
+    fn invalid_binning_fails() {
+        let values = [Some(1.0)];
+        assert!(buckets(&values, Binning::Width(0.0), Closed::Left).is_err());
+        assert!(buckets(&values, Binning::Log(1.0), Closed::Left).is_err());
+        assert!(buckets(&values, Binning::Quantiles(0), Closed::Left).is_err());
+        assert!(buckets(&values, Binning::Breaks(vec![1.0, 1.0]), Closed::Left).is_err());
+    }
+}
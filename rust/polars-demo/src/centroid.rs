@@ -0,0 +1,75 @@
+use crate::crs::{keep_crs, keeps_crs};
+use crate::geometry::{decode_geometries, point_dtype, points_column};
+use geo::Centroid;
+use polars::prelude::*;
+
+/// Centroid of each geometry as an `{x, y}` struct.
+///
+/// Holes and every part of a multi-geometry are taken into account. Null,
+/// empty or undecodable geometries give a null centroid instead of an error.
+/// The points keep the CRS tag of a reprojected `geometry` column.
+pub fn centroid(geometry: Expr) -> Expr {
+    geometry.map(
+        |s| {
+            let decoded = decode_geometries(&s)?;
+            let points: Vec<_> = decoded
+                .geometries
+                .iter()
+                .map(|g| g.as_ref().and_then(|g| g.centroid()).map(|p| p.0))
+                .collect();
+            keep_crs(&s, points_column(s.name().clone(), &points)?).map(Some)
+        },
+        keeps_crs(point_dtype()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::point_coords;
+    use crate::test_util::{geometries, list, polygons, pos, square};
+    use geo::Coord;
+
+    fn centroids(geometry: Column) -> Vec<Option<Coord>> {
+        let df = DataFrame::new(vec![geometry])
+            .unwrap()
+            .lazy()
+            .select([centroid(col("geometry"))])
+            .collect()
+            .unwrap();
+        point_coords(df.column("geometry").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn centroid_of_square_is_its_centre() {
+        let got = centroids(polygons(vec![Some(list(vec![square(0.0, 0.0, 2.0)]))]));
+        assert_eq!(got, [Some(Coord { x: 1.0, y: 1.0 })]);
+    }
+
+    #[test]
+    fn holes_move_the_centroid() {
+        let got = centroids(polygons(vec![Some(list(vec![
+            square(0.0, 0.0, 4.0),
+            square(0.0, 0.0, 2.0),
+        ]))]));
+        let c = got[0].unwrap();
+        // the 12 remaining unit squares average to (7/3, 7/3)
+        assert!((c.x - 7.0 / 3.0).abs() < 1e-9 && (c.y - 7.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn null_and_malformed_geometries_give_null() {
+        let column = geometries(
+            "geometry",
+            vec![
+                None,
+                Some(("Polygon", list(vec![list(vec![pos(1.0, 1.0)])]))),
+                Some(("Blob", list(vec![list(vec![pos(1.0, 1.0)])]))),
+            ],
+        );
+        let got = centroids(column);
+        assert_eq!(got[0], None);
+        assert_eq!(got[1], Some(Coord { x: 1.0, y: 1.0 }));
+        assert_eq!(got[2], None);
+    }
+}
@@ -0,0 +1,447 @@
+use crate::geometry::{decode_geometries, encode_geometries, point_dtype, points_column};
+use geo::{Coord, Geometry, MapCoords};
+use polars::prelude::*;
+use proj4rs::Proj;
+use std::fmt;
+use std::str::FromStr;
+
+/// The coordinate reference systems the buildings data can be expressed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Crs {
+    /// EPSG:4326, longitude/latitude in degrees. What `download_data` requests.
+    #[default]
+    Wgs84,
+    /// EPSG:2326, Hong Kong 1980 Grid eastings/northings in metres.
+    Hk1980Grid,
+    /// EPSG:3857, spherical Web Mercator in metres.
+    WebMercator,
+}
+
+impl Crs {
+    pub fn epsg(self) -> u32 {
+        match self {
+            Crs::Wgs84 => 4326,
+            Crs::Hk1980Grid => 2326,
+            Crs::WebMercator => 3857,
+        }
+    }
+
+    pub fn from_epsg(code: u32) -> Option<Crs> {
+        match code {
+            4326 => Some(Crs::Wgs84),
+            2326 => Some(Crs::Hk1980Grid),
+            3857 => Some(Crs::WebMercator),
+            _ => None,
+        }
+    }
+
+    /// Whether coordinates are longitude/latitude degrees rather than metres.
+    pub fn is_geographic(self) -> bool {
+        self == Crs::Wgs84
+    }
+
+    fn proj_string(self) -> &'static str {
+        match self {
+            Crs::Wgs84 => "+proj=longlat +datum=WGS84 +no_defs",
+            Crs::Hk1980Grid => {
+                "+proj=tmerc +lat_0=22.3121333333333 +lon_0=114.178555555556 +k=1 \
+                 +x_0=836694.05 +y_0=819069.8 +ellps=intl \
+                 +towgs84=-162.619,-276.959,-161.764,0.067753,-2.243648,-1.158828,-1.094246 \
+                 +units=m +no_defs"
+            }
+            Crs::WebMercator => {
+                "+proj=merc +a=6378137 +b=6378137 +lat_ts=0 +lon_0=0 +x_0=0 +y_0=0 +k=1 \
+                 +units=m +no_defs"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Crs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EPSG:{}", self.epsg())
+    }
+}
+
+impl FromStr for Crs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim();
+        let code = code
+            .strip_prefix("EPSG:")
+            .or_else(|| code.strip_prefix("epsg:"))
+            .unwrap_or(code);
+        code.parse()
+            .ok()
+            .and_then(Crs::from_epsg)
+            .ok_or_else(|| format!("unsupported CRS '{}'", s))
+    }
+}
+
+/// Converts coordinates between two [`Crs`]s.
+pub struct Transformer {
+    from: Crs,
+    to: Crs,
+    src: Proj,
+    dst: Proj,
+}
+
+impl Transformer {
+    pub fn new(from: Crs, to: Crs) -> PolarsResult<Self> {
+        let proj = |crs: Crs| {
+            Proj::from_proj_string(crs.proj_string())
+                .map_err(|e| polars_err!(ComputeError: "invalid projection for {}: {}", crs, e))
+        };
+        Ok(Transformer {
+            from,
+            to,
+            src: proj(from)?,
+            dst: proj(to)?,
+        })
+    }
+
+    /// Transform one coordinate, `None` if it falls outside the projection.
+    pub fn coord(&self, c: Coord) -> Option<Coord> {
+        if self.from == self.to {
+            return Some(c);
+        }
+        // proj4rs works in radians for geographic systems
+        let mut pt = if self.from.is_geographic() {
+            (c.x.to_radians(), c.y.to_radians(), 0.0)
+        } else {
+            (c.x, c.y, 0.0)
+        };
+        proj4rs::transform::transform(&self.src, &self.dst, &mut pt).ok()?;
+        let (x, y) = if self.to.is_geographic() {
+            (pt.0.to_degrees(), pt.1.to_degrees())
+        } else {
+            (pt.0, pt.1)
+        };
+        (x.is_finite() && y.is_finite()).then_some(Coord { x, y })
+    }
+
+    /// Transform every coordinate of a geometry, `None` if any of them fails.
+    pub fn geometry(&self, g: &Geometry) -> Option<Geometry> {
+        g.try_map_coords(|c| self.coord(c).ok_or(())).ok()
+    }
+}
+
+/// The struct field that tags a `geometry` or `{x, y}` point column with its CRS,
+/// e.g. `"EPSG:2326"`, once it is reprojected out of EPSG:4326. Untagged columns
+/// are longitude/latitude, as GeoJSON is.
+///
+/// The tag travels with the data, so it survives [`crate::dataset::Dataset::lazy`]
+/// and lets expressions that work in degrees refuse projected coordinates.
+pub const CRS_FIELD: &str = "crs";
+
+/// The CRS a `geometry` or point column is tagged with; EPSG:4326 when untagged.
+pub fn column_crs(column: &Column) -> PolarsResult<Crs> {
+    let fields = column.struct_()?.fields_as_series();
+    let Some(tag) = fields.iter().find(|f| f.name().as_str() == CRS_FIELD) else {
+        return Ok(Crs::Wgs84);
+    };
+    let tag = tag.str()?.into_iter().flatten().next();
+    match tag {
+        Some(tag) => tag
+            .parse()
+            .map_err(|e: String| polars_err!(ComputeError: "{}", e)),
+        None => Ok(Crs::Wgs84),
+    }
+}
+
+/// Tag a `geometry` or point column with `crs`, or drop its tag for EPSG:4326.
+pub fn with_crs(column: &Column, crs: Crs) -> PolarsResult<Column> {
+    let s = column.as_materialized_series().rechunk();
+    let ca = s.struct_()?;
+    let mut fields: Vec<Series> = ca
+        .fields_as_series()
+        .into_iter()
+        .filter(|f| f.name().as_str() != CRS_FIELD)
+        .collect();
+    if !crs.is_geographic() {
+        let tag = StringChunked::full(CRS_FIELD.into(), &crs.to_string(), ca.len());
+        fields.push(tag.into_series());
+    }
+    Ok(
+        StructChunked::from_series(column.name().clone(), ca.len(), fields.iter())?
+            .with_outer_validity(ca.rechunk_validity())
+            .into_column(),
+    )
+}
+
+/// [`with_crs`] as an expression, e.g. for a projected file read without the tag.
+pub fn tag_crs(column: Expr, crs: Crs) -> Expr {
+    column.map(
+        move |s| with_crs(&s, crs).map(Some),
+        GetOutput::map_dtype(move |dtype| Ok(tagged_dtype(dtype, !crs.is_geographic()))),
+    )
+}
+
+/// Give `output` the CRS tag of `input`, for operations that don't change the CRS.
+pub(crate) fn keep_crs(input: &Column, output: Column) -> PolarsResult<Column> {
+    match column_crs(input)? {
+        Crs::Wgs84 => Ok(output),
+        crs => with_crs(&output, crs),
+    }
+}
+
+/// `dtype` with the [`CRS_FIELD`] tag added, or removed.
+fn tagged_dtype(dtype: &DataType, tagged: bool) -> DataType {
+    let DataType::Struct(fields) = dtype else {
+        return dtype.clone();
+    };
+    let mut fields: Vec<Field> = fields
+        .iter()
+        .filter(|f| f.name().as_str() != CRS_FIELD)
+        .cloned()
+        .collect();
+    if tagged {
+        fields.push(Field::new(CRS_FIELD.into(), DataType::String));
+    }
+    DataType::Struct(fields)
+}
+
+/// The output type of an operation that gives `output` and keeps the input's CRS tag.
+pub(crate) fn keeps_crs(output: DataType) -> GetOutput {
+    GetOutput::map_dtype(move |input| {
+        let tagged = matches!(
+            input,
+            DataType::Struct(fields) if fields.iter().any(|f| f.name().as_str() == CRS_FIELD)
+        );
+        Ok(tagged_dtype(&output, tagged))
+    })
+}
+
+/// Pass a `geometry` or point column through unchanged, failing if it is tagged
+/// with a projected CRS. `what` names the operation that needs degrees.
+pub(crate) fn require_geographic(column: Expr, what: &'static str) -> Expr {
+    column.map(
+        move |s| {
+            let crs = column_crs(&s)?;
+            polars_ensure!(
+                crs.is_geographic(),
+                ComputeError: "{} needs EPSG:4326 longitude/latitude, but the column is in {}; \
+                reproject it to Crs::Wgs84 first",
+                what, crs
+            );
+            Ok(Some(s))
+        },
+        GetOutput::same_type(),
+    )
+}
+
+/// Fail unless `column` is in `from`. Untagged columns are EPSG:4326, so
+/// projected data read without its tag needs [`tag_crs`] first.
+fn ensure_in(column: &Column, from: Crs) -> PolarsResult<()> {
+    let crs = column_crs(column)?;
+    polars_ensure!(
+        crs == from,
+        ComputeError: "column is in {}, not {}{}", crs, from,
+        if crs.is_geographic() { "; tag projected data with tag_crs first" } else { "" }
+    );
+    Ok(())
+}
+
+/// Reproject a GeoJSON `geometry` struct column from one CRS to another.
+///
+/// The result is tagged with `to` (see [`CRS_FIELD`]) unless it is EPSG:4326.
+pub fn to_crs(geometry: Expr, from: Crs, to: Crs) -> Expr {
+    geometry.map(
+        move |s| {
+            ensure_in(&s, from)?;
+            let transformer = Transformer::new(from, to)?;
+            let decoded = decode_geometries(&s)?;
+            let out: Vec<_> = decoded
+                .geometries
+                .iter()
+                .map(|g| g.as_ref().and_then(|g| transformer.geometry(g)))
+                .collect();
+            with_crs(&encode_geometries(s.name().clone(), &out)?, to).map(Some)
+        },
+        GetOutput::map_dtype(move |dtype| Ok(tagged_dtype(dtype, !to.is_geographic()))),
+    )
+}
+
+/// Reproject an `{x, y}` point column from one CRS to another, tagged like [`to_crs`].
+pub fn point_to_crs(point: Expr, from: Crs, to: Crs) -> Expr {
+    point.map(
+        move |s| {
+            ensure_in(&s, from)?;
+            let transformer = Transformer::new(from, to)?;
+            let ca = s.struct_()?;
+            let x = ca.field_by_name("x")?.cast(&DataType::Float64)?;
+            let y = ca.field_by_name("y")?.cast(&DataType::Float64)?;
+            let points: Vec<_> = x
+                .f64()?
+                .into_iter()
+                .zip(y.f64()?)
+                .map(|(x, y)| match (x, y) {
+                    (Some(x), Some(y)) => transformer.coord(Coord { x, y }),
+                    _ => None,
+                })
+                .collect();
+            with_crs(&points_column(s.name().clone(), &points)?, to).map(Some)
+        },
+        GetOutput::from_type(tagged_dtype(&point_dtype(), !to.is_geographic())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::centroid::centroid;
+    use crate::dataset::Dataset;
+    use crate::distance::{distance_to, DistanceMethod};
+    use crate::shape::area;
+    use crate::test_util::{list, polygons, square};
+
+    fn transform(from: Crs, to: Crs, x: f64, y: f64) -> Coord {
+        Transformer::new(from, to)
+            .unwrap()
+            .coord(Coord { x, y })
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_epsg_codes() {
+        assert_eq!("EPSG:2326".parse::<Crs>(), Ok(Crs::Hk1980Grid));
+        assert_eq!(" epsg:3857".parse::<Crs>(), Ok(Crs::WebMercator));
+        assert_eq!("4326".parse::<Crs>(), Ok(Crs::Wgs84));
+        assert!("EPSG:27700".parse::<Crs>().is_err());
+        assert_eq!(Crs::Hk1980Grid.to_string(), "EPSG:2326");
+    }
+
+    #[test]
+    fn hk1980_grid_false_origin() {
+        // the grid origin, 22°18'43.68"N 114°10'42.80"E on HK1980, lies about
+        // 5.5" south and 8.8" east of those coordinates on WGS84
+        let c = transform(Crs::Hk1980Grid, Crs::Wgs84, 836694.05, 819069.80);
+        let lat = 22.0 + 18.0 / 60.0 + (43.68 - 5.5) / 3600.0;
+        let lon = 114.0 + 10.0 / 60.0 + (42.80 + 8.8) / 3600.0;
+        assert!((c.y - lat).abs() < 0.3 / 3600.0, "{:?}", c);
+        assert!((c.x - lon).abs() < 0.3 / 3600.0, "{:?}", c);
+
+        let back = transform(Crs::Wgs84, Crs::Hk1980Grid, c.x, c.y);
+        assert!((back.x - 836694.05).abs() < 1e-3 && (back.y - 819069.80).abs() < 1e-3);
+    }
+
+    #[test]
+    fn web_mercator_is_spherical() {
+        let c = transform(Crs::Wgs84, Crs::WebMercator, 114.17, 0.0);
+        assert!((c.x - 6_378_137.0 * 114.17f64.to_radians()).abs() < 1e-6);
+        assert!(c.y.abs() < 1e-6);
+    }
+
+    fn footprints() -> Dataset {
+        let geometry = polygons(vec![Some(list(vec![square(114.17, 22.3, 0.001)])), None]);
+        Dataset::new(DataFrame::new(vec![geometry]).unwrap().lazy(), Crs::Wgs84)
+    }
+
+    #[test]
+    fn reprojected_columns_carry_their_crs() {
+        let projected = footprints().to_crs(Crs::Hk1980Grid);
+        let df = projected.lazy().collect().unwrap();
+        let geometry = df.column("geometry").unwrap();
+        assert_eq!(column_crs(geometry).unwrap(), Crs::Hk1980Grid);
+        let decoded = decode_geometries(geometry).unwrap();
+        assert!(decoded.geometries[0].is_some() && decoded.geometries[1].is_none());
+
+        let df = projected
+            .lazy()
+            .select([centroid(col("geometry"))])
+            .collect()
+            .unwrap();
+        assert_eq!(
+            column_crs(df.column("geometry").unwrap()).unwrap(),
+            Crs::Hk1980Grid
+        );
+
+        let back = projected.to_crs(Crs::Wgs84).lazy().collect().unwrap();
+        assert_eq!(
+            column_crs(back.column("geometry").unwrap()).unwrap(),
+            Crs::Wgs84
+        );
+    }
+
+    #[test]
+    fn degree_based_expressions_refuse_projected_columns() {
+        let projected = footprints().to_crs(Crs::Hk1980Grid).lazy();
+        let err = projected
+            .clone()
+            .select([area(col("geometry"))])
+            .collect()
+            .unwrap_err();
+        assert!(err.to_string().contains("EPSG:2326"), "{}", err);
+        let distance = distance_to(
+            centroid(col("geometry")),
+            114.17,
+            22.3,
+            DistanceMethod::Haversine,
+        );
+        assert!(projected.select([distance]).collect().is_err());
+
+        let areas = footprints()
+            .to_crs(Crs::Hk1980Grid)
+            .to_crs(Crs::Wgs84)
+            .lazy()
+            .select([area(col("geometry"))])
+            .collect()
+            .unwrap();
+        let a = areas.column("geometry").unwrap().f64().unwrap().get(0);
+        assert!(a.is_some_and(|a| (a - 11_460.0).abs() < 100.0), "{:?}", a);
+    }
+
+    #[test]
+    fn reprojecting_from_the_wrong_crs_fails() {
+        let projected = footprints().to_crs(Crs::Hk1980Grid).lazy();
+        let wrong = to_crs(col("geometry"), Crs::WebMercator, Crs::Wgs84);
+        assert!(projected.select([wrong]).collect().is_err());
+
+        // untagged coordinates are longitude/latitude, whatever `from` claims
+        let plain = footprints().lazy();
+        let err = plain
+            .clone()
+            .select([to_crs(col("geometry"), Crs::Hk1980Grid, Crs::Wgs84)])
+            .collect()
+            .unwrap_err();
+        assert!(err.to_string().contains("tag_crs"), "{}", err);
+        let tagged = tag_crs(col("geometry"), Crs::Hk1980Grid);
+        let df = plain
+            .select([to_crs(tagged, Crs::Hk1980Grid, Crs::WebMercator)])
+            .collect()
+            .unwrap();
+        assert_eq!(
+            column_crs(df.column("geometry").unwrap()).unwrap(),
+            Crs::WebMercator
+        );
+    }
+
+    #[test]
+    fn point_to_crs_round_trips() {
+        let points =
+            points_column("p".into(), &[Some(Coord { x: 114.17, y: 22.3 }), None]).unwrap();
+        let df = DataFrame::new(vec![points])
+            .unwrap()
+            .lazy()
+            .select([
+                point_to_crs(col("p"), Crs::Wgs84, Crs::Hk1980Grid).alias("grid"),
+                point_to_crs(
+                    point_to_crs(col("p"), Crs::Wgs84, Crs::Hk1980Grid),
+                    Crs::Hk1980Grid,
+                    Crs::Wgs84,
+                )
+                .alias("back"),
+            ])
+            .collect()
+            .unwrap();
+        assert_eq!(
+            column_crs(df.column("grid").unwrap()).unwrap(),
+            Crs::Hk1980Grid
+        );
+        let back = crate::geometry::point_coords(df.column("back").unwrap()).unwrap();
+        let c = back[0].unwrap();
+        assert!((c.x - 114.17).abs() < 1e-7 && (c.y - 22.3).abs() < 1e-7);
+        assert_eq!(back[1], None);
+    }
+}
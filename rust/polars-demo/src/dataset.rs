@@ -0,0 +1,219 @@
+use crate::crs::{tag_crs, to_crs, Crs};
+use crate::geometry::map_geometry_bool;
+use crate::shape::bounding_box;
+use crate::{load_data, read_geojson};
+use geo::{BoundingRect, Coord, Intersects, MultiPolygon, Rect, Relate};
+use polars::prelude::*;
+use std::path::Path;
+
+/// Footprint bounding box columns written into the Parquet cache. Their per-row-group
+/// min/max statistics let bbox filters skip row groups without reading them.
+pub const BBOX_COLUMNS: [&str; 4] = ["bbox_min_x", "bbox_min_y", "bbox_max_x", "bbox_max_y"];
+
+/// Add the [`BBOX_COLUMNS`] of the `geometry` column.
+pub fn with_bbox_columns(lf: LazyFrame) -> LazyFrame {
+    let fields = ["min_x", "min_y", "max_x", "max_y"];
+    lf.with_column(bounding_box(col("geometry")).alias("bbox"))
+        .with_columns(
+            fields
+                .iter()
+                .zip(BBOX_COLUMNS)
+                .map(|(field, name)| col("bbox").struct_().field_by_name(field).alias(name))
+                .collect::<Vec<_>>(),
+        )
+        .drop(["bbox"])
+}
+
+/// A lazily loaded buildings frame together with the CRS its `geometry` column is in.
+///
+/// A projected `geometry` column also carries its CRS as a
+/// [`crate::crs::CRS_FIELD`] tag, so the frame from [`Dataset::lazy`] still
+/// knows it and degree-based expressions such as [`crate::shape::area`] fail
+/// on it instead of measuring metres as degrees.
+#[derive(Clone)]
+pub struct Dataset {
+    lf: LazyFrame,
+    crs: Crs,
+}
+
+impl Dataset {
+    /// A dataset whose `geometry` column is in `crs`, tagging it if `crs` is projected.
+    pub fn new(lf: LazyFrame, crs: Crs) -> Self {
+        if crs.is_geographic() {
+            return Dataset { lf, crs };
+        }
+        Dataset {
+            lf: lf.with_column(tag_crs(col("geometry"), crs)),
+            crs,
+        }
+    }
+
+    /// Load (downloading and converting if needed) and scan a Parquet cache.
+    /// The data is requested as EPSG:4326, so that is the starting CRS.
+    pub fn scan(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        load_data(path)?;
+        let lf = LazyFrame::scan_parquet(path, ScanArgsParquet::default())?;
+        Ok(Dataset::new(lf, Crs::Wgs84))
+    }
+
+    /// Read a GeoJSON layer, such as district boundaries. GeoJSON is always EPSG:4326.
+    pub fn read_geojson(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Dataset::new(read_geojson(path)?.lazy(), Crs::Wgs84))
+    }
+
+    pub fn crs(&self) -> Crs {
+        self.crs
+    }
+
+    pub fn lazy(&self) -> LazyFrame {
+        self.lf.clone()
+    }
+
+    pub fn into_lazy(self) -> LazyFrame {
+        self.lf
+    }
+
+    /// Reproject the `geometry` column into `crs`.
+    ///
+    /// Any stored [`BBOX_COLUMNS`] are dropped, as they describe the old CRS.
+    pub fn to_crs(self, crs: Crs) -> Self {
+        if crs == self.crs {
+            return self;
+        }
+        let lf = self
+            .lf
+            .drop_no_validate(BBOX_COLUMNS)
+            .with_column(to_crs(col("geometry"), self.crs, crs).alias("geometry"));
+        Dataset { lf, crs }
+    }
+
+    /// Keep buildings whose footprint intersects the box from `min` to `max`, in the
+    /// dataset CRS.
+    pub fn filter_bbox(self, min: Coord, max: Coord) -> PolarsResult<Self> {
+        let rect = Rect::new(min, max);
+        let (min, max) = (rect.min(), rect.max());
+        let overlaps = col("bbox_max_x")
+            .gt_eq(lit(min.x))
+            .and(col("bbox_min_x").lt_eq(lit(max.x)))
+            .and(col("bbox_max_y").gt_eq(lit(min.y)))
+            .and(col("bbox_min_y").lt_eq(lit(max.y)));
+        let exact = map_geometry_bool(col("geometry"), move |g| g.intersects(&rect));
+        self.filter_with_bbox(overlaps, exact)
+    }
+
+    /// Keep buildings whose footprint lies entirely within `polygon`, in the dataset CRS.
+    pub fn filter_within(self, polygon: impl Into<MultiPolygon>) -> PolarsResult<Self> {
+        let polygon: MultiPolygon = polygon.into();
+        let Some(rect) = polygon.bounding_rect() else {
+            return Ok(Dataset::new(self.lf.filter(lit(false)), self.crs));
+        };
+        let (min, max) = (rect.min(), rect.max());
+        let inside = col("bbox_min_x")
+            .gt_eq(lit(min.x))
+            .and(col("bbox_max_x").lt_eq(lit(max.x)))
+            .and(col("bbox_min_y").gt_eq(lit(min.y)))
+            .and(col("bbox_max_y").lt_eq(lit(max.y)));
+        let exact = map_geometry_bool(col("geometry"), move |g| polygon.relate(g).is_contains());
+        self.filter_with_bbox(inside, exact)
+    }
+
+    /// Filter on the stored bbox columns first, when present, so the Parquet scan can
+    /// skip row groups by their statistics, then run the exact geometry test on the rest.
+    fn filter_with_bbox(self, bbox: Expr, exact: Expr) -> PolarsResult<Self> {
+        let mut lf = self.lf;
+        let schema = lf.collect_schema()?;
+        if BBOX_COLUMNS.iter().all(|name| schema.contains(name)) {
+            lf = lf.filter(bbox);
+        }
+        // Materialized as a column so predicate pushdown can't merge it into the scan
+        // predicate, where it would stop the statistics from ruling out row groups.
+        let lf = lf
+            .with_column(exact.alias("_keep"))
+            .filter(col("_keep"))
+            .drop(["_keep"]);
+        Ok(Dataset::new(lf, self.crs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{list, polygons, square, temp_dir};
+    use crate::write_parquet;
+    use geo::{polygon, Polygon};
+
+    /// Unit squares with their lower-left corner at (0, 0), (2, 0), (4, 0), ...,
+    /// and a null footprint last.
+    fn squares(n: usize) -> DataFrame {
+        let mut rows: Vec<Option<Series>> = (0..n)
+            .map(|i| Some(list(vec![square(2.0 * i as f64, 0.0, 1.0)])))
+            .collect();
+        rows.push(None);
+        let ids = Column::new("OBJECTID".into(), (0..=n as i64).collect::<Vec<_>>());
+        DataFrame::new(vec![ids, polygons(rows)]).unwrap()
+    }
+
+    /// The same dataset in memory and as a Parquet cache with bbox columns.
+    fn both(n: usize, name: &str) -> [Dataset; 2] {
+        let path = temp_dir(name).join("cache.parquet");
+        let mut df = with_bbox_columns(squares(n).lazy()).collect().unwrap();
+        write_parquet(&mut df, &path).unwrap();
+        let cached = LazyFrame::scan_parquet(&path, ScanArgsParquet::default()).unwrap();
+        [
+            Dataset::new(squares(n).lazy(), Crs::Wgs84),
+            Dataset::new(cached, Crs::Wgs84),
+        ]
+    }
+
+    fn ids(dataset: Dataset) -> Vec<i64> {
+        let df = dataset.into_lazy().collect().unwrap();
+        df.column("OBJECTID")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn bbox_columns_follow_the_footprint() {
+        let df = with_bbox_columns(squares(2).lazy()).collect().unwrap();
+        let get = |name: &str| df.column(name).unwrap().f64().unwrap().to_vec();
+        assert_eq!(get("bbox_min_x"), [Some(0.0), Some(2.0), None]);
+        assert_eq!(get("bbox_max_y"), [Some(1.0), Some(1.0), None]);
+    }
+
+    #[test]
+    fn filter_bbox_keeps_intersecting_footprints() {
+        for dataset in both(5, "filter-bbox") {
+            let kept = dataset
+                .filter_bbox(Coord { x: 2.5, y: 0.5 }, Coord { x: 4.0, y: 3.0 })
+                .unwrap();
+            assert_eq!(ids(kept), [1, 2]);
+        }
+    }
+
+    #[test]
+    fn filter_within_keeps_footprints_entirely_inside() {
+        let area: Polygon = polygon![
+            (x: -0.5, y: -0.5),
+            (x: 3.5, y: -0.5),
+            (x: 3.5, y: 1.5),
+            (x: -0.5, y: 1.5),
+        ];
+        for dataset in both(5, "filter-within") {
+            assert_eq!(ids(dataset.filter_within(area.clone()).unwrap()), [0, 1]);
+        }
+        let empty = MultiPolygon::new(vec![]);
+        let [dataset, _] = both(5, "filter-within-empty");
+        assert!(ids(dataset.filter_within(empty).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn reprojecting_drops_the_stale_bbox_columns() {
+        let [_, cached] = both(1, "to-crs-bbox");
+        let mut lf = cached.to_crs(Crs::WebMercator).into_lazy();
+        let schema = lf.collect_schema().unwrap();
+        assert!(BBOX_COLUMNS.iter().all(|c| !schema.contains(c)));
+    }
+}
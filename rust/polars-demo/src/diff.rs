@@ -0,0 +1,299 @@
+use crate::crs::require_geographic;
+use crate::geometry::{decode_geometries, LocalPlane};
+use geo::HausdorffDistance;
+use polars::prelude::*;
+
+/// What [`diff_snapshots`] compares between two versions of the buildings data.
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// Attribute columns compared row by row; each gets `_old` and `_new` columns
+    /// in the diff.
+    pub columns: Vec<String>,
+    /// Footprints that moved at most this far (Hausdorff distance in metres)
+    /// count as unchanged.
+    pub geometry_tolerance_m: f64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            columns: vec![
+                "TOPHEIGHT".into(),
+                "GROSSFLOORAREA".into(),
+                "OFFICIALBUILDINGNAMEEN".into(),
+            ],
+            geometry_tolerance_m: 0.5,
+        }
+    }
+}
+
+/// Buildings added, removed or modified between two snapshots joined on `OBJECTID`.
+///
+/// One row per changed building, ordered by `change` then `OBJECTID`, with:
+/// `change` (`added`, `removed` or `modified`), `changed_columns` of modified rows
+/// (comma-separated, `geometry` when the footprint moved beyond the tolerance), the old
+/// and new value of each compared column, and `geometry_shift_m`.
+pub fn diff_snapshots(
+    old: LazyFrame,
+    new: LazyFrame,
+    options: &DiffOptions,
+) -> PolarsResult<DataFrame> {
+    let side = |lf: LazyFrame, suffix: &str| {
+        let mut exprs = vec![col("OBJECTID"), lit(true).alias(format!("in{}", suffix))];
+        exprs.extend(
+            options
+                .columns
+                .iter()
+                .chain(std::iter::once(&"geometry".to_string()))
+                .map(|c| col(c.as_str()).alias(format!("{}{}", c, suffix))),
+        );
+        lf.select(exprs)
+    };
+    let joined = side(old, "_old").join(
+        side(new, "_new"),
+        [col("OBJECTID")],
+        [col("OBJECTID")],
+        JoinArgs::new(JoinType::Full).with_coalesce(JoinCoalesce::CoalesceColumns),
+    );
+
+    let geometry_changed = missing(col("geometry_old"))
+        .neq(missing(col("geometry_new")))
+        .or(col("geometry_shift_m").gt(lit(options.geometry_tolerance_m)));
+    let changed = options
+        .columns
+        .iter()
+        .map(|c| {
+            when(col(format!("{}_old", c)).neq_missing(col(format!("{}_new", c))))
+                .then(lit(c.clone()))
+                .otherwise(lit(NULL))
+        })
+        .chain(std::iter::once(
+            when(geometry_changed)
+                .then(lit("geometry"))
+                .otherwise(lit(NULL)),
+        ))
+        .collect::<Vec<_>>();
+    let change = when(col("in_old").is_null())
+        .then(lit("added"))
+        .when(col("in_new").is_null())
+        .then(lit("removed"))
+        .when(col("changed_columns").neq(lit("")))
+        .then(lit("modified"))
+        .otherwise(lit(NULL))
+        .alias("change");
+
+    let mut columns = vec![col("OBJECTID"), col("change"), col("changed_columns")];
+    for c in &options.columns {
+        columns.push(col(format!("{}_old", c)));
+        columns.push(col(format!("{}_new", c)));
+    }
+    columns.push(col("geometry_shift_m"));
+
+    joined
+        .with_column(
+            geometry_shift(col("geometry_old"), col("geometry_new")).alias("geometry_shift_m"),
+        )
+        .with_column(concat_str(changed, ", ", true).alias("changed_columns"))
+        .with_column(change)
+        .filter(col("change").is_not_null())
+        .with_column(
+            when(col("change").eq(lit("modified")))
+                .then(col("changed_columns"))
+                .otherwise(lit(NULL).cast(DataType::String)),
+        )
+        .select(columns)
+        .sort(["change", "OBJECTID"], SortMultipleOptions::default())
+        .collect()
+}
+
+/// Whether a GeoJSON `geometry` is missing: a null row, or null fields as the
+/// encoders write for rows without a geometry.
+fn missing(geometry: Expr) -> Expr {
+    geometry.struct_().field_by_name("type").is_null()
+}
+
+/// Hausdorff distance in metres between the vertices of two GeoJSON `geometry`
+/// columns, row by row; null where either geometry is missing or malformed.
+pub fn geometry_shift(a: Expr, b: Expr) -> Expr {
+    let b = require_geographic(b, "geometry_shift");
+    require_geographic(a, "geometry_shift").map_many(
+        |s| {
+            let a = decode_geometries(&s[0])?.geometries;
+            let b = decode_geometries(&s[1])?.geometries;
+            let out: Float64Chunked = a
+                .iter()
+                .zip(&b)
+                .map(|(a, b)| {
+                    let (a, b) = (a.as_ref()?, b.as_ref()?);
+                    let plane = LocalPlane::around(a)?;
+                    Some(plane.project(a).hausdorff_distance(&plane.project(b)))
+                })
+                .collect();
+            Ok(Some(out.with_name(s[0].name().clone()).into_column()))
+        },
+        &[b],
+        GetOutput::from_type(DataType::Float64),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{buildings, list, polygons, square, ORIGIN};
+
+    /// The buildings a while later: the tower's footprint nudged ~10 cm, the
+    /// house 10 m shorter, the courtyard block moved ~10 m, a footprint drawn for
+    /// the bare row, the small block demolished and a copy of the tower built.
+    fn later() -> DataFrame {
+        let (x, y) = ORIGIN;
+        let geometry = polygons(vec![
+            Some(list(vec![square(x + 0.000001, y, 0.0005)])),
+            Some(list(vec![square(x + 0.01, y + 0.01, 0.0003)])),
+            Some(list(vec![
+                square(x + 0.1001, y + 0.05, 0.001),
+                square(x + 0.1003, y + 0.0502, 0.0002).reverse(),
+            ])),
+            Some(list(vec![square(x + 0.03, y, 0.0002)])),
+            Some(list(vec![square(x - 0.02, y - 0.01, 0.0004)])),
+        ]);
+        let mut df = buildings();
+        df.with_column(geometry).unwrap();
+        let mut df = df
+            .lazy()
+            .with_column(
+                when(col("OBJECTID").eq(lit(2)))
+                    .then(lit(20.0))
+                    .otherwise(col("TOPHEIGHT"))
+                    .alias("TOPHEIGHT"),
+            )
+            .filter(col("OBJECTID").neq(lit(5)))
+            .collect()
+            .unwrap();
+        let built = df
+            .head(Some(1))
+            .with_column(Column::new("OBJECTID".into(), &[6i64]))
+            .unwrap()
+            .clone();
+        df.vstack_mut(&built).unwrap();
+        df
+    }
+
+    fn strings(df: &DataFrame, name: &str) -> Vec<Option<String>> {
+        df.column(name)
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.map(String::from))
+            .collect()
+    }
+
+    fn ids(df: &DataFrame) -> Vec<Option<i64>> {
+        df.column("OBJECTID")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn added_removed_and_modified_buildings() {
+        let diff =
+            diff_snapshots(buildings().lazy(), later().lazy(), &DiffOptions::default()).unwrap();
+        assert_eq!(ids(&diff), [Some(6), Some(2), Some(3), Some(4), Some(5)]);
+        assert_eq!(
+            strings(&diff, "change"),
+            ["added", "modified", "modified", "modified", "removed"].map(|s| Some(s.into()))
+        );
+        assert_eq!(
+            strings(&diff, "changed_columns"),
+            [
+                None,
+                Some("TOPHEIGHT".into()),
+                Some("geometry".into()),
+                Some("geometry".into()),
+                None
+            ]
+        );
+        let heights = |name| -> Vec<Option<f64>> {
+            diff.column(name)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_iter()
+                .collect()
+        };
+        assert_eq!(heights("TOPHEIGHT_old")[..2], [None, Some(30.0)]);
+        assert_eq!(heights("TOPHEIGHT_new")[..2], [Some(120.0), Some(20.0)]);
+        assert_eq!(heights("TOPHEIGHT_new")[4], None);
+    }
+
+    #[test]
+    fn footprints_moved_within_the_tolerance_are_unchanged() {
+        let shifts: Vec<Option<f64>> = {
+            let diff = diff_snapshots(buildings().lazy(), later().lazy(), &DiffOptions::default())
+                .unwrap();
+            diff.column("geometry_shift_m")
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_iter()
+                .collect()
+        };
+        // the house didn't move, the courtyard block moved ~10 m, and the shift
+        // from or to a missing footprint is unknown
+        assert_eq!(shifts[1], Some(0.0));
+        assert!((shifts[2].unwrap() - 10.3).abs() < 0.5, "{:?}", shifts[2]);
+        assert_eq!(shifts[3], None);
+        assert_eq!(shifts[4], None);
+
+        let strict = DiffOptions {
+            geometry_tolerance_m: 0.01,
+            ..DiffOptions::default()
+        };
+        let diff = diff_snapshots(buildings().lazy(), later().lazy(), &strict).unwrap();
+        assert_eq!(
+            ids(&diff),
+            [Some(6), Some(1), Some(2), Some(3), Some(4), Some(5)]
+        );
+        assert_eq!(
+            strings(&diff, "changed_columns")[1],
+            Some("geometry".into())
+        );
+    }
+
+    #[test]
+    fn only_the_chosen_columns_are_compared() {
+        let options = DiffOptions {
+            columns: vec!["NUMABOVEGROUNDSTOREYS".into()],
+            geometry_tolerance_m: 1000.0,
+        };
+        let diff = diff_snapshots(buildings().lazy(), later().lazy(), &options).unwrap();
+        // the height change and the moves are ignored; the drawn footprint still counts
+        assert_eq!(ids(&diff), [Some(6), Some(4), Some(5)]);
+        let names: Vec<_> = diff.get_column_names().into_iter().cloned().collect();
+        assert_eq!(
+            names,
+            [
+                "OBJECTID",
+                "change",
+                "changed_columns",
+                "NUMABOVEGROUNDSTOREYS_old",
+                "NUMABOVEGROUNDSTOREYS_new",
+                "geometry_shift_m"
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_snapshots_have_no_changes() {
+        let diff = diff_snapshots(
+            buildings().lazy(),
+            buildings().lazy(),
+            &DiffOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(diff.height(), 0);
+    }
+}
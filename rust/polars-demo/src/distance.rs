@@ -0,0 +1,195 @@
+use crate::crs::require_geographic;
+use geo::{Coord, Distance, Geodesic, Point, VincentyDistance};
+use polars::prelude::*;
+
+/// Mean Earth radius in metres, as used by `geo`'s haversine.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// How the distance between two longitude/latitude points is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMethod {
+    /// Great-circle distance on a sphere, built from native polars arithmetic.
+    #[default]
+    Haversine,
+    /// Vincenty's iterative formula on the WGS84 ellipsoid; null if it fails to converge.
+    Vincenty,
+    /// Karney's geodesic on the WGS84 ellipsoid.
+    Geodesic,
+}
+
+/// Distance in metres from each `{x, y}` point (longitude, latitude) to a fixed point.
+/// Points reprojected with [`crate::crs::point_to_crs`] are an error.
+pub fn distance_to(point: Expr, lon: f64, lat: f64, method: DistanceMethod) -> Expr {
+    let point = require_geographic(point, "distance_to");
+    distance(
+        point.clone().struct_().field_by_name("x"),
+        point.struct_().field_by_name("y"),
+        lit(lon),
+        lit(lat),
+        method,
+    )
+}
+
+/// Row-wise distance in metres between two `{x, y}` point columns.
+pub fn pairwise_distance(a: Expr, b: Expr, method: DistanceMethod) -> Expr {
+    let a = require_geographic(a, "pairwise_distance");
+    let b = require_geographic(b, "pairwise_distance");
+    distance(
+        a.clone().struct_().field_by_name("x"),
+        a.struct_().field_by_name("y"),
+        b.clone().struct_().field_by_name("x"),
+        b.struct_().field_by_name("y"),
+        method,
+    )
+}
+
+/// Distance in metres between longitude/latitude coordinate expressions.
+pub fn distance(lon1: Expr, lat1: Expr, lon2: Expr, lat2: Expr, method: DistanceMethod) -> Expr {
+    match method {
+        DistanceMethod::Haversine => haversine(lon1, lat1, lon2, lat2),
+        DistanceMethod::Vincenty => {
+            ellipsoidal(lon1, lat1, lon2, lat2, |a, b| a.vincenty_distance(&b).ok())
+        }
+        DistanceMethod::Geodesic => ellipsoidal(lon1, lat1, lon2, lat2, |a, b| {
+            Some(Geodesic::distance(a, b))
+        }),
+    }
+}
+
+/// Haversine distance in metres between two longitude/latitude coordinates, the
+/// scalar counterpart of [`DistanceMethod::Haversine`].
+pub fn haversine_m(a: Coord, b: Coord) -> f64 {
+    let (lat1, lat2) = (a.y.to_radians(), b.y.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b.x - a.x).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+fn haversine(lon1: Expr, lat1: Expr, lon2: Expr, lat2: Expr) -> Expr {
+    let (lat1, lat2) = (lat1.radians(), lat2.radians());
+    let d_lat = lat2.clone() - lat1.clone();
+    let d_lon = lon2.radians() - lon1.radians();
+    let a =
+        (d_lat / lit(2.0)).sin().pow(2) + lat1.cos() * lat2.cos() * (d_lon / lit(2.0)).sin().pow(2);
+    lit(2.0 * EARTH_RADIUS_M) * a.sqrt().arcsin()
+}
+
+fn ellipsoidal<F>(lon1: Expr, lat1: Expr, lon2: Expr, lat2: Expr, f: F) -> Expr
+where
+    F: Fn(Point, Point) -> Option<f64> + Send + Sync + 'static,
+{
+    lon1.map_many(
+        move |s| {
+            let len = s.iter().map(|c| c.len()).max().unwrap_or(0);
+            let cols = s
+                .iter()
+                .map(|c| {
+                    let c = c.cast(&DataType::Float64)?;
+                    Ok(if c.len() == 1 {
+                        c.new_from_index(0, len)
+                    } else {
+                        c
+                    })
+                })
+                .collect::<PolarsResult<Vec<_>>>()?;
+            let [lon1, lat1, lon2, lat2] = [0, 1, 2, 3].map(|i| cols[i].f64().cloned());
+            let (lon1, lat1, lon2, lat2) = (lon1?, lat1?, lon2?, lat2?);
+            let out: Float64Chunked = (0..len)
+                .map(
+                    |i| match (lon1.get(i), lat1.get(i), lon2.get(i), lat2.get(i)) {
+                        (Some(x1), Some(y1), Some(x2), Some(y2)) => {
+                            f(Point::new(x1, y1), Point::new(x2, y2))
+                        }
+                        _ => None,
+                    },
+                )
+                .collect();
+            Ok(Some(out.with_name(s[0].name().clone()).into_column()))
+        },
+        &[lat1, lon2, lat2],
+        GetOutput::from_type(DataType::Float64),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::points_column;
+
+    /// One degree of latitude along the equator, then two rows with a null end.
+    fn frame() -> DataFrame {
+        let a = [
+            Some(Coord { x: 0.0, y: 0.0 }),
+            None,
+            Some(Coord { x: 0.0, y: 0.0 }),
+        ];
+        let b = [
+            Some(Coord { x: 0.0, y: 1.0 }),
+            Some(Coord { x: 0.0, y: 1.0 }),
+            None,
+        ];
+        DataFrame::new(vec![
+            points_column("a".into(), &a).unwrap(),
+            points_column("b".into(), &b).unwrap(),
+        ])
+        .unwrap()
+    }
+
+    fn pairwise(method: DistanceMethod) -> Vec<Option<f64>> {
+        let df = frame()
+            .lazy()
+            .select([pairwise_distance(col("a"), col("b"), method).alias("d")])
+            .collect()
+            .unwrap();
+        df.column("d").unwrap().f64().unwrap().to_vec()
+    }
+
+    #[test]
+    fn haversine_is_a_great_circle_arc() {
+        let d = pairwise(DistanceMethod::Haversine);
+        let expected = EARTH_RADIUS_M * 1f64.to_radians();
+        assert!((d[0].unwrap() - expected).abs() < 1e-6);
+        assert_eq!(&d[1..], [None, None]);
+    }
+
+    #[test]
+    fn ellipsoidal_methods_agree_on_a_meridian_degree() {
+        // a degree of latitude at the equator is 110574.4 m on WGS84
+        for method in [DistanceMethod::Vincenty, DistanceMethod::Geodesic] {
+            let d = pairwise(method);
+            assert!(
+                (d[0].unwrap() - 110_574.4).abs() < 0.5,
+                "{:?}: {:?}",
+                method,
+                d
+            );
+            assert_eq!(&d[1..], [None, None]);
+        }
+    }
+
+    #[test]
+    fn scalar_haversine_matches_the_expression() {
+        let d = haversine_m(Coord { x: 0.0, y: 0.0 }, Coord { x: 0.0, y: 1.0 });
+        assert!((d - pairwise(DistanceMethod::Haversine)[0].unwrap()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn distance_to_broadcasts_the_fixed_point() {
+        for method in [
+            DistanceMethod::Haversine,
+            DistanceMethod::Vincenty,
+            DistanceMethod::Geodesic,
+        ] {
+            let df = frame()
+                .lazy()
+                .select([distance_to(col("b"), 0.0, 1.0, method).alias("d")])
+                .collect()
+                .unwrap();
+            let d = df.column("d").unwrap().f64().unwrap().to_vec();
+            assert_eq!(d.len(), 3);
+            assert!(d[0].unwrap().abs() < 1e-6 && d[1].unwrap().abs() < 1e-6);
+            assert_eq!(d[2], None);
+        }
+    }
+}
@@ -0,0 +1,760 @@
+use geo::{
+    Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint, MultiPolygon,
+    Point, Polygon,
+};
+use polars::chunked_array::builder::get_list_builder;
+use polars::prelude::*;
+use std::fmt;
+
+/// Why a row of the `geometry` column could not be turned into a `geo::Geometry`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    MissingType,
+    UnsupportedType(String),
+    MissingCoordinates,
+    InvalidPosition,
+    InvalidNesting(&'static str),
+    InvalidMember(Box<DecodeError>),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::MissingType => write!(f, "geometry has no type"),
+            DecodeError::UnsupportedType(t) => write!(f, "unsupported geometry type '{}'", t),
+            DecodeError::MissingCoordinates => write!(f, "geometry has no coordinates"),
+            DecodeError::InvalidPosition => {
+                write!(f, "position is not a pair of numeric coordinates")
+            }
+            DecodeError::InvalidNesting(t) => {
+                write!(f, "coordinates are not nested as expected for a {}", t)
+            }
+            DecodeError::InvalidMember(e) => write!(f, "invalid collection member: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The decoded rows of a `geometry` column, with the rows that failed to decode.
+///
+/// `geometries` has one entry per input row. Null geometries and rows listed in
+/// `errors` are `None`.
+#[derive(Debug, Default)]
+pub struct DecodedGeometries {
+    pub geometries: Vec<Option<Geometry>>,
+    pub errors: Vec<(usize, DecodeError)>,
+}
+
+impl DecodedGeometries {
+    /// The decode failures as a `row`/`reason` frame.
+    pub fn errors_frame(&self) -> PolarsResult<DataFrame> {
+        let rows: Vec<IdxSize> = self.errors.iter().map(|(i, _)| *i as IdxSize).collect();
+        let reasons: Vec<String> = self.errors.iter().map(|(_, e)| e.to_string()).collect();
+        DataFrame::new(vec![
+            Column::new("row".into(), rows),
+            Column::new("reason".into(), reasons),
+        ])
+    }
+}
+
+/// Decode a GeoJSON `geometry` struct column (`type` plus `coordinates`, or
+/// `geometries` for collections) into `geo` geometries.
+pub fn decode_geometries(geometry: &Column) -> PolarsResult<DecodedGeometries> {
+    let ca = geometry.struct_()?;
+    let fields = ca.fields_as_series();
+    let field = |name: &str| fields.iter().find(|s| s.name().as_str() == name);
+
+    let types = field("type")
+        .map(|s| s.str().cloned())
+        .transpose()?
+        .unwrap_or_else(|| StringChunked::full_null("type".into(), ca.len()));
+    let coordinates = field("coordinates")
+        .map(|s| s.list().cloned())
+        .transpose()?;
+    let members = field("geometries").map(|s| s.list().cloned()).transpose()?;
+
+    let mut out = DecodedGeometries {
+        geometries: Vec::with_capacity(ca.len()),
+        errors: Vec::new(),
+    };
+    for idx in 0..ca.len() {
+        let coords = coordinates.as_ref().and_then(|c| c.get_as_series(idx));
+        let geoms = members.as_ref().and_then(|g| g.get_as_series(idx));
+        match decode_row(types.get(idx), coords, geoms) {
+            Ok(geometry) => out.geometries.push(geometry),
+            Err(e) => {
+                out.geometries.push(None);
+                out.errors.push((idx, e));
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn decode_row(
+    kind: Option<&str>,
+    coords: Option<Series>,
+    members: Option<Series>,
+) -> Result<Option<Geometry>, DecodeError> {
+    let kind = match kind {
+        Some(kind) => kind,
+        None if coords.is_none() && members.is_none() => return Ok(None),
+        None => return Err(DecodeError::MissingType),
+    };
+    if kind == "GeometryCollection" {
+        let members = members.ok_or(DecodeError::MissingCoordinates)?;
+        let decoded = decode_geometries(&members.into_column())
+            .map_err(|_| DecodeError::InvalidNesting("GeometryCollection"))?;
+        if let Some((_, e)) = decoded.errors.into_iter().next() {
+            return Err(DecodeError::InvalidMember(Box::new(e)));
+        }
+        let geometries = decoded.geometries.into_iter().flatten().collect();
+        return Ok(Some(Geometry::GeometryCollection(GeometryCollection(
+            geometries,
+        ))));
+    }
+
+    let coords = coords.ok_or(DecodeError::MissingCoordinates)?;
+    let geometry = match kind {
+        "Point" => Geometry::Point(Point(position(&coords)?)),
+        "MultiPoint" => Geometry::MultiPoint(MultiPoint(
+            nested(&coords, "MultiPoint", position)?
+                .into_iter()
+                .map(Point)
+                .collect(),
+        )),
+        "LineString" => Geometry::LineString(line_string(&coords)?),
+        "MultiLineString" => Geometry::MultiLineString(MultiLineString(nested(
+            &coords,
+            "MultiLineString",
+            line_string,
+        )?)),
+        "Polygon" => Geometry::Polygon(polygon(&coords)?),
+        "MultiPolygon" => {
+            Geometry::MultiPolygon(MultiPolygon(nested(&coords, "MultiPolygon", polygon)?))
+        }
+        other => return Err(DecodeError::UnsupportedType(other.to_string())),
+    };
+    Ok(Some(geometry))
+}
+
+fn nested<T>(
+    s: &Series,
+    kind: &'static str,
+    item: fn(&Series) -> Result<T, DecodeError>,
+) -> Result<Vec<T>, DecodeError> {
+    let ca = s.list().map_err(|_| DecodeError::InvalidNesting(kind))?;
+    ca.into_iter()
+        .map(|opt| {
+            opt.ok_or(DecodeError::InvalidNesting(kind))
+                .and_then(|s| item(&s))
+        })
+        .collect()
+}
+
+fn position(s: &Series) -> Result<Coord, DecodeError> {
+    // polars-json reads a column mixing nesting depths (e.g. Polygon and
+    // MultiPolygon rows) at the deepest one, wrapping the numbers of shallower
+    // rows in one-element lists
+    let mut s = s.clone();
+    while matches!(s.dtype(), DataType::List(_)) {
+        let ca = s.list().map_err(|_| DecodeError::InvalidPosition)?;
+        if ca.into_iter().any(|v| v.is_none_or(|v| v.len() != 1)) {
+            return Err(DecodeError::InvalidNesting("Point"));
+        }
+        s = s.explode().map_err(|_| DecodeError::InvalidPosition)?;
+    }
+    if s.dtype().is_nested() {
+        return Err(DecodeError::InvalidNesting("Point"));
+    }
+    let s = s
+        .cast(&DataType::Float64)
+        .map_err(|_| DecodeError::InvalidPosition)?;
+    let ca = s.f64().map_err(|_| DecodeError::InvalidPosition)?;
+    if ca.len() < 2 {
+        return Err(DecodeError::InvalidPosition);
+    }
+    match (ca.get(0), ca.get(1)) {
+        (Some(x), Some(y)) => Ok(Coord { x, y }),
+        _ => Err(DecodeError::InvalidPosition),
+    }
+}
+
+fn line_string(s: &Series) -> Result<LineString, DecodeError> {
+    nested(s, "LineString", position).map(LineString::new)
+}
+
+fn polygon(s: &Series) -> Result<Polygon, DecodeError> {
+    let mut rings = nested(s, "Polygon", line_string)?.into_iter();
+    let exterior = rings.next().unwrap_or_else(|| LineString::new(vec![]));
+    Ok(Polygon::new(exterior, rings.collect()))
+}
+
+/// The rings of each Polygon or MultiPolygon row exactly as stored, one list
+/// per polygon, without the ring closing that [`decode_geometries`] applies.
+/// Other geometry types and null rows have no rings.
+pub(crate) fn raw_polygon_rings(
+    geometry: &Column,
+) -> PolarsResult<Vec<Result<Vec<Vec<LineString>>, DecodeError>>> {
+    let ca = geometry.struct_()?;
+    let fields = ca.fields_as_series();
+    let field = |name: &str| fields.iter().find(|s| s.name().as_str() == name);
+    let types = field("type").map(|s| s.str().cloned()).transpose()?;
+    let coordinates = field("coordinates")
+        .map(|s| s.list().cloned())
+        .transpose()?;
+    let (Some(types), Some(coordinates)) = (types, coordinates) else {
+        return Ok((0..ca.len()).map(|_| Ok(Vec::new())).collect());
+    };
+    let rings = |s: &Series| nested(s, "Polygon", line_string);
+    Ok((0..ca.len())
+        .map(
+            |idx| match (types.get(idx), coordinates.get_as_series(idx)) {
+                (Some("Polygon"), Some(coords)) => rings(&coords).map(|r| vec![r]),
+                (Some("MultiPolygon"), Some(coords)) => nested(&coords, "MultiPolygon", |s| {
+                    nested(s, "Polygon", line_string)
+                }),
+                _ => Ok(Vec::new()),
+            },
+        )
+        .collect())
+}
+
+/// The `{x, y}` struct type used for point-valued expressions such as centroids.
+pub fn point_dtype() -> DataType {
+    DataType::Struct(vec![
+        Field::new("x".into(), DataType::Float64),
+        Field::new("y".into(), DataType::Float64),
+    ])
+}
+
+/// Build an `{x, y}` struct column; non-finite coordinates become null.
+pub fn points_column(name: PlSmallStr, points: &[Option<Coord>]) -> PolarsResult<Column> {
+    let finite = |c: &&Coord| c.x.is_finite() && c.y.is_finite();
+    let x: Float64Chunked = points
+        .iter()
+        .map(|p| p.as_ref().filter(finite).map(|c| c.x))
+        .collect();
+    let y: Float64Chunked = points
+        .iter()
+        .map(|p| p.as_ref().filter(finite).map(|c| c.y))
+        .collect();
+    let fields = [
+        x.with_name("x".into()).into_series(),
+        y.with_name("y".into()).into_series(),
+    ];
+    Ok(StructChunked::from_series(name, points.len(), fields.iter())?.into_column())
+}
+
+/// Read an `{x, y}` struct column back into coordinates.
+pub(crate) fn point_coords(column: &Column) -> PolarsResult<Vec<Option<Coord>>> {
+    let ca = column.struct_()?;
+    let x = ca.field_by_name("x")?.cast(&DataType::Float64)?;
+    let y = ca.field_by_name("y")?.cast(&DataType::Float64)?;
+    Ok(x.f64()?
+        .into_iter()
+        .zip(y.f64()?)
+        .map(|(x, y)| Some(Coord { x: x?, y: y? }))
+        .collect())
+}
+
+/// Apply `f` to every decoded geometry of a `geometry` column, giving a float column.
+pub(crate) fn map_geometry_f64<F>(geometry: Expr, f: F) -> Expr
+where
+    F: Fn(&Geometry) -> Option<f64> + Send + Sync + 'static,
+{
+    geometry.map(
+        move |s| {
+            let decoded = decode_geometries(&s)?;
+            let out: Float64Chunked = decoded
+                .geometries
+                .iter()
+                .map(|g| g.as_ref().and_then(&f).filter(|v| v.is_finite()))
+                .collect();
+            Ok(Some(out.with_name(s.name().clone()).into_column()))
+        },
+        GetOutput::from_type(DataType::Float64),
+    )
+}
+
+/// Apply a test to every decoded geometry of a `geometry` column, giving a boolean
+/// column that is false for null or malformed geometries.
+pub(crate) fn map_geometry_bool<F>(geometry: Expr, f: F) -> Expr
+where
+    F: Fn(&Geometry) -> bool + Send + Sync + 'static,
+{
+    geometry.map(
+        move |s| {
+            let decoded = decode_geometries(&s)?;
+            let out: BooleanChunked = decoded
+                .geometries
+                .iter()
+                .map(|g| g.as_ref().is_some_and(&f))
+                .collect();
+            Ok(Some(out.with_name(s.name().clone()).into_column()))
+        },
+        GetOutput::from_type(DataType::Boolean),
+    )
+}
+
+/// An equirectangular tangent plane in metres around a longitude/latitude origin.
+///
+/// Good enough for building-sized geometries, where a full projection would
+/// be overkill.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LocalPlane {
+    origin: Coord,
+    kx: f64,
+    ky: f64,
+}
+
+impl LocalPlane {
+    pub(crate) fn around(geometry: &Geometry) -> Option<Self> {
+        use geo::Centroid;
+        let origin = geometry.centroid()?.0;
+        let ky = crate::distance::EARTH_RADIUS_M.to_radians();
+        Some(LocalPlane {
+            origin,
+            kx: ky * origin.y.to_radians().cos(),
+            ky,
+        })
+    }
+
+    pub(crate) fn project(&self, geometry: &Geometry) -> Geometry {
+        use geo::MapCoords;
+        geometry.map_coords(|c| Coord {
+            x: (c.x - self.origin.x) * self.kx,
+            y: (c.y - self.origin.y) * self.ky,
+        })
+    }
+
+    pub(crate) fn unproject(&self, geometry: &Geometry) -> Geometry {
+        use geo::MapCoords;
+        geometry.map_coords(|c| Coord {
+            x: c.x / self.kx + self.origin.x,
+            y: c.y / self.ky + self.origin.y,
+        })
+    }
+}
+
+fn type_name(g: &Geometry) -> &'static str {
+    match g {
+        Geometry::Point(_) => "Point",
+        Geometry::MultiPoint(_) => "MultiPoint",
+        Geometry::LineString(_) | Geometry::Line(_) => "LineString",
+        Geometry::MultiLineString(_) => "MultiLineString",
+        Geometry::Polygon(_) | Geometry::Rect(_) | Geometry::Triangle(_) => "Polygon",
+        Geometry::MultiPolygon(_) => "MultiPolygon",
+        Geometry::GeometryCollection(_) => "GeometryCollection",
+    }
+}
+
+fn multi_type_name(kind: &str) -> &str {
+    match kind {
+        "Point" => "MultiPoint",
+        "LineString" => "MultiLineString",
+        "Polygon" => "MultiPolygon",
+        other => other,
+    }
+}
+
+/// The GeoJSON type a column of these geometries is encoded as: the shared type,
+/// or its multi variant when single and multi geometries are mixed.
+fn common_type(geometries: &[Option<Geometry>]) -> PolarsResult<&'static str> {
+    let mut kind: Option<&'static str> = None;
+    for g in geometries.iter().flatten() {
+        let next = type_name(g);
+        kind = match kind {
+            None => Some(next),
+            Some(k) if k == next => Some(k),
+            Some(k) if multi_type_name(k) == multi_type_name(next) => {
+                Some(if k.starts_with("Multi") { k } else { next })
+            }
+            Some(k) => {
+                polars_bail!(ComputeError: "cannot encode {} and {} geometries in one column", k, next)
+            }
+        };
+    }
+    match kind {
+        Some("GeometryCollection") => {
+            polars_bail!(ComputeError: "encoding GeometryCollection is not supported")
+        }
+        kind => Ok(kind.unwrap_or("Polygon")),
+    }
+}
+
+fn coordinates_dtype(kind: &str) -> DataType {
+    let depth = match kind {
+        "Point" => 0,
+        "MultiPoint" | "LineString" => 1,
+        "MultiLineString" | "Polygon" => 2,
+        _ => 3,
+    };
+    (0..depth).fold(DataType::Float64, |dt, _| DataType::List(Box::new(dt)))
+}
+
+fn positions_series(coords: &[Coord]) -> Series {
+    let mut builder = ListPrimitiveChunkedBuilder::<Float64Type>::new(
+        PlSmallStr::EMPTY,
+        coords.len(),
+        coords.len() * 2,
+        DataType::Float64,
+    );
+    for c in coords {
+        builder.append_slice(&[c.x, c.y]);
+    }
+    builder.finish().into_series()
+}
+
+fn nest_series(items: &[Series], inner: &DataType) -> PolarsResult<Series> {
+    let mut builder = get_list_builder(inner, items.len(), items.len(), PlSmallStr::EMPTY);
+    for s in items {
+        builder.append_series(s)?;
+    }
+    Ok(builder.finish().into_series())
+}
+
+fn polygon_series(p: &Polygon) -> PolarsResult<Series> {
+    let rings: Vec<Series> = std::iter::once(p.exterior())
+        .chain(p.interiors())
+        .map(|r| positions_series(&r.0))
+        .collect();
+    nest_series(&rings, &coordinates_dtype("LineString"))
+}
+
+fn coordinates_series(g: &Geometry, kind: &str) -> PolarsResult<Series> {
+    let lines = |ls: &[LineString]| -> PolarsResult<Series> {
+        let items: Vec<Series> = ls.iter().map(|l| positions_series(&l.0)).collect();
+        nest_series(&items, &coordinates_dtype("LineString"))
+    };
+    let polygons = |ps: &[Polygon]| -> PolarsResult<Series> {
+        let items = ps
+            .iter()
+            .map(polygon_series)
+            .collect::<PolarsResult<Vec<_>>>()?;
+        nest_series(&items, &coordinates_dtype("Polygon"))
+    };
+    Ok(match (g, kind) {
+        (Geometry::Point(p), "Point") => Series::new(PlSmallStr::EMPTY, &[p.x(), p.y()]),
+        (Geometry::Point(p), _) => positions_series(&[p.0]),
+        (Geometry::MultiPoint(mp), _) => {
+            positions_series(&mp.iter().map(|p| p.0).collect::<Vec<_>>())
+        }
+        (Geometry::Line(l), _) => positions_series(&[l.start, l.end]),
+        (Geometry::LineString(l), "LineString") => positions_series(&l.0),
+        (Geometry::LineString(l), _) => lines(std::slice::from_ref(l))?,
+        (Geometry::MultiLineString(ml), _) => lines(&ml.0)?,
+        (Geometry::Polygon(p), "Polygon") => polygon_series(p)?,
+        (Geometry::Polygon(p), _) => polygons(std::slice::from_ref(p))?,
+        (Geometry::Rect(r), _) => coordinates_series(&Geometry::Polygon(r.to_polygon()), kind)?,
+        (Geometry::Triangle(t), _) => coordinates_series(&Geometry::Polygon(t.to_polygon()), kind)?,
+        (Geometry::MultiPolygon(mp), _) => polygons(&mp.0)?,
+        (Geometry::GeometryCollection(_), _) => {
+            polars_bail!(ComputeError: "encoding GeometryCollection is not supported")
+        }
+    })
+}
+
+/// Encode geometries back into the GeoJSON `geometry` struct layout read by
+/// [`decode_geometries`].
+///
+/// A column holds a single GeoJSON type, so single geometries are promoted to
+/// their multi variant when both occur. Mixing families (e.g. points and
+/// polygons) or encoding a `GeometryCollection` is an error.
+pub fn encode_geometries(
+    name: PlSmallStr,
+    geometries: &[Option<Geometry>],
+) -> PolarsResult<Column> {
+    encode_as(name, geometries, common_type(geometries)?)
+}
+
+/// The struct type of a `geometry` column holding GeoJSON `kind` geometries.
+pub(crate) fn geometry_dtype(kind: &str) -> DataType {
+    DataType::Struct(vec![
+        Field::new("type".into(), DataType::String),
+        Field::new(
+            "coordinates".into(),
+            DataType::List(Box::new(coordinates_dtype(kind))),
+        ),
+    ])
+}
+
+/// The nesting of a `geometry` column's `coordinates`: 1 for points up to 4 for
+/// MultiPolygons, or `None` if it is not a geometry column.
+fn coordinates_depth(dtype: &DataType) -> Option<usize> {
+    let DataType::Struct(fields) = dtype else {
+        return None;
+    };
+    let mut dtype = fields
+        .iter()
+        .find(|f| f.name().as_str() == "coordinates")?
+        .dtype();
+    let mut depth = 0;
+    while let DataType::List(inner) = dtype {
+        depth += 1;
+        dtype = inner;
+    }
+    (1..=4).contains(&depth).then_some(depth)
+}
+
+fn kind_depth(kind: &str) -> usize {
+    match kind {
+        "Point" => 1,
+        "MultiPoint" | "LineString" => 2,
+        "MultiLineString" | "Polygon" => 3,
+        _ => 4,
+    }
+}
+
+/// The GeoJSON type [`encode_like`] writes geometries of a column of `dtype` as.
+fn kind_like(dtype: &DataType, geometries: &[Option<Geometry>]) -> PolarsResult<&'static str> {
+    let Some(depth) = coordinates_depth(dtype) else {
+        return common_type(geometries);
+    };
+    if geometries.iter().all(Option::is_none) {
+        return Ok(["Point", "LineString", "Polygon", "MultiPolygon"][depth - 1]);
+    }
+    let kind = common_type(geometries)?;
+    match [kind, multi_type_name(kind)]
+        .into_iter()
+        .find(|k| kind_depth(k) == depth)
+    {
+        Some(kind) => Ok(kind),
+        None => {
+            polars_bail!(ComputeError: "cannot encode {} geometries at the nesting of {}", kind, dtype)
+        }
+    }
+}
+
+/// The type of [`encode_like`]'s output for an input column of `dtype`, keeping
+/// its [`crate::crs::CRS_FIELD`] tag if it has one.
+pub(crate) fn encoded_like_dtype(dtype: &DataType) -> DataType {
+    let Some(depth) = coordinates_depth(dtype) else {
+        return dtype.clone();
+    };
+    let DataType::Struct(mut fields) =
+        geometry_dtype(["Point", "LineString", "Polygon", "MultiPolygon"][depth - 1])
+    else {
+        unreachable!()
+    };
+    if let DataType::Struct(input) = dtype {
+        fields.extend(
+            input
+                .iter()
+                .filter(|f| f.name().as_str() == crate::crs::CRS_FIELD)
+                .cloned(),
+        );
+    }
+    DataType::Struct(fields)
+}
+
+/// Encode geometries with the coordinate nesting of the column `like`, for
+/// expressions that keep each geometry's type, so their output type follows
+/// from their input's: see [`encoded_like_dtype`]. Floats replace other
+/// coordinate types.
+pub(crate) fn encode_like(like: &Column, geometries: &[Option<Geometry>]) -> PolarsResult<Column> {
+    encode_as(
+        like.name().clone(),
+        geometries,
+        kind_like(like.dtype(), geometries)?,
+    )
+}
+
+/// Encode geometries as GeoJSON `kind`, which must be their type or its multi variant.
+pub(crate) fn encode_as(
+    name: PlSmallStr,
+    geometries: &[Option<Geometry>],
+    kind: &str,
+) -> PolarsResult<Column> {
+    let inner = coordinates_dtype(kind);
+    let mut builder = get_list_builder(
+        &inner,
+        geometries.len(),
+        geometries.len(),
+        "coordinates".into(),
+    );
+    let mut types = Vec::with_capacity(geometries.len());
+    for g in geometries {
+        match g {
+            Some(g) => {
+                builder.append_series(&coordinates_series(g, kind)?)?;
+                types.push(Some(kind));
+            }
+            None => {
+                builder.append_null();
+                types.push(None);
+            }
+        }
+    }
+    let fields = [
+        Series::new("type".into(), types),
+        builder.finish().into_series(),
+    ];
+    Ok(StructChunked::from_series(name, geometries.len(), fields.iter())?.into_column())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{geometries, list, pos, ring, square};
+
+    fn decode_one(kind: &str, coordinates: Series) -> Result<Option<Geometry>, DecodeError> {
+        let column = geometries("geometry", vec![Some((kind, coordinates))]);
+        let mut decoded = decode_geometries(&column).unwrap();
+        match decoded.errors.pop() {
+            Some((_, e)) => Err(e),
+            None => Ok(decoded.geometries.pop().unwrap()),
+        }
+    }
+
+    /// A position as polars-json reads it in a column one level deeper.
+    fn wrap(x: f64, y: f64) -> Series {
+        list(vec![
+            Series::new(PlSmallStr::EMPTY, &[x]),
+            Series::new(PlSmallStr::EMPTY, &[y]),
+        ])
+    }
+
+    fn coords(g: &Geometry) -> Vec<(f64, f64)> {
+        use geo::CoordsIter;
+        g.coords_iter().map(|c| (c.x, c.y)).collect()
+    }
+
+    #[test]
+    fn decodes_point() {
+        let g = decode_one("Point", pos(1.0, 2.0)).unwrap().unwrap();
+        assert_eq!(g, Geometry::Point(Point::new(1.0, 2.0)));
+    }
+
+    #[test]
+    fn decodes_multi_point() {
+        let g = decode_one("MultiPoint", ring(&[(1.0, 2.0), (3.0, 4.0)]))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(g, Geometry::MultiPoint(ref mp) if mp.0.len() == 2));
+        assert_eq!(coords(&g), [(1.0, 2.0), (3.0, 4.0)]);
+    }
+
+    #[test]
+    fn decodes_line_string() {
+        let g = decode_one("LineString", ring(&[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)]))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(g, Geometry::LineString(ref l) if l.0.len() == 3));
+    }
+
+    #[test]
+    fn decodes_multi_line_string() {
+        let lines = list(vec![
+            ring(&[(0.0, 0.0), (1.0, 1.0)]),
+            ring(&[(2.0, 2.0), (3.0, 3.0), (4.0, 4.0)]),
+        ]);
+        let g = decode_one("MultiLineString", lines).unwrap().unwrap();
+        assert!(matches!(g, Geometry::MultiLineString(ref ml) if ml.0.len() == 2));
+        assert_eq!(coords(&g).len(), 5);
+    }
+
+    #[test]
+    fn decodes_polygon_with_hole() {
+        let rings = list(vec![square(0.0, 0.0, 4.0), square(1.0, 1.0, 1.0)]);
+        let Some(Geometry::Polygon(p)) = decode_one("Polygon", rings).unwrap() else {
+            panic!("expected a polygon");
+        };
+        assert_eq!(p.exterior().0.len(), 5);
+        assert_eq!(p.interiors().len(), 1);
+    }
+
+    #[test]
+    fn decodes_multi_polygon() {
+        let polygons = list(vec![
+            list(vec![square(0.0, 0.0, 1.0)]),
+            list(vec![square(5.0, 5.0, 1.0)]),
+        ]);
+        let g = decode_one("MultiPolygon", polygons).unwrap().unwrap();
+        assert!(matches!(g, Geometry::MultiPolygon(ref mp) if mp.0.len() == 2));
+    }
+
+    #[test]
+    fn decodes_geometry_collection() {
+        let members = geometries(
+            "",
+            vec![
+                Some(("Point", wrap(1.0, 2.0))),
+                Some(("MultiPoint", list(vec![pos(3.0, 4.0), pos(5.0, 6.0)]))),
+            ],
+        );
+        let members = members.as_materialized_series().clone();
+        let types = Series::new("type".into(), &["GeometryCollection"]);
+        let fields = [types, Series::new("geometries".into(), [members])];
+        let column = StructChunked::from_series("geometry".into(), 1, fields.iter())
+            .unwrap()
+            .into_column();
+        let decoded = decode_geometries(&column).unwrap();
+        assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+        let Some(Geometry::GeometryCollection(gc)) = &decoded.geometries[0] else {
+            panic!("expected a collection");
+        };
+        assert_eq!(gc.0.len(), 2);
+        assert_eq!(gc.0[0], Geometry::Point(Point::new(1.0, 2.0)));
+    }
+
+    #[test]
+    fn decodes_polygons_read_at_multi_polygon_depth() {
+        // a Polygon row as polars-json reads it next to MultiPolygon rows
+        let ring = list(vec![
+            wrap(0.0, 0.0),
+            wrap(1.0, 0.0),
+            wrap(1.0, 1.0),
+            wrap(0.0, 0.0),
+        ]);
+        let column = geometries(
+            "geometry",
+            vec![
+                Some(("Polygon", list(vec![ring]))),
+                Some((
+                    "MultiPolygon",
+                    list(vec![list(vec![square(5.0, 5.0, 1.0)])]),
+                )),
+                None,
+            ],
+        );
+        let decoded = decode_geometries(&column).unwrap();
+        assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+        let Some(Geometry::Polygon(p)) = &decoded.geometries[0] else {
+            panic!("expected a polygon");
+        };
+        assert_eq!(p.exterior().0[1], Coord { x: 1.0, y: 0.0 });
+        assert!(matches!(
+            decoded.geometries[1],
+            Some(Geometry::MultiPolygon(_))
+        ));
+        assert!(decoded.geometries[2].is_none());
+    }
+
+    #[test]
+    fn reports_rows_that_fail_to_decode() {
+        let column = geometries(
+            "geometry",
+            vec![
+                Some(("Point", list(vec![pos(1.0, 2.0)]))),
+                Some(("Blob", list(vec![pos(1.0, 2.0)]))),
+                Some((
+                    "MultiPoint",
+                    list(vec![Series::new(PlSmallStr::EMPTY, &[1.0])]),
+                )),
+            ],
+        );
+        let decoded = decode_geometries(&column).unwrap();
+        assert_eq!(
+            decoded.errors,
+            [
+                (0, DecodeError::InvalidNesting("Point")),
+                (1, DecodeError::UnsupportedType("Blob".into())),
+                (2, DecodeError::InvalidPosition),
+            ]
+        );
+        assert!(decoded.geometries.iter().all(Option::is_none));
+        assert_eq!(decoded.errors_frame().unwrap().height(), 3);
+    }
+}
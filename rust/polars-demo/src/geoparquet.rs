@@ -0,0 +1,510 @@
+use crate::crs::{column_crs, Crs};
+use crate::geometry::decode_geometries;
+use geo::{
+    BoundingRect, Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint,
+    MultiPolygon, Point, Polygon, Rect,
+};
+use polars::prelude::*;
+use polars_parquet::write::KeyValue;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Write `df` as GeoParquet 1.1: the GeoJSON `geometry` struct column becomes WKB
+/// and the file carries the `geo` metadata readers such as GDAL, DuckDB and
+/// GeoPandas look for. Untagged geometries are OGC:CRS84 longitude/latitude; a
+/// column reprojected with [`crate::crs`] has its CRS written as PROJJSON.
+pub fn write_geoparquet(df: &DataFrame, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let crs = column_crs(df.column("geometry")?)?;
+    let decoded = decode_geometries(df.column("geometry")?)?;
+    if let Some((row, e)) = decoded.errors.first() {
+        return Err(format!("row {} has an invalid geometry: {}", row, e).into());
+    }
+    let wkb: BinaryChunked = decoded
+        .geometries
+        .iter()
+        .map(|g| g.as_ref().map(to_wkb))
+        .collect();
+    let mut df = df.clone();
+    df.with_column(wkb.with_name("geometry".into()).into_column())?;
+
+    let mut types = BTreeSet::new();
+    let mut bbox: Option<Rect> = None;
+    for g in decoded.geometries.iter().flatten() {
+        types.insert(geometry_type(g));
+        if let Some(rect) = g.bounding_rect() {
+            bbox = Some(match bbox {
+                Some(b) => Rect::new(
+                    Coord {
+                        x: b.min().x.min(rect.min().x),
+                        y: b.min().y.min(rect.min().y),
+                    },
+                    Coord {
+                        x: b.max().x.max(rect.max().x),
+                        y: b.max().y.max(rect.max().y),
+                    },
+                ),
+                None => rect,
+            });
+        }
+    }
+    let mut column = serde_json::json!({
+        "encoding": "WKB",
+        "geometry_types": types,
+    });
+    if let Some(b) = bbox {
+        column["bbox"] = serde_json::json!([b.min().x, b.min().y, b.max().x, b.max().y]);
+    }
+    if let Some(projjson) = projjson(crs) {
+        column["crs"] = projjson;
+    }
+    let geo = serde_json::json!({
+        "version": "1.1.0",
+        "primary_column": "geometry",
+        "columns": { "geometry": column },
+    });
+
+    let file = std::fs::File::create(path)?;
+    let mut writer = ParquetWriter::new(file)
+        .with_row_group_size(Some(crate::ROW_GROUP_SIZE))
+        .batched(&df.schema())?;
+    writer.write_batch(&df)?;
+    // `finish` writes no key-value metadata, so end the file ourselves
+    writer
+        .get_writer()
+        .lock()
+        .map_err(|_| "the Parquet writer was poisoned by an earlier panic")?
+        .end(Some(vec![KeyValue {
+            key: "geo".into(),
+            value: Some(geo.to_string()),
+        }]))?;
+    Ok(())
+}
+
+/// The PROJJSON definition of `crs` for the `geo` metadata; none for
+/// longitude/latitude, which GeoParquet assumes when `crs` is omitted.
+fn projjson(crs: Crs) -> Option<serde_json::Value> {
+    use serde_json::{json, Value};
+    let id = |code: u32| json!({ "authority": "EPSG", "code": code });
+    let parameter = |name: &str, value: f64, unit: &str, code: u32| json!({ "name": name, "value": value, "unit": unit, "id": id(code) });
+    let axis = |name: &str, abbreviation: &str, direction: &str, unit: &str| json!({ "name": name, "abbreviation": abbreviation, "direction": direction, "unit": unit });
+    let base_crs = |name: &str, datum: &str, ellipsoid: Value, code: u32| {
+        json!({
+            "name": name,
+            "datum": { "type": "GeodeticReferenceFrame", "name": datum, "ellipsoid": ellipsoid },
+            "coordinate_system": {
+                "subtype": "ellipsoidal",
+                "axis": [
+                    axis("Geodetic latitude", "Lat", "north", "degree"),
+                    axis("Geodetic longitude", "Lon", "east", "degree"),
+                ],
+            },
+            "id": id(code),
+        })
+    };
+    let (name, base_crs, conversion, axes) = match crs {
+        Crs::Wgs84 => return None,
+        Crs::Hk1980Grid => (
+            "Hong Kong 1980 Grid System",
+            base_crs(
+                "Hong Kong 1980",
+                "Hong Kong 1980",
+                json!({ "name": "International 1924", "semi_major_axis": 6378388, "inverse_flattening": 297 }),
+                4611,
+            ),
+            json!({
+                "name": "Hong Kong 1980 Grid System",
+                "method": { "name": "Transverse Mercator", "id": id(9807) },
+                "parameters": [
+                    parameter("Latitude of natural origin", 22.3121333333333, "degree", 8801),
+                    parameter("Longitude of natural origin", 114.178555555556, "degree", 8802),
+                    parameter("Scale factor at natural origin", 1.0, "unity", 8805),
+                    parameter("False easting", 836694.05, "metre", 8806),
+                    parameter("False northing", 819069.8, "metre", 8807),
+                ],
+            }),
+            // EPSG lists northing first; WKB stays easting, northing regardless
+            vec![
+                axis("Northing", "N", "north", "metre"),
+                axis("Easting", "E", "east", "metre"),
+            ],
+        ),
+        Crs::WebMercator => (
+            "WGS 84 / Pseudo-Mercator",
+            base_crs(
+                "WGS 84",
+                "World Geodetic System 1984",
+                json!({ "name": "WGS 84", "semi_major_axis": 6378137, "inverse_flattening": 298.257223563 }),
+                4326,
+            ),
+            json!({
+                "name": "Popular Visualisation Pseudo-Mercator",
+                "method": { "name": "Popular Visualisation Pseudo Mercator", "id": id(1024) },
+                "parameters": [
+                    parameter("Latitude of natural origin", 0.0, "degree", 8801),
+                    parameter("Longitude of natural origin", 0.0, "degree", 8802),
+                    parameter("False easting", 0.0, "metre", 8806),
+                    parameter("False northing", 0.0, "metre", 8807),
+                ],
+            }),
+            vec![
+                axis("Easting", "X", "east", "metre"),
+                axis("Northing", "Y", "north", "metre"),
+            ],
+        ),
+    };
+    Some(json!({
+        "$schema": "https://proj.org/schemas/v0.7/projjson.schema.json",
+        "type": "ProjectedCRS",
+        "name": name,
+        "base_crs": base_crs,
+        "conversion": conversion,
+        "coordinate_system": { "subtype": "Cartesian", "axis": axes },
+        "id": id(crs.epsg()),
+    }))
+}
+
+fn geometry_type(g: &Geometry) -> &'static str {
+    match g {
+        Geometry::Point(_) => "Point",
+        Geometry::MultiPoint(_) => "MultiPoint",
+        Geometry::Line(_) | Geometry::LineString(_) => "LineString",
+        Geometry::MultiLineString(_) => "MultiLineString",
+        Geometry::Polygon(_) | Geometry::Rect(_) | Geometry::Triangle(_) => "Polygon",
+        Geometry::MultiPolygon(_) => "MultiPolygon",
+        Geometry::GeometryCollection(_) => "GeometryCollection",
+    }
+}
+
+/// Little-endian ISO WKB for a 2D geometry.
+pub fn to_wkb(g: &Geometry) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_wkb(g, &mut out);
+    out
+}
+
+fn write_wkb(g: &Geometry, out: &mut Vec<u8>) {
+    let header = |out: &mut Vec<u8>, code: u32| {
+        out.push(1);
+        out.extend_from_slice(&code.to_le_bytes());
+    };
+    let count = |out: &mut Vec<u8>, n: usize| out.extend_from_slice(&(n as u32).to_le_bytes());
+    let coord = |out: &mut Vec<u8>, c: Coord| {
+        out.extend_from_slice(&c.x.to_le_bytes());
+        out.extend_from_slice(&c.y.to_le_bytes());
+    };
+    let line = |out: &mut Vec<u8>, ls: &LineString| {
+        count(out, ls.0.len());
+        ls.0.iter().for_each(|c| coord(out, *c));
+    };
+    let polygon = |out: &mut Vec<u8>, p: &Polygon| {
+        count(out, 1 + p.interiors().len());
+        line(out, p.exterior());
+        p.interiors().iter().for_each(|r| line(out, r));
+    };
+    match g {
+        Geometry::Point(p) => {
+            header(out, 1);
+            coord(out, p.0);
+        }
+        Geometry::Line(l) => write_wkb(&Geometry::LineString(LineString::from(*l)), out),
+        Geometry::LineString(ls) => {
+            header(out, 2);
+            line(out, ls);
+        }
+        Geometry::Polygon(p) => {
+            header(out, 3);
+            polygon(out, p);
+        }
+        Geometry::Rect(r) => write_wkb(&Geometry::Polygon(r.to_polygon()), out),
+        Geometry::Triangle(t) => write_wkb(&Geometry::Polygon(t.to_polygon()), out),
+        Geometry::MultiPoint(mp) => {
+            header(out, 4);
+            count(out, mp.0.len());
+            mp.0.iter()
+                .for_each(|p| write_wkb(&Geometry::Point(*p), out));
+        }
+        Geometry::MultiLineString(mls) => {
+            header(out, 5);
+            count(out, mls.0.len());
+            for ls in &mls.0 {
+                header(out, 2);
+                line(out, ls);
+            }
+        }
+        Geometry::MultiPolygon(mp) => {
+            header(out, 6);
+            count(out, mp.0.len());
+            for p in &mp.0 {
+                header(out, 3);
+                polygon(out, p);
+            }
+        }
+        Geometry::GeometryCollection(gc) => {
+            header(out, 7);
+            count(out, gc.0.len());
+            gc.0.iter().for_each(|g| write_wkb(g, out));
+        }
+    }
+}
+
+/// Read 2D ISO WKB, in either byte order, as [`to_wkb`] writes it.
+pub fn from_wkb(bytes: &[u8]) -> PolarsResult<Geometry> {
+    let mut reader = WkbReader { bytes, pos: 0 };
+    let g = reader.geometry()?;
+    polars_ensure!(
+        reader.pos == bytes.len(),
+        ComputeError: "{} trailing bytes after WKB geometry", bytes.len() - reader.pos
+    );
+    Ok(g)
+}
+
+struct WkbReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl WkbReader<'_> {
+    fn take<const N: usize>(&mut self) -> PolarsResult<[u8; N]> {
+        let end = self.pos + N;
+        let Some(chunk) = self.bytes.get(self.pos..end) else {
+            polars_bail!(ComputeError: "WKB ends early at byte {}", self.bytes.len());
+        };
+        self.pos = end;
+        Ok(chunk.try_into().unwrap())
+    }
+
+    fn u32(&mut self, little: bool) -> PolarsResult<u32> {
+        let b = self.take::<4>()?;
+        Ok(if little {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn coord(&mut self, little: bool) -> PolarsResult<Coord> {
+        let mut f = || -> PolarsResult<f64> {
+            let b = self.take::<8>()?;
+            Ok(if little {
+                f64::from_le_bytes(b)
+            } else {
+                f64::from_be_bytes(b)
+            })
+        };
+        Ok(Coord { x: f()?, y: f()? })
+    }
+
+    fn line(&mut self, little: bool) -> PolarsResult<LineString> {
+        let n = self.u32(little)?;
+        (0..n).map(|_| self.coord(little)).collect()
+    }
+
+    fn polygon(&mut self, little: bool) -> PolarsResult<Polygon> {
+        let n = self.u32(little)?;
+        let mut rings = (0..n)
+            .map(|_| self.line(little))
+            .collect::<PolarsResult<Vec<_>>>()?;
+        polars_ensure!(!rings.is_empty(), ComputeError: "WKB polygon without rings");
+        let exterior = rings.remove(0);
+        Ok(Polygon::new(exterior, rings))
+    }
+
+    /// The members of a multi-geometry, each with its own header.
+    fn members<T>(
+        &mut self,
+        little: bool,
+        f: impl Fn(Geometry) -> Option<T>,
+        kind: &str,
+    ) -> PolarsResult<Vec<T>> {
+        let n = self.u32(little)?;
+        (0..n)
+            .map(|_| {
+                let g = self.geometry()?;
+                f(g).ok_or_else(
+                    || polars_err!(ComputeError: "WKB {} has a member of another type", kind),
+                )
+            })
+            .collect()
+    }
+
+    fn geometry(&mut self) -> PolarsResult<Geometry> {
+        let little = match self.take::<1>()?[0] {
+            0 => false,
+            1 => true,
+            b => polars_bail!(ComputeError: "invalid WKB byte order {}", b),
+        };
+        Ok(match self.u32(little)? {
+            1 => Geometry::Point(Point(self.coord(little)?)),
+            2 => Geometry::LineString(self.line(little)?),
+            3 => Geometry::Polygon(self.polygon(little)?),
+            4 => Geometry::MultiPoint(MultiPoint(self.members(
+                little,
+                |g| match g {
+                    Geometry::Point(p) => Some(p),
+                    _ => None,
+                },
+                "MultiPoint",
+            )?)),
+            5 => Geometry::MultiLineString(MultiLineString(self.members(
+                little,
+                |g| match g {
+                    Geometry::LineString(ls) => Some(ls),
+                    _ => None,
+                },
+                "MultiLineString",
+            )?)),
+            6 => Geometry::MultiPolygon(MultiPolygon(self.members(
+                little,
+                |g| match g {
+                    Geometry::Polygon(p) => Some(p),
+                    _ => None,
+                },
+                "MultiPolygon",
+            )?)),
+            7 => Geometry::GeometryCollection(GeometryCollection(self.members(
+                little,
+                Some,
+                "GeometryCollection",
+            )?)),
+            code => polars_bail!(ComputeError: "unsupported WKB geometry type {}", code),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crs::with_crs;
+    use crate::test_util::{list, polygons, square, temp_dir};
+    use geo::{line_string, point, polygon, Line, Triangle};
+
+    fn round_trip(g: Geometry) {
+        assert_eq!(from_wkb(&to_wkb(&g)).unwrap(), g);
+    }
+
+    fn lines() -> Vec<LineString> {
+        vec![
+            line_string![(x: 0.0, y: 0.0), (x: 1.0, y: 1.0)],
+            line_string![(x: 2.0, y: 2.0), (x: 3.0, y: 2.5), (x: 4.0, y: 2.0)],
+        ]
+    }
+
+    fn with_hole() -> Polygon {
+        polygon!(
+            exterior: [(x: 0.0, y: 0.0), (x: 4.0, y: 0.0), (x: 4.0, y: 4.0), (x: 0.0, y: 4.0)],
+            interiors: [[(x: 1.0, y: 1.0), (x: 1.0, y: 2.0), (x: 2.0, y: 2.0), (x: 2.0, y: 1.0)]],
+        )
+    }
+
+    #[test]
+    fn wkb_round_trips_every_type() {
+        round_trip(Geometry::Point(point!(x: 114.1, y: 22.3)));
+        round_trip(Geometry::LineString(lines()[1].clone()));
+        round_trip(Geometry::Polygon(with_hole()));
+        round_trip(Geometry::MultiPoint(MultiPoint(vec![
+            point!(x: 1.0, y: 2.0),
+            point!(x: -3.0, y: 4.5),
+        ])));
+        round_trip(Geometry::MultiLineString(MultiLineString(lines())));
+        round_trip(Geometry::MultiPolygon(MultiPolygon(vec![
+            with_hole(),
+            polygon![(x: 5.0, y: 5.0), (x: 6.0, y: 5.0), (x: 6.0, y: 6.0)],
+        ])));
+        round_trip(Geometry::GeometryCollection(GeometryCollection(vec![
+            Geometry::Point(point!(x: 1.0, y: 2.0)),
+            Geometry::Polygon(with_hole()),
+            Geometry::MultiLineString(MultiLineString(lines())),
+        ])));
+        round_trip(Geometry::MultiPolygon(MultiPolygon(vec![])));
+    }
+
+    #[test]
+    fn wkb_layout_and_simple_types() {
+        // byte order, type code, x and y
+        let point = to_wkb(&Geometry::Point(point!(x: 1.0, y: 2.0)));
+        assert_eq!(point.len(), 21);
+        assert_eq!(point[..5], [1, 1, 0, 0, 0]);
+        assert_eq!(point[5..13], 1.0f64.to_le_bytes());
+
+        let line = Line::new((0.0, 0.0), (1.0, 1.0));
+        let decoded = from_wkb(&to_wkb(&Geometry::Line(line))).unwrap();
+        assert_eq!(decoded, Geometry::LineString(line.into()));
+        let triangle = Triangle::new((0.0, 0.0).into(), (1.0, 0.0).into(), (0.0, 1.0).into());
+        let decoded = from_wkb(&to_wkb(&Geometry::Triangle(triangle))).unwrap();
+        assert_eq!(decoded, Geometry::Polygon(triangle.to_polygon()));
+    }
+
+    #[test]
+    fn reads_big_endian_and_rejects_bad_wkb() {
+        let mut big = vec![0, 0, 0, 0, 1];
+        big.extend_from_slice(&1.5f64.to_be_bytes());
+        big.extend_from_slice(&(-2.0f64).to_be_bytes());
+        assert_eq!(
+            from_wkb(&big).unwrap(),
+            Geometry::Point(point!(x: 1.5, y: -2.0))
+        );
+
+        let point = to_wkb(&Geometry::Point(point!(x: 1.0, y: 2.0)));
+        assert!(from_wkb(&point[..20]).is_err());
+        assert!(from_wkb(&[point.clone(), vec![0]].concat()).is_err());
+        let mut z = point.clone();
+        z[1] = 0xe9; // 1001, a Point Z
+        z[2] = 0x03;
+        assert!(from_wkb(&z).is_err());
+    }
+
+    fn geo_metadata(path: &Path) -> serde_json::Value {
+        let mut file = std::fs::File::open(path).unwrap();
+        let metadata = polars_parquet::read::read_metadata(&mut file).unwrap();
+        let kv = metadata.key_value_metadata.unwrap();
+        let geo = kv.iter().find(|kv| kv.key == "geo").unwrap();
+        serde_json::from_str(geo.value.as_deref().unwrap()).unwrap()
+    }
+
+    fn footprints() -> DataFrame {
+        DataFrame::new(vec![
+            Column::new("OBJECTID".into(), [1i64, 2]),
+            polygons(vec![Some(list(vec![square(1.0, 2.0, 1.0)])), None]),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn writes_wkb_and_geo_metadata() {
+        let path = temp_dir("geoparquet").join("out.parquet");
+        write_geoparquet(&footprints(), &path).unwrap();
+
+        let geo = geo_metadata(&path);
+        assert_eq!(geo["primary_column"], "geometry");
+        let column = &geo["columns"]["geometry"];
+        assert_eq!(column["encoding"], "WKB");
+        assert_eq!(column["geometry_types"], serde_json::json!(["Polygon"]));
+        assert_eq!(column["bbox"], serde_json::json!([1.0, 2.0, 2.0, 3.0]));
+        assert!(column.get("crs").is_none());
+
+        let df = LazyFrame::scan_parquet(&path, ScanArgsParquet::default())
+            .unwrap()
+            .collect()
+            .unwrap();
+        let wkb = df.column("geometry").unwrap().binary().unwrap().clone();
+        let Geometry::Polygon(p) = from_wkb(wkb.get(0).unwrap()).unwrap() else {
+            panic!("expected a polygon");
+        };
+        assert_eq!(p.exterior().0[2], Coord { x: 2.0, y: 3.0 });
+        assert_eq!(wkb.get(1), None);
+    }
+
+    #[test]
+    fn writes_the_crs_of_a_tagged_column() {
+        for crs in [Crs::Hk1980Grid, Crs::WebMercator] {
+            let mut df = footprints();
+            let tagged = with_crs(df.column("geometry").unwrap(), crs).unwrap();
+            df.with_column(tagged).unwrap();
+            let path = temp_dir("geoparquet-crs").join(format!("{}.parquet", crs.epsg()));
+            write_geoparquet(&df, &path).unwrap();
+            let column = &geo_metadata(&path)["columns"]["geometry"];
+            assert_eq!(column["crs"]["type"], "ProjectedCRS");
+            assert_eq!(column["crs"]["id"]["code"], crs.epsg());
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::crs::require_geographic;
+use crate::geometry::{point_coords, point_dtype, points_column};
+use geo::Coord;
+use h3o::{CellIndex, LatLng, Resolution};
+use polars::prelude::*;
+
+/// A global grid that points can be binned into for density maps.
+///
+/// Cell IDs are strings: H3 indexes in their usual hex form, and geohashes.
+/// Points must be longitude/latitude (EPSG:4326).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grid {
+    /// Hexagonal H3 cells, resolution 0 (continents) to 15 (about 1 m²).
+    /// Resolution 9 cells are about 0.1 km².
+    H3 { resolution: u8 },
+    /// Geohash rectangles of 1 to 12 characters. 7 characters is about 150 m.
+    Geohash { precision: usize },
+}
+
+impl Grid {
+    /// The cell containing each `{x, y}` point; null for null points and for
+    /// coordinates outside longitude/latitude range or not finite. An invalid
+    /// resolution or precision fails the query.
+    pub fn cell(self, point: Expr) -> Expr {
+        require_geographic(point, "grid cells").map(
+            move |s| {
+                self.check()?;
+                let cells: StringChunked = point_coords(&s)?
+                    .iter()
+                    .map(|p| p.and_then(|p| self.cell_of(p)))
+                    .collect();
+                Ok(Some(cells.with_name(s.name().clone()).into_column()))
+            },
+            GetOutput::from_type(DataType::String),
+        )
+    }
+
+    /// The centre of each cell ID as an `{x, y}` point; null for unknown IDs.
+    pub fn center(self, cell: Expr) -> Expr {
+        cell.map(
+            move |s| {
+                let centers: Vec<Option<Coord>> = s
+                    .str()?
+                    .into_iter()
+                    .map(|id| id.and_then(|id| self.center_of(id)))
+                    .collect();
+                points_column(s.name().clone(), &centers).map(Some)
+            },
+            GetOutput::from_type(point_dtype()),
+        )
+    }
+
+    fn check(self) -> PolarsResult<()> {
+        match self {
+            Grid::H3 { resolution } => {
+                Resolution::try_from(resolution)
+                    .map_err(|e| polars_err!(ComputeError: "invalid H3 resolution: {}", e))?;
+            }
+            Grid::Geohash { precision } => polars_ensure!(
+                (1..=12).contains(&precision),
+                ComputeError: "invalid geohash precision {}, expected 1 to 12", precision
+            ),
+        }
+        Ok(())
+    }
+
+    fn cell_of(self, point: Coord) -> Option<String> {
+        // h3o wraps any finite degrees, so range is checked here for both grids
+        if !((-180.0..=180.0).contains(&point.x) && (-90.0..=90.0).contains(&point.y)) {
+            return None;
+        }
+        match self {
+            Grid::H3 { resolution } => {
+                let resolution = Resolution::try_from(resolution).ok()?;
+                let latlng = LatLng::new(point.y, point.x).ok()?;
+                Some(latlng.to_cell(resolution).to_string())
+            }
+            Grid::Geohash { precision } => geohash::encode(point, precision).ok(),
+        }
+    }
+
+    fn center_of(self, id: &str) -> Option<Coord> {
+        match self {
+            Grid::H3 { .. } => {
+                let center = LatLng::from(id.parse::<CellIndex>().ok()?);
+                Some(Coord {
+                    x: center.lng(),
+                    y: center.lat(),
+                })
+            }
+            Grid::Geohash { .. } => geohash::decode(id).ok().map(|(center, _, _)| center),
+        }
+    }
+}
+
+/// H3 cell ID of each `{x, y}` point at `resolution`.
+pub fn h3_cell(point: Expr, resolution: u8) -> Expr {
+    Grid::H3 { resolution }.cell(point)
+}
+
+/// Geohash of each `{x, y}` point with `precision` characters.
+pub fn geohash_cell(point: Expr, precision: usize) -> Expr {
+    Grid::Geohash { precision }.cell(point)
+}
+
+/// Group rows by the `grid` cell of `point`, added as a `cell` column.
+pub fn group_by_cell(lf: LazyFrame, point: Expr, grid: Grid) -> LazyGroupBy {
+    lf.with_column(grid.cell(point).alias("cell"))
+        .group_by([col("cell")])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coords: &[Option<(f64, f64)>]) -> DataFrame {
+        let coords: Vec<_> = coords
+            .iter()
+            .map(|c| c.map(|(x, y)| Coord { x, y }))
+            .collect();
+        DataFrame::new(vec![points_column("point".into(), &coords).unwrap()]).unwrap()
+    }
+
+    fn cells(df: &DataFrame, grid: Grid) -> PolarsResult<Vec<Option<String>>> {
+        let out = df
+            .clone()
+            .lazy()
+            .select([grid.cell(col("point"))])
+            .collect()?;
+        Ok(out
+            .column("point")?
+            .str()?
+            .into_iter()
+            .map(|c| c.map(String::from))
+            .collect())
+    }
+
+    #[test]
+    fn geohash_of_a_known_point() {
+        let df = points(&[Some((-5.6, 42.6))]);
+        let grid = Grid::Geohash { precision: 5 };
+        assert_eq!(cells(&df, grid).unwrap(), [Some("ezs42".to_string())]);
+    }
+
+    #[test]
+    fn cells_round_trip_through_their_centres() {
+        let df = points(&[Some((114.1441448, 22.2878391))]);
+        for grid in [Grid::H3 { resolution: 9 }, Grid::Geohash { precision: 7 }] {
+            let out = df
+                .clone()
+                .lazy()
+                .select([grid.cell(col("point")).alias("cell")])
+                .with_column(grid.cell(grid.center(col("cell"))).alias("again"))
+                .collect()
+                .unwrap();
+            assert_eq!(
+                out.column("cell").unwrap(),
+                &out.column("again")
+                    .unwrap()
+                    .clone()
+                    .with_name("cell".into())
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_and_null_points_get_null_cells() {
+        let df = points(&[
+            Some((114.1, 22.3)),
+            None,
+            Some((200.0, 22.3)),
+            Some((114.1, -91.0)),
+        ]);
+        for grid in [Grid::H3 { resolution: 9 }, Grid::Geohash { precision: 7 }] {
+            let cells = cells(&df, grid).unwrap();
+            assert!(cells[0].is_some());
+            assert_eq!(cells[1..], [None, None, None]);
+        }
+        // a raw struct column, as points_column would null these out
+        let x = Series::new("x".into(), [f64::NAN, f64::INFINITY]);
+        let y = Series::new("y".into(), [22.3, 22.3]);
+        let raw = StructChunked::from_series("point".into(), 2, [x, y].iter()).unwrap();
+        let raw = DataFrame::new(vec![raw.into_column()]).unwrap();
+        for grid in [Grid::H3 { resolution: 9 }, Grid::Geohash { precision: 7 }] {
+            assert_eq!(cells(&raw, grid).unwrap(), [None, None]);
+        }
+    }
+
+    #[test]
+    fn invalid_grid_fails_the_query() {
+        let df = points(&[Some((114.1, 22.3))]);
+        assert!(cells(&df, Grid::H3 { resolution: 16 }).is_err());
+        assert!(cells(&df, Grid::Geohash { precision: 0 }).is_err());
+        assert!(cells(&df, Grid::Geohash { precision: 13 }).is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::dataset::BBOX_COLUMNS;
+use crate::write_parquet;
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// When a version of a building became current.
+pub const VALID_FROM: &str = "valid_from";
+/// When a version stopped being current (exclusive); null for current versions.
+pub const VALID_TO: &str = "valid_to";
+
+fn timestamp_dtype() -> DataType {
+    DataType::Datetime(TimeUnit::Milliseconds, None)
+}
+
+/// `t` as a naive UTC millisecond timestamp, the type of [`VALID_FROM`] and [`VALID_TO`].
+pub fn timestamp(t: DateTime<Utc>) -> Expr {
+    lit(t.timestamp_millis()).cast(timestamp_dtype())
+}
+
+/// Fold a snapshot taken at `taken_at` into a slowly-changing-dimension (type 2)
+/// history table keyed by `OBJECTID`, or start one from it.
+///
+/// Buildings whose attributes or geometry differ from their current version get
+/// that version closed at `taken_at` and a new one opened; buildings missing from
+/// the snapshot are closed; unchanged ones keep their version. The bbox columns
+/// follow the geometry, so they are carried but not compared. `taken_at` must be
+/// later than every `valid_from` already in `history`.
+///
+/// The table gains the columns a snapshot adds, null in earlier versions; columns
+/// a snapshot lacks are null in the versions it opens.
+pub fn apply_snapshot(
+    history: Option<LazyFrame>,
+    mut snapshot: LazyFrame,
+    taken_at: DateTime<Utc>,
+) -> PolarsResult<LazyFrame> {
+    let opened = |lf: LazyFrame| {
+        lf.with_columns([
+            timestamp(taken_at).alias(VALID_FROM),
+            lit(NULL).cast(timestamp_dtype()).alias(VALID_TO),
+        ])
+    };
+    let Some(mut history) = history else {
+        return Ok(opened(snapshot));
+    };
+
+    // columns the snapshot adds are null in older versions, and columns it drops
+    // are null in the versions it opens
+    let old = history.collect_schema()?;
+    let new = snapshot.collect_schema()?;
+    let missing = |from: &Schema, to: &Schema| -> Vec<Expr> {
+        from.iter()
+            .filter(|(n, _)| !to.contains(n) && ![VALID_FROM, VALID_TO].contains(&n.as_str()))
+            .map(|(n, dtype)| lit(NULL).cast(dtype.clone()).alias(n.clone()))
+            .collect()
+    };
+    let history = history.with_columns(missing(&new, &old));
+    let snapshot = snapshot.with_columns(missing(&old, &new));
+    let columns: Vec<PlSmallStr> = old
+        .iter_names()
+        .filter(|n| ![VALID_FROM, VALID_TO].contains(&n.as_str()))
+        .chain(new.iter_names().filter(|n| !old.contains(n)))
+        .cloned()
+        .collect();
+    let names: Vec<Expr> = columns
+        .iter()
+        .chain([&VALID_FROM.into(), &VALID_TO.into()])
+        .map(|n| col(n.clone()))
+        .collect();
+    let compared: Vec<PlSmallStr> = columns
+        .iter()
+        .filter(|n| n.as_str() != "OBJECTID" && !BBOX_COLUMNS.contains(&n.as_str()))
+        .cloned()
+        .collect();
+
+    let incoming = snapshot.clone().select(
+        std::iter::once(col("OBJECTID"))
+            .chain(std::iter::once(lit(true).alias("_incoming")))
+            .chain(
+                compared
+                    .iter()
+                    .map(|c| col(c.clone()).alias(format!("{}_incoming", c))),
+            )
+            .collect::<Vec<_>>(),
+    );
+    let same = compared
+        .iter()
+        .fold(col("_incoming").is_not_null(), |acc, c| {
+            acc.and(col(c.clone()).eq_missing(col(format!("{}_incoming", c))))
+        });
+    let open = history
+        .clone()
+        .filter(col(VALID_TO).is_null())
+        .join(
+            incoming,
+            [col("OBJECTID")],
+            [col("OBJECTID")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .with_column(same.alias("_same"));
+
+    let closed = history
+        .filter(col(VALID_TO).is_not_null())
+        .select(names.clone());
+    let kept = open.clone().filter(col("_same")).select(names.clone());
+    let ended = open
+        .filter(col("_same").not())
+        .with_column(timestamp(taken_at).alias(VALID_TO))
+        .select(names.clone());
+    let started = opened(snapshot)
+        .join(
+            kept.clone()
+                .select([col("OBJECTID"), lit(true).alias("_kept")]),
+            [col("OBJECTID")],
+            [col("OBJECTID")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .filter(col("_kept").is_null())
+        .select(names);
+
+    Ok(
+        concat([closed, kept, ended, started], UnionArgs::default())?
+            .sort(["OBJECTID", VALID_FROM], SortMultipleOptions::default()),
+    )
+}
+
+/// Apply a snapshot to the history table at `path`, creating the file if needed.
+pub fn update_history(
+    path: &Path,
+    snapshot: LazyFrame,
+    taken_at: DateTime<Utc>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let staged = stage_history(path, snapshot, taken_at)?;
+    std::fs::rename(staged, path)?;
+    Ok(())
+}
+
+/// Write the table [`update_history`] would to a file next to `path`, returning
+/// it for the caller to rename over `path`.
+pub(crate) fn stage_history(
+    path: &Path,
+    snapshot: LazyFrame,
+    taken_at: DateTime<Utc>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let history = if path.exists() {
+        Some(scan_history(path)?)
+    } else {
+        None
+    };
+    let mut df = apply_snapshot(history, snapshot, taken_at)?.collect()?;
+    // the old table is still being read above, so the new one goes elsewhere
+    let tmp = path.with_extension("parquet.tmp");
+    if let Err(e) = write_parquet(&mut df, &tmp) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(tmp)
+}
+
+pub fn scan_history(path: &Path) -> PolarsResult<LazyFrame> {
+    LazyFrame::scan_parquet(path, ScanArgsParquet::default())
+}
+
+/// The versions current at `at`: the data as the snapshot then held it.
+pub fn as_of(history: LazyFrame, at: DateTime<Utc>) -> LazyFrame {
+    history.filter(
+        col(VALID_FROM)
+            .lt_eq(timestamp(at))
+            .and(col(VALID_TO).is_null().or(col(VALID_TO).gt(timestamp(at)))),
+    )
+}
+
+/// Every change to `column`: `OBJECTID`, `changed_at`, the value before and the
+/// value after, ordered by building and time. A building's first version is
+/// not a change.
+pub fn changes(history: LazyFrame, column: &str) -> LazyFrame {
+    let before = format!("{}_before", column);
+    history
+        .sort(["OBJECTID", VALID_FROM], SortMultipleOptions::default())
+        .with_columns([
+            col(column)
+                .shift(lit(1))
+                .over([col("OBJECTID")])
+                .alias(before.as_str()),
+            col(VALID_FROM)
+                .shift(lit(1))
+                .over([col("OBJECTID")])
+                .is_not_null()
+                .alias("_has_before"),
+        ])
+        .filter(col("_has_before").and(col(column).neq_missing(col(before.as_str()))))
+        .select([
+            col("OBJECTID"),
+            col(VALID_FROM).alias("changed_at"),
+            col(before.as_str()),
+            col(column),
+        ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone};
+
+    fn at(month: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, month, 1, 0, 0, 0).unwrap()
+    }
+
+    fn snapshot(rows: &[(i64, f64)]) -> LazyFrame {
+        df!(
+            "OBJECTID" => rows.iter().map(|r| r.0).collect::<Vec<_>>(),
+            "TOPHEIGHT" => rows.iter().map(|r| r.1).collect::<Vec<_>>(),
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn history(snapshots: Vec<LazyFrame>) -> LazyFrame {
+        snapshots
+            .into_iter()
+            .enumerate()
+            .try_fold(None, |history, (i, snapshot)| {
+                apply_snapshot(history, snapshot, at(i as u32 + 1)).map(Some)
+            })
+            .unwrap()
+            .unwrap()
+    }
+
+    /// `(OBJECTID, TOPHEIGHT, valid_from month, valid_to month)` per version.
+    fn versions(lf: LazyFrame) -> Vec<(i64, Option<f64>, u32, Option<u32>)> {
+        let df = lf.collect().unwrap();
+        let month = |name: &str| -> Vec<Option<u32>> {
+            df.column(name)
+                .unwrap()
+                .datetime()
+                .unwrap()
+                .into_iter()
+                .map(|t| t.map(|t| DateTime::from_timestamp_millis(t).unwrap().month()))
+                .collect()
+        };
+        let ids = df.column("OBJECTID").unwrap().i64().unwrap();
+        let heights = df.column("TOPHEIGHT").unwrap().f64().unwrap();
+        let (from, to) = (month(VALID_FROM), month(VALID_TO));
+        (0..df.height())
+            .map(|i| (ids.get(i).unwrap(), heights.get(i), from[i].unwrap(), to[i]))
+            .collect()
+    }
+
+    #[test]
+    fn versions_open_and_close_with_changes() {
+        let lf = history(vec![
+            snapshot(&[(1, 10.0), (2, 20.0), (3, 30.0)]),
+            // 1 unchanged, 2 modified, 3 removed
+            snapshot(&[(1, 10.0), (2, 25.0)]),
+            // 3 re-added
+            snapshot(&[(1, 10.0), (2, 25.0), (3, 30.0)]),
+        ]);
+        assert_eq!(
+            versions(lf),
+            [
+                (1, Some(10.0), 1, None),
+                (2, Some(20.0), 1, Some(2)),
+                (2, Some(25.0), 2, None),
+                (3, Some(30.0), 1, Some(2)),
+                (3, Some(30.0), 3, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn as_of_boundaries() {
+        let lf = history(vec![
+            snapshot(&[(1, 10.0), (2, 20.0)]),
+            snapshot(&[(1, 15.0)]),
+        ]);
+        let heights = |t: DateTime<Utc>| -> Vec<(i64, Option<f64>)> {
+            versions(as_of(lf.clone(), t))
+                .into_iter()
+                .map(|v| (v.0, v.1))
+                .collect()
+        };
+        let before_start = at(1) - chrono::Duration::milliseconds(1);
+        assert_eq!(heights(before_start), []);
+        // valid_from is inclusive and valid_to exclusive
+        assert_eq!(heights(at(1)), [(1, Some(10.0)), (2, Some(20.0))]);
+        assert_eq!(
+            heights(at(2) - chrono::Duration::milliseconds(1)),
+            [(1, Some(10.0)), (2, Some(20.0))]
+        );
+        assert_eq!(heights(at(2)), [(1, Some(15.0))]);
+        assert_eq!(heights(at(12)), [(1, Some(15.0))]);
+    }
+
+    #[test]
+    fn snapshots_may_add_and_drop_columns() {
+        let named = snapshot(&[(1, 10.0), (2, 20.0)]).with_column(lit("A").alias("NAME"));
+        let lf = history(vec![
+            snapshot(&[(1, 10.0), (2, 20.0)]),
+            named.clone(),
+            named.clone(),
+            // the height is no longer published
+            named.select([col("OBJECTID"), col("NAME")]),
+        ]);
+        let df = lf.clone().collect().unwrap();
+        let columns: Vec<&str> = df
+            .get_column_names()
+            .into_iter()
+            .map(|n| n.as_str())
+            .collect();
+        assert_eq!(
+            columns,
+            ["OBJECTID", "TOPHEIGHT", "NAME", VALID_FROM, VALID_TO]
+        );
+        // the name opened a version, the dropped height another
+        assert_eq!(
+            versions(lf.filter(col("OBJECTID").eq(lit(1)))),
+            [
+                (1, Some(10.0), 1, Some(2)),
+                (1, Some(10.0), 2, Some(4)),
+                (1, None, 4, None)
+            ]
+        );
+        let names: Vec<_> = df
+            .column("NAME")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(names[..3], [None, Some("A"), Some("A")]);
+    }
+
+    #[test]
+    fn changes_list_each_new_value() {
+        let lf = history(vec![
+            snapshot(&[(1, 10.0)]),
+            snapshot(&[(1, 12.0)]),
+            snapshot(&[(1, 12.0)]),
+            snapshot(&[(1, 14.0)]),
+        ]);
+        let df = changes(lf, "TOPHEIGHT").collect().unwrap();
+        let before: Vec<_> = df
+            .column("TOPHEIGHT_before")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        let after: Vec<_> = df
+            .column("TOPHEIGHT")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(before, [Some(10.0), Some(12.0)]);
+        assert_eq!(after, [Some(12.0), Some(14.0)]);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::crs::Crs;
+use crate::dataset::Dataset;
+use crate::distance::EARTH_RADIUS_M;
+use crate::shape::bounding_box;
+use geo::Coord;
+use polars::prelude::*;
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{RTree, AABB};
+use std::path::{Path, PathBuf};
+
+type Entry = GeomWithData<Rectangle<[f64; 2]>, IdxSize>;
+
+/// An R-tree over footprint bounding boxes, keyed by row index in the frame it was built from.
+///
+/// For EPSG:4326 the longitudes are scaled by the cosine of the data's mean
+/// latitude, so distances inside the tree are close to true distances over an
+/// area the size of Hong Kong.
+pub struct SpatialIndex {
+    tree: RTree<Entry>,
+    crs: Crs,
+    x_scale: f64,
+}
+
+impl SpatialIndex {
+    /// Index every non-null geometry of the dataset.
+    pub fn build(dataset: &Dataset) -> PolarsResult<Self> {
+        let boxes = dataset
+            .lazy()
+            .select([bounding_box(col("geometry")).alias("bbox")])
+            .unnest(["bbox"])
+            .with_row_index("row", None)
+            .collect()?;
+        Self::from_boxes(&boxes, dataset.crs())
+    }
+
+    /// Build from a `row`, `min_x`, `min_y`, `max_x`, `max_y` frame; rows with null bounds are skipped.
+    pub fn from_boxes(boxes: &DataFrame, crs: Crs) -> PolarsResult<Self> {
+        let rows = boxes.column("row")?.cast(&IDX_DTYPE)?;
+        let rows = rows.idx()?;
+        let bound = |name: &str| -> PolarsResult<Float64Chunked> {
+            Ok(boxes.column(name)?.cast(&DataType::Float64)?.f64()?.clone())
+        };
+        let (min_x, min_y) = (bound("min_x")?, bound("min_y")?);
+        let (max_x, max_y) = (bound("max_x")?, bound("max_y")?);
+
+        let x_scale = if crs.is_geographic() {
+            let mean_lat = (min_y.mean().unwrap_or(0.0) + max_y.mean().unwrap_or(0.0)) / 2.0;
+            mean_lat.to_radians().cos()
+        } else {
+            1.0
+        };
+        let entries = (0..boxes.height())
+            .filter_map(|i| {
+                let row = rows.get(i)?;
+                let (x0, y0) = (min_x.get(i)?, min_y.get(i)?);
+                let (x1, y1) = (max_x.get(i)?, max_y.get(i)?);
+                let rect = Rectangle::from_corners([x0 * x_scale, y0], [x1 * x_scale, y1]);
+                Some(GeomWithData::new(rect, row))
+            })
+            .collect();
+        Ok(SpatialIndex {
+            tree: RTree::bulk_load(entries),
+            crs,
+            x_scale,
+        })
+    }
+
+    /// Where the index for a Parquet cache is persisted, e.g. `hk_buildings.4326.rtree.parquet`.
+    pub fn path_for(cache_path: &Path, crs: Crs) -> PathBuf {
+        cache_path.with_extension(format!("{}.rtree.parquet", crs.epsg()))
+    }
+
+    /// Load the index persisted next to the Parquet cache at `cache_path`, building
+    /// and saving it first if it is missing or older than the cache.
+    ///
+    /// The index is always built from a fresh scan of the whole cache, reprojected
+    /// to `crs`, so its row ids are rows of that file: query results apply to
+    /// [`Dataset::scan`] of `cache_path`, not to a filtered or sorted frame.
+    pub fn load_or_build(cache_path: &Path, crs: Crs) -> Result<Self, Box<dyn std::error::Error>> {
+        let cache_modified = std::fs::metadata(cache_path)?.modified()?;
+        let index_path = Self::path_for(cache_path, crs);
+        let fresh = std::fs::metadata(&index_path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|index| index >= cache_modified);
+        if fresh {
+            return Self::load(&index_path, crs);
+        }
+        let lf = LazyFrame::scan_parquet(cache_path, ScanArgsParquet::default())?;
+        let index = Self::build(&Dataset::new(lf, Crs::Wgs84).to_crs(crs))?;
+        index.save(&index_path)?;
+        Ok(index)
+    }
+
+    pub fn load(path: &Path, crs: Crs) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path)?;
+        let boxes = ParquetReader::new(file).finish()?;
+        Ok(Self::from_boxes(&boxes, crs)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut df = self.boxes()?;
+        let file = std::fs::File::create(path)?;
+        ParquetWriter::new(file).finish(&mut df)?;
+        Ok(())
+    }
+
+    /// The indexed bounding boxes as a `row`, `min_x`, `min_y`, `max_x`, `max_y` frame.
+    pub fn boxes(&self) -> PolarsResult<DataFrame> {
+        let entries: Vec<&Entry> = self.tree.iter().collect();
+        let bound = |f: fn(&Rectangle<[f64; 2]>) -> f64| -> Vec<f64> {
+            entries.iter().map(|e| f(e.geom())).collect()
+        };
+        let x_scale = self.x_scale;
+        let min_x: Vec<f64> = bound(|r| r.lower()[0])
+            .iter()
+            .map(|x| x / x_scale)
+            .collect();
+        let max_x: Vec<f64> = bound(|r| r.upper()[0])
+            .iter()
+            .map(|x| x / x_scale)
+            .collect();
+        let rows: Vec<IdxSize> = entries.iter().map(|e| e.data).collect();
+        let mut df = DataFrame::new(vec![
+            Column::new("row".into(), rows),
+            Column::new("min_x".into(), min_x),
+            Column::new("min_y".into(), bound(|r| r.lower()[1])),
+            Column::new("max_x".into(), max_x),
+            Column::new("max_y".into(), bound(|r| r.upper()[1])),
+        ])?;
+        df.sort_in_place(["row"], SortMultipleOptions::default())?;
+        Ok(df)
+    }
+
+    pub fn crs(&self) -> Crs {
+        self.crs
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn to_tree(&self, c: Coord) -> [f64; 2] {
+        [c.x * self.x_scale, c.y]
+    }
+
+    /// Metres per unit of distance inside the tree.
+    fn metres_per_unit(&self) -> f64 {
+        if self.crs.is_geographic() {
+            EARTH_RADIUS_M.to_radians()
+        } else {
+            1.0
+        }
+    }
+
+    /// Rows whose bounding box intersects the box spanned by `min` and `max`.
+    pub fn query_bbox(&self, min: Coord, max: Coord) -> Vec<IdxSize> {
+        let envelope = AABB::from_corners(self.to_tree(min), self.to_tree(max));
+        let mut rows: Vec<IdxSize> = self
+            .tree
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|e| e.data)
+            .collect();
+        rows.sort_unstable();
+        rows
+    }
+
+    /// Rows whose bounding box comes within `radius_m` metres of `center`.
+    pub fn query_radius(&self, center: Coord, radius_m: f64) -> Vec<IdxSize> {
+        let radius = radius_m / self.metres_per_unit();
+        let mut rows: Vec<IdxSize> = self
+            .tree
+            .locate_within_distance(self.to_tree(center), radius * radius)
+            .map(|e| e.data)
+            .collect();
+        rows.sort_unstable();
+        rows
+    }
+
+    /// The `k` rows whose bounding boxes are nearest to `point`, nearest first,
+    /// with their distance in metres (zero when the point is inside the box).
+    pub fn nearest(&self, point: Coord, k: usize) -> Vec<(IdxSize, f64)> {
+        let metres = self.metres_per_unit();
+        self.tree
+            .nearest_neighbor_iter_with_distance_2(&self.to_tree(point))
+            .take(k)
+            .map(|(e, d2)| (e.data, d2.sqrt() * metres))
+            .collect()
+    }
+}
+
+/// Keep only the given rows (as returned by a [`SpatialIndex`] query) of the frame the index was built from.
+pub fn take_rows(lf: LazyFrame, rows: &[IdxSize]) -> LazyFrame {
+    let rows = Series::new("rows".into(), rows);
+    lf.with_row_index("row", None)
+        .filter(col("row").is_in(lit(rows)))
+        .drop(["row"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{list, polygons, square, temp_dir};
+    use crate::write_parquet;
+    use std::time::{Duration, SystemTime};
+
+    /// Unit squares at x = 0, 10, 20, ... with a null footprint second.
+    fn cache(squares: usize) -> DataFrame {
+        let mut rows: Vec<Option<Series>> = (0..squares)
+            .map(|i| Some(list(vec![square(114.0 + i as f64 * 0.01, 22.3, 0.001)])))
+            .collect();
+        rows.insert(1, None);
+        DataFrame::new(vec![polygons(rows)]).unwrap()
+    }
+
+    fn coord(x: f64, y: f64) -> Coord {
+        Coord { x, y }
+    }
+
+    #[test]
+    fn queries_return_rows_of_the_indexed_frame() {
+        let index = SpatialIndex::build(&Dataset::new(cache(3).lazy(), Crs::Wgs84)).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(
+            index.query_bbox(coord(114.0, 22.3), coord(114.0105, 22.31)),
+            [0, 2]
+        );
+        // the second square is about 1 km east of the first
+        assert_eq!(index.query_radius(coord(114.0005, 22.3005), 500.0), [0]);
+        assert_eq!(
+            index.query_radius(coord(114.0005, 22.3005), 1_500.0),
+            [0, 2]
+        );
+        let nearest = index.nearest(coord(114.0205, 22.3005), 2);
+        assert_eq!(nearest[0], (3, 0.0));
+        assert_eq!(nearest[1].0, 2);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = temp_dir("index-round-trip");
+        let index = SpatialIndex::build(&Dataset::new(cache(3).lazy(), Crs::Wgs84)).unwrap();
+        let path = dir.join("boxes.parquet");
+        index.save(&path).unwrap();
+        let loaded = SpatialIndex::load(&path, Crs::Wgs84).unwrap();
+        assert!(loaded.boxes().unwrap().equals(&index.boxes().unwrap()));
+        let (min, max) = (coord(113.0, 22.0), coord(115.0, 23.0));
+        assert_eq!(loaded.query_bbox(min, max), index.query_bbox(min, max));
+        assert_eq!(loaded.crs(), Crs::Wgs84);
+    }
+
+    #[test]
+    fn load_or_build_indexes_the_whole_cache_and_rebuilds_when_stale() {
+        let dir = temp_dir("index-load-or-build");
+        let cache_path = dir.join("buildings.parquet");
+        write_parquet(&mut cache(2), &cache_path).unwrap();
+
+        let built = SpatialIndex::load_or_build(&cache_path, Crs::Wgs84).unwrap();
+        assert_eq!(
+            built
+                .boxes()
+                .unwrap()
+                .column("row")
+                .unwrap()
+                .idx()
+                .unwrap()
+                .to_vec(),
+            [Some(0), Some(2)]
+        );
+        let loaded = SpatialIndex::load_or_build(&cache_path, Crs::Wgs84).unwrap();
+        assert!(loaded.boxes().unwrap().equals(&built.boxes().unwrap()));
+
+        write_parquet(&mut cache(4), &cache_path).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&cache_path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let rebuilt = SpatialIndex::load_or_build(&cache_path, Crs::Wgs84).unwrap();
+        assert_eq!(rebuilt.len(), 4);
+
+        let projected = SpatialIndex::load_or_build(&cache_path, Crs::Hk1980Grid).unwrap();
+        assert_eq!(projected.crs(), Crs::Hk1980Grid);
+        assert!(SpatialIndex::path_for(&cache_path, Crs::Hk1980Grid).exists());
+        assert!(SpatialIndex::load_or_build(&dir.join("missing.parquet"), Crs::Wgs84).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::dataset::Dataset;
+use crate::geometry::decode_geometries;
+use crate::index::SpatialIndex;
+use geo::{BoundingRect, Centroid, Geometry, Intersects, PreparedGeometry, Relate};
+use polars::prelude::*;
+
+/// The relationship tested between a left geometry and a right polygon,
+/// read as "left `predicate` right".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Predicate {
+    Contains,
+    Intersects,
+    /// For a point, lying inside or on the boundary of the right polygon, so a
+    /// centroid exactly on a district border still gets a district (both of
+    /// them, if the border is shared). Other geometries must lie in the interior
+    /// with at most their boundary touching the right polygon's.
+    #[default]
+    Within,
+}
+
+/// Which left rows are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JoinHow {
+    Inner,
+    /// Keep every left row, with null right columns where nothing matched.
+    #[default]
+    Left,
+}
+
+/// What part of each left geometry the predicate is tested on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JoinOn {
+    #[default]
+    Centroid,
+    Footprint,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpatialJoinOptions {
+    pub predicate: Predicate,
+    pub how: JoinHow,
+    pub on: JoinOn,
+}
+
+/// Join each left row to the right rows whose geometry satisfies the predicate,
+/// e.g. buildings (by centroid) within District Council districts.
+///
+/// The right layer is reprojected to the left CRS. A left row matching several
+/// right rows appears once per match; right columns whose names clash with
+/// left ones get a `_right` suffix.
+pub fn sjoin(
+    left: &Dataset,
+    right: &Dataset,
+    options: SpatialJoinOptions,
+) -> PolarsResult<DataFrame> {
+    let right = right.clone().to_crs(left.crs());
+    let index = SpatialIndex::build(&right)?;
+    let right_df = right.lazy().collect()?;
+    let right_geoms = decode_geometries(right_df.column("geometry")?)?.geometries;
+    let prepared: Vec<Option<PreparedGeometry>> = right_geoms
+        .iter()
+        .map(|g| g.as_ref().map(|g| PreparedGeometry::from(g.clone())))
+        .collect();
+
+    let left_df = left.lazy().collect()?;
+    let left_geoms = decode_geometries(left_df.column("geometry")?)?.geometries;
+
+    let mut left_rows: Vec<IdxSize> = Vec::new();
+    let mut right_rows: Vec<Option<IdxSize>> = Vec::new();
+    for (i, g) in left_geoms.iter().enumerate() {
+        let target = g.as_ref().and_then(|g| match options.on {
+            JoinOn::Centroid => g.centroid().map(Geometry::Point),
+            JoinOn::Footprint => Some(g.clone()),
+        });
+        let mut matched = false;
+        if let Some(target) = target {
+            if let Some(rect) = target.bounding_rect() {
+                for j in index.query_bbox(rect.min(), rect.max()) {
+                    let (Some(geom), Some(prep)) =
+                        (&right_geoms[j as usize], &prepared[j as usize])
+                    else {
+                        continue;
+                    };
+                    if matches(&target, geom, prep, options.predicate) {
+                        left_rows.push(i as IdxSize);
+                        right_rows.push(Some(j));
+                        matched = true;
+                    }
+                }
+            }
+        }
+        if !matched && options.how == JoinHow::Left {
+            left_rows.push(i as IdxSize);
+            right_rows.push(None);
+        }
+    }
+
+    let mut out = left_df.take(&IdxCa::from_vec("row".into(), left_rows))?;
+    let mut matched = right_df.take(&IdxCa::new("row".into(), right_rows))?;
+    for name in matched.get_column_names_owned() {
+        if out.get_column_index(&name).is_some() {
+            matched.rename(&name, format!("{}_right", name).into())?;
+        }
+    }
+    out.hstack_mut(matched.get_columns())?;
+    Ok(out)
+}
+
+fn matches(left: &Geometry, right: &Geometry, prepared: &PreparedGeometry, p: Predicate) -> bool {
+    // point-in-polygon is by far the common case and has a cheaper test than relate
+    if let Geometry::Point(point) = left {
+        return match p {
+            Predicate::Within | Predicate::Intersects => right.intersects(point),
+            Predicate::Contains => matches!(right, Geometry::Point(q) if q == point),
+        };
+    }
+    let matrix = prepared.relate(left);
+    match p {
+        Predicate::Within => matrix.is_contains(),
+        Predicate::Intersects => matrix.is_intersects(),
+        Predicate::Contains => matrix.is_within(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crs::Crs;
+    use crate::geometry::encode_geometries;
+    use crate::test_util::{list, polygons, square};
+    use geo::Point;
+
+    /// Two unit-square districts sharing the edge x = 1.
+    fn districts() -> Dataset {
+        let geometry = polygons(vec![
+            Some(list(vec![square(0.0, 0.0, 1.0)])),
+            Some(list(vec![square(1.0, 0.0, 1.0)])),
+        ]);
+        let names = Column::new("name".into(), &["west", "east"]);
+        Dataset::new(
+            DataFrame::new(vec![names, geometry]).unwrap().lazy(),
+            Crs::Wgs84,
+        )
+    }
+
+    fn points(points: &[(f64, f64)]) -> Dataset {
+        let geometries: Vec<Option<Geometry>> = points
+            .iter()
+            .map(|&(x, y)| Some(Geometry::Point(Point::new(x, y))))
+            .collect();
+        let geometry = encode_geometries("geometry".into(), &geometries).unwrap();
+        let ids = Column::new("id".into(), (0..points.len() as i64).collect::<Vec<_>>());
+        Dataset::new(
+            DataFrame::new(vec![ids, geometry]).unwrap().lazy(),
+            Crs::Wgs84,
+        )
+    }
+
+    fn pairs(df: &DataFrame) -> Vec<(i64, Option<String>)> {
+        let ids = df.column("id").unwrap().i64().unwrap();
+        let names = df.column("name").unwrap().str().unwrap();
+        ids.into_no_null_iter()
+            .zip(names)
+            .map(|(id, name)| (id, name.map(str::to_string)))
+            .collect()
+    }
+
+    #[test]
+    fn points_on_a_boundary_are_within() {
+        let left = points(&[(0.5, 0.5), (0.0, 0.5), (1.0, 0.5), (5.0, 5.0)]);
+        let out = sjoin(&left, &districts(), SpatialJoinOptions::default()).unwrap();
+        assert_eq!(
+            pairs(&out),
+            [
+                (0, Some("west".into())),
+                (1, Some("west".into())),
+                (2, Some("west".into())),
+                (2, Some("east".into())),
+                (3, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn inner_joins_drop_unmatched_rows() {
+        let left = points(&[(1.5, 0.5), (5.0, 5.0)]);
+        let options = SpatialJoinOptions {
+            how: JoinHow::Inner,
+            ..Default::default()
+        };
+        let out = sjoin(&left, &districts(), options).unwrap();
+        assert_eq!(pairs(&out), [(0, Some("east".into()))]);
+    }
+
+    #[test]
+    fn footprints_within_and_intersecting() {
+        let geometry = polygons(vec![
+            Some(list(vec![square(0.25, 0.25, 0.5)])),
+            Some(list(vec![square(0.75, 0.25, 0.5)])),
+        ]);
+        let ids = Column::new("id".into(), &[0i64, 1]);
+        let name = Column::new("name".into(), &["a", "b"]);
+        let left = Dataset::new(
+            DataFrame::new(vec![ids, name, geometry]).unwrap().lazy(),
+            Crs::Wgs84,
+        );
+        let join = |predicate| {
+            let options = SpatialJoinOptions {
+                predicate,
+                how: JoinHow::Inner,
+                on: JoinOn::Footprint,
+            };
+            let out = sjoin(&left, &districts(), options).unwrap();
+            let right = out.column("name_right").unwrap().str().unwrap();
+            let ids = out.column("id").unwrap().i64().unwrap();
+            ids.into_no_null_iter()
+                .zip(right.into_no_null_iter().map(str::to_string))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(join(Predicate::Within), [(0, "west".to_string())]);
+        assert_eq!(
+            join(Predicate::Intersects),
+            [
+                (0, "west".to_string()),
+                (1, "west".to_string()),
+                (1, "east".to_string()),
+            ]
+        );
+        assert!(join(Predicate::Contains).is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use crate::centroid::centroid;
+use crate::crs::{column_crs, Crs};
+use crate::dataset::Dataset;
+use crate::distance::haversine_m;
+use crate::geometry::point_coords;
+use geo::Coord;
+use polars::prelude::*;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+
+type Entry = GeomWithData<[f64; 3], IdxSize>;
+
+/// Nearest-neighbour lookups between points and building centroids.
+///
+/// The dataset is collected once with a `centroid` column, and an R-tree is
+/// built over the centroids so each lookup only visits nearby buildings.
+/// Longitude/latitude centroids are stored as points on the unit sphere, where
+/// straight-line distance grows with great-circle distance, so the tree yields
+/// neighbours in exact haversine order and none is missed near the k-th.
+pub struct KnnIndex {
+    frame: DataFrame,
+    centroids: Vec<Option<Coord>>,
+    tree: RTree<Entry>,
+    crs: Crs,
+}
+
+impl KnnIndex {
+    pub fn build(dataset: &Dataset) -> PolarsResult<Self> {
+        let crs = dataset.crs();
+        let frame = dataset
+            .lazy()
+            .with_column(centroid(col("geometry")).alias("centroid"))
+            .collect()?;
+        let centroids = point_coords(frame.column("centroid")?)?;
+        let entries = centroids
+            .iter()
+            .enumerate()
+            .filter_map(|(row, c)| Some(GeomWithData::new(tree_point(crs, (*c)?), row as IdxSize)))
+            .collect();
+        Ok(KnnIndex {
+            frame,
+            centroids,
+            tree: RTree::bulk_load(entries),
+            crs,
+        })
+    }
+
+    fn distance(&self, a: Coord, b: Coord) -> f64 {
+        if self.crs.is_geographic() {
+            haversine_m(a, b)
+        } else {
+            (a.x - b.x).hypot(a.y - b.y)
+        }
+    }
+
+    /// Up to `k` (row, distance in metres) pairs nearest to `point`, nearest first.
+    fn neighbours(&self, point: Coord, k: usize) -> Vec<(IdxSize, f64)> {
+        self.tree
+            .nearest_neighbor_iter(&tree_point(self.crs, point))
+            .take(k)
+            .filter_map(|e| {
+                let c = self.centroids[e.data as usize]?;
+                Some((e.data, self.distance(point, c)))
+            })
+            .collect()
+    }
+
+    /// The `k` buildings whose centroids are nearest to `point`, nearest first,
+    /// with a `distance_m` column.
+    pub fn nearest(&self, point: Coord, k: usize) -> PolarsResult<DataFrame> {
+        let (rows, distances): (Vec<IdxSize>, Vec<f64>) =
+            self.neighbours(point, k).into_iter().unzip();
+        let mut out = self.frame.take(&IdxCa::from_vec("row".into(), rows))?;
+        out.with_column(Column::new("distance_m".into(), distances))?;
+        Ok(out)
+    }
+
+    /// For every row of `left`, the `k` nearest buildings to its `{x, y}` `point`.
+    ///
+    /// Each output row is a left row followed by the building columns (suffixed
+    /// `_right` where the names clash), `neighbour_rank` from 1 and `distance_m`.
+    /// Left rows with a null point have no matches and are dropped. The points
+    /// must be in the index's CRS, as [`crate::crs::point_to_crs`] tags them.
+    pub fn join(&self, left: LazyFrame, point: Expr, k: usize) -> PolarsResult<DataFrame> {
+        let left = left.with_column(point.alias("_knn_point")).collect()?;
+        let crs = column_crs(left.column("_knn_point")?)?;
+        polars_ensure!(
+            crs == self.crs,
+            ComputeError: "the points are in {}, but the index is in {}; reproject them first",
+            crs, self.crs
+        );
+        let points = point_coords(left.column("_knn_point")?)?;
+
+        let mut left_rows = Vec::new();
+        let mut right_rows = Vec::new();
+        let mut ranks = Vec::new();
+        let mut distances = Vec::new();
+        for (i, p) in points.iter().enumerate() {
+            let Some(p) = p else { continue };
+            for (rank, (row, distance)) in self.neighbours(*p, k).into_iter().enumerate() {
+                left_rows.push(i as IdxSize);
+                right_rows.push(row);
+                ranks.push(rank as u32 + 1);
+                distances.push(distance);
+            }
+        }
+
+        let mut out = left
+            .drop("_knn_point")?
+            .take(&IdxCa::from_vec("row".into(), left_rows))?;
+        let mut right = self
+            .frame
+            .take(&IdxCa::from_vec("row".into(), right_rows))?;
+        for name in right.get_column_names_owned() {
+            if out.get_column_index(&name).is_some() {
+                right.rename(&name, format!("{}_right", name).into())?;
+            }
+        }
+        out.hstack_mut(right.get_columns())?;
+        out.with_column(Column::new("neighbour_rank".into(), ranks))?;
+        out.with_column(Column::new("distance_m".into(), distances))?;
+        Ok(out)
+    }
+}
+
+/// Where a coordinate goes in the tree: a unit vector for longitude/latitude,
+/// the plane itself for projected coordinates.
+fn tree_point(crs: Crs, c: Coord) -> [f64; 3] {
+    if crs.is_geographic() {
+        let (lon, lat) = (c.x.to_radians(), c.y.to_radians());
+        [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+    } else {
+        [c.x, c.y, 0.0]
+    }
+}
+
+/// The `k` buildings whose centroids are nearest to `point`. Builds a
+/// throwaway [`KnnIndex`]; keep one around for repeated lookups.
+pub fn nearest(dataset: &Dataset, point: Coord, k: usize) -> PolarsResult<DataFrame> {
+    KnnIndex::build(dataset)?.nearest(point, k)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crs::point_to_crs;
+    use crate::geometry::points_column;
+    use crate::test_util::{list, polygons, square};
+
+    /// Tiny footprints centred on `centres`, with `OBJECTID` 0, 1, ...
+    fn dataset(centres: &[(f64, f64)]) -> Dataset {
+        let d = 1e-5;
+        let geometry = polygons(
+            centres
+                .iter()
+                .map(|&(x, y)| Some(list(vec![square(x - d / 2.0, y - d / 2.0, d)])))
+                .collect(),
+        );
+        let ids = Column::new(
+            "OBJECTID".into(),
+            (0..centres.len() as i64).collect::<Vec<_>>(),
+        );
+        Dataset::new(
+            DataFrame::new(vec![ids, geometry]).unwrap().lazy(),
+            Crs::Wgs84,
+        )
+    }
+
+    fn ids(df: &DataFrame) -> Vec<i64> {
+        df.column("OBJECTID")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_haversine_nearest_far_from_the_mean_latitude() {
+        // at 60°N a degree of longitude is about 55.6 km, but scaling longitudes by
+        // the cosine of the data's mean latitude (about 35°) made it look like
+        // 91 km, behind a building 0.7° (77.8 km) north
+        let index = KnnIndex::build(&dataset(&[
+            (11.0, 60.0),
+            (10.0, 60.7),
+            (10.0, 10.0),
+            (10.0, 10.5),
+        ]))
+        .unwrap();
+        let nearest = index.nearest(Coord { x: 10.0, y: 60.0 }, 1).unwrap();
+        assert_eq!(ids(&nearest), [0]);
+        let d = nearest
+            .column("distance_m")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert!((d - 55_600.0).abs() < 200.0, "{}", d);
+    }
+
+    #[test]
+    fn agrees_with_brute_force() {
+        let centres: Vec<(f64, f64)> = (0..200)
+            .map(|i| {
+                let i = i as f64;
+                ((i * 7.3) % 40.0 + 100.0, (i * 3.1) % 70.0 - 10.0)
+            })
+            .collect();
+        let index = KnnIndex::build(&dataset(&centres)).unwrap();
+        for query in [(115.0, 50.0), (101.0, -5.0), (139.0, 59.0)] {
+            let q = Coord {
+                x: query.0,
+                y: query.1,
+            };
+            let mut expected: Vec<(i64, f64)> = centres
+                .iter()
+                .enumerate()
+                .map(|(i, &(x, y))| (i as i64, haversine_m(q, Coord { x, y })))
+                .collect();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let got = index.nearest(q, 5).unwrap();
+            let expected: Vec<i64> = expected[..5].iter().map(|e| e.0).collect();
+            assert_eq!(ids(&got), expected, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn join_ranks_neighbours_of_each_left_row() {
+        let index =
+            KnnIndex::build(&dataset(&[(114.0, 22.0), (114.01, 22.0), (114.1, 22.0)])).unwrap();
+        let points = [
+            Some(Coord { x: 114.0, y: 22.0 }),
+            None,
+            Some(Coord { x: 114.1, y: 22.0 }),
+        ];
+        let left = DataFrame::new(vec![
+            Column::new("OBJECTID".into(), &[10i64, 11, 12]),
+            points_column("point".into(), &points).unwrap(),
+        ])
+        .unwrap();
+        let out = index.join(left.lazy(), col("point"), 2).unwrap();
+        assert_eq!(ids(&out), [10, 10, 12, 12]);
+        let right: Vec<i64> = out
+            .column("OBJECTID_right")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(right, [0, 1, 2, 1]);
+        let ranks: Vec<u32> = out
+            .column("neighbour_rank")
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(ranks, [1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn join_refuses_points_in_another_crs() {
+        let centres = [(114.0, 22.0), (114.01, 22.0)];
+        let points = points_column("point".into(), &[Some(Coord { x: 114.0, y: 22.0 })]).unwrap();
+        let left = DataFrame::new(vec![points]).unwrap().lazy();
+
+        let projected = KnnIndex::build(&dataset(&centres).to_crs(Crs::Hk1980Grid)).unwrap();
+        let err = projected.join(left.clone(), col("point"), 1).unwrap_err();
+        assert!(err.to_string().contains("EPSG:4326"), "{}", err);
+        let reprojected = point_to_crs(col("point"), Crs::Wgs84, Crs::Hk1980Grid);
+        let out = projected
+            .join(left.clone(), reprojected.clone(), 1)
+            .unwrap();
+        assert_eq!(out.height(), 1);
+        let d = out
+            .column("distance_m")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert!(d < 1.0, "{}", d);
+
+        let geographic = KnnIndex::build(&dataset(&centres)).unwrap();
+        assert!(geographic.join(left, reprojected, 1).is_err());
+    }
+}
@@ -1,8 +1,37 @@
+pub mod bucket;
+pub mod centroid;
+pub mod crs;
+pub mod dataset;
+pub mod diff;
+pub mod distance;
+pub mod geometry;
+pub mod geoparquet;
+pub mod grid;
+pub mod history;
+pub mod index;
+pub mod join;
+pub mod knn;
+pub mod ops;
+pub mod quality;
+pub mod report;
+pub mod shape;
+pub mod simplify;
+pub mod snapshot;
+pub mod sort;
+pub mod sql;
+#[cfg(test)]
+mod test_util;
+pub mod validate;
+
+use dataset::with_bbox_columns;
 use polars::prelude::*;
 use reqwest::blocking::Client;
-use std::path::Path;
+use sort::{sort_spatially, Curve};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+const ROW_GROUP_SIZE: usize = 8_192;
+
 fn download_data(file_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
     let url = "https://hub.arcgis.com/api/v3/datasets/2163df5803044dc3a8f6b6054092fc71_0/downloads/data?format=geojson&spatialRefId=4326&where=1%3D1";
     let client = Client::new();
@@ -34,36 +63,151 @@ pub fn load_data_json(file_path: &Path) -> Result<(), Box<dyn std::error::Error>
 }
 
 pub fn load_data_parquet(file_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    load_data_parquet_sorted(file_path, None)
+}
+
+/// The Parquet file of the newest snapshot taken on or before `date` in the
+/// [`snapshot::SnapshotStore`] at `store`. Nothing is downloaded: an empty store
+/// has no snapshot for any date.
+pub fn load_data_as_of(
+    store: &Path,
+    date: chrono::NaiveDate,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let store = snapshot::SnapshotStore::open(store)?;
+    match (store.as_of(date), store.snapshots().first()) {
+        (Some(snapshot), _) => Ok(store.path(snapshot)),
+        (None, Some(oldest)) => Err(format!(
+            "no snapshot on or before {}; the oldest is from {}",
+            date,
+            oldest.date()
+        )
+        .into()),
+        (None, None) => Err(format!(
+            "no snapshot on or before {}; the store is empty, run `snapshot refresh`",
+            date
+        )
+        .into()),
+    }
+}
+
+/// Like [`load_data_parquet`], choosing how rows are ordered when the cache is
+/// written. With a curve, nearby buildings share row groups, so bbox filters on
+/// the scan skip most of the file; `None`, the default, keeps the download's
+/// feature order.
+pub fn load_data_parquet_sorted(
+    file_path: &Path,
+    sort: Option<Curve>,
+) -> Result<(), Box<dyn std::error::Error>> {
     if !file_path.exists() {
         let json_path = file_path.with_extension("geojson");
         if json_path.exists() {
-            let json_file = std::fs::File::open(&json_path)?;
-            let mut df = JsonReader::new(json_file).finish()?;
-            df = unnest_df(&df)?;
-            let file = std::fs::File::create(file_path)?;
-            ParquetWriter::new(file).finish(&mut df)?;
+            let mut df = read_geojson_sorted(&json_path, sort)?;
+            write_parquet(&mut df, file_path)?;
         } else {
             load_data_json(&json_path)?;
-            load_data_parquet(file_path)?;
+            load_data_parquet_sorted(file_path, sort)?;
         }
     }
     Ok(())
 }
+
+/// Read a buildings GeoJSON with the [`dataset::BBOX_COLUMNS`] added, ordered along
+/// `sort` if given.
+fn read_geojson_sorted(
+    file_path: &Path,
+    sort: Option<Curve>,
+) -> Result<DataFrame, Box<dyn std::error::Error>> {
+    Ok(cache_layout(read_geojson(file_path)?.lazy(), sort)?.collect()?)
+}
+
+/// Add the [`dataset::BBOX_COLUMNS`] and order rows along `sort` if given.
+fn cache_layout(lf: LazyFrame, sort: Option<Curve>) -> PolarsResult<LazyFrame> {
+    let lf = with_bbox_columns(lf);
+    match sort {
+        Some(curve) => sort_spatially(lf, curve),
+        None => Ok(lf),
+    }
+}
+
+fn write_parquet(df: &mut DataFrame, file_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::create(file_path)?;
+    // small row groups so their bbox statistics can skip most of the file
+    ParquetWriter::new(file)
+        .with_row_group_size(Some(ROW_GROUP_SIZE))
+        .finish(df)?;
+    Ok(())
+}
+
+/// File formats [`convert`] can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Parquet laid out like the [`load_data`] cache.
+    Parquet,
+    /// Arrow IPC (Feather v2).
+    Ipc,
+    /// Parquet with WKB geometries and GeoParquet metadata, see [`geoparquet`].
+    GeoParquet,
+}
+
+impl OutputFormat {
+    /// The format implied by `.parquet`, `.geoparquet`, `.ipc`, `.arrow` or `.feather`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "parquet" => Ok(OutputFormat::Parquet),
+            "ipc" | "arrow" | "feather" => Ok(OutputFormat::Ipc),
+            "geoparquet" => Ok(OutputFormat::GeoParquet),
+            other => Err(format!("unknown output format '{}'", other)),
+        }
+    }
+}
+
+/// Convert a buildings GeoJSON file into `format`, with the same bbox columns as
+/// the Parquet cache and rows ordered along `sort` if given.
+pub fn convert(
+    input: &Path,
+    output: &Path,
+    format: OutputFormat,
+    sort: Option<Curve>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut df = read_geojson_sorted(input, sort)?;
+    match format {
+        OutputFormat::Parquet => write_parquet(&mut df, output),
+        OutputFormat::Ipc => {
+            let file = std::fs::File::create(output)?;
+            IpcWriter::new(file).finish(&mut df)?;
+            Ok(())
+        }
+        OutputFormat::GeoParquet => geoparquet::write_geoparquet(&df, output),
+    }
+}
+
+pub fn read_geojson(file_path: &Path) -> Result<DataFrame, Box<dyn std::error::Error>> {
+    let json_file = std::fs::File::open(file_path)?;
+    let df = JsonReader::new(json_file).finish()?;
+    unnest_df(&df)
+}
+
 pub fn unnest_df(df: &DataFrame) -> Result<DataFrame, Box<dyn std::error::Error>> {
     let mut data: Vec<Column> = Vec::new();
-    for value in df.column("features")?.list()? {
-        if let Some(series) = value {
-            let geometry = series.struct_()?.field_by_name("geometry")?.into_column();
-            let fields_as_series = series
-                .struct_()?
-                .field_by_name("properties")?
-                .struct_()?
-                .fields_as_series();
-            let properties = fields_as_series.iter().map(|s| s.clone().into_column());
-
-            data.extend(properties);
-            data.push(geometry)
-        }
+    for series in df.column("features")?.list()?.into_iter().flatten() {
+        let geometry = series.struct_()?.field_by_name("geometry")?.into_column();
+        let fields_as_series = series
+            .struct_()?
+            .field_by_name("properties")?
+            .struct_()?
+            .fields_as_series();
+        let properties = fields_as_series.iter().map(|s| s.clone().into_column());
+
+        data.extend(properties);
+        data.push(geometry)
     }
     Ok(DataFrame::new(data)?)
 }
@@ -0,0 +1,549 @@
+use chrono::NaiveDate;
+use clap::builder::RangedU64ValueParser;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use polars::prelude::*;
+use polars::sql::sql_expr;
+use polars_demo::dataset::BBOX_COLUMNS;
+use polars_demo::diff::{diff_snapshots, DiffOptions};
+use polars_demo::history::changes;
+use polars_demo::quality::{check_rules, default_rules, profile};
+use polars_demo::report::{Format, Report, ReportConfig, SnapshotDiff};
+use polars_demo::snapshot::{Retention, SnapshotStore};
+use polars_demo::sort::Curve;
+use polars_demo::sql::SpatialSql;
+use polars_demo::{convert, load_data, load_data_as_of, read_geojson, OutputFormat};
+use std::path::{Path, PathBuf};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn cli() -> Command {
+    Command::new("polars-demo")
+        .about("Download, convert, query and report on the Hong Kong buildings dataset")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("data")
+                .long("data")
+                .global(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Parquet cache of the buildings data [default: <temp dir>/hk_buildings.parquet]"),
+        )
+        .arg(
+            Arg::new("store")
+                .long("store")
+                .global(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Snapshot store directory [default: <temp dir>/hk_buildings_snapshots]"),
+        )
+        .arg(
+            Arg::new("as_of")
+                .long("as-of")
+                .global(true)
+                .conflicts_with("data")
+                .value_parser(value_parser!(NaiveDate))
+                .help("Use the newest snapshot taken on or before this date (YYYY-MM-DD) instead of the cache"),
+        )
+        .subcommand(
+            Command::new("fetch")
+                .about("Download the buildings data and build the Parquet cache")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Delete the cache and its GeoJSON download first"),
+                ),
+        )
+        .subcommand(
+            Command::new("snapshot")
+                .about("Manage the store of dated, immutable snapshots")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("List the snapshots, oldest first"))
+                .subcommand(
+                    retention_args(Command::new("refresh"))
+                        .about("Download the data as a new snapshot, then apply the retention policy"),
+                )
+                .subcommand(
+                    retention_args(Command::new("prune"))
+                        .about("Delete snapshots outside the retention policy"),
+                )
+                .subcommand(
+                    Command::new("history")
+                        .about("Show the recorded versions of a building, or changes to a column")
+                        .arg(
+                            Arg::new("objectid")
+                                .value_parser(value_parser!(i64))
+                                .help("Only this building"),
+                        )
+                        .arg(
+                            Arg::new("changes")
+                                .long("changes")
+                                .help("List changes to this column instead of whole versions, e.g. TOPHEIGHT"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("convert")
+                .about("Convert a buildings GeoJSON file to Parquet, IPC or GeoParquet")
+                .arg(
+                    Arg::new("input")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("output")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_parser(value_parser!(OutputFormat))
+                        .help("parquet, ipc or geoparquet [default: from the output extension]"),
+                )
+                .arg(
+                    Arg::new("sort")
+                        .long("sort")
+                        .value_parser(value_parser!(Curve))
+                        .help("Order rows along a curve of the building centroids: hilbert or z-order [default: input order]"),
+                ),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Run a report definition against the cache")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("TOML or YAML report definition [default: the built-in report]"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Write to this file instead of printing"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_parser(value_parser!(Format))
+                        .help("text, md, html or json [default: from the output extension, else text]"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("List buildings added, removed or modified between two snapshots")
+                .arg(
+                    Arg::new("old")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("new")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Parquet, IPC or GeoJSON file [default: the cache]"),
+                )
+                .arg(
+                    Arg::new("tolerance")
+                        .long("tolerance")
+                        .value_parser(value_parser!(f64))
+                        .default_value("0.5")
+                        .help("Footprint shift in metres below which geometry counts as unchanged"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Write the full diff table to .parquet or .ipc, or the report to .md, .html or .json"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_parser(value_parser!(Format))
+                        .help("Report format: text, md, html or json [default: from the output extension, else text]"),
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Query the cache with SQL or filter expressions")
+                .arg(
+                    Arg::new("sql")
+                        .long("sql")
+                        .conflicts_with_all(["filter", "select"])
+                        .help("SQL query against the table `buildings`, with spatial functions"),
+                )
+                .arg(
+                    Arg::new("filter")
+                        .long("filter")
+                        .action(ArgAction::Append)
+                        .help("SQL expression rows must match, e.g. \"TOPHEIGHT > 100\" (repeatable)"),
+                )
+                .arg(
+                    Arg::new("select")
+                        .long("select")
+                        .value_delimiter(',')
+                        .help("Comma-separated columns to show"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .value_parser(value_parser!(u32))
+                        .default_value("20"),
+                ),
+        )
+        .subcommand(
+            Command::new("sql")
+                .about("Run SQL with spatial functions, or start an interactive session")
+                .arg(
+                    Arg::new("query")
+                        .help("Statement to run [default: read statements from stdin]"),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Profile a data file: nulls, distinct values and ranges per column")
+                .arg(
+                    Arg::new("file")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Parquet, IPC or GeoJSON file [default: the cache]"),
+                )
+                .arg(
+                    Arg::new("histograms")
+                        .long("histograms")
+                        .action(ArgAction::SetTrue)
+                        .help("Also show value counts per column"),
+                )
+                .arg(
+                    Arg::new("bins")
+                        .long("bins")
+                        .value_parser(RangedU64ValueParser::<usize>::new().range(1..))
+                        .default_value("10")
+                        .help("Histogram buckets, or most common values for text"),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Run the default data quality rules; fails if an error-level rule is violated")
+                .arg(
+                    Arg::new("file")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Parquet, IPC or GeoJSON file [default: the cache]"),
+                )
+                .arg(
+                    Arg::new("show")
+                        .long("show")
+                        .value_parser(value_parser!(usize))
+                        .help("Also list this many violating rows"),
+                ),
+        )
+}
+
+fn retention_args(command: Command) -> Command {
+    // defaults match Retention::default()
+    command
+        .arg(
+            Arg::new("keep")
+                .long("keep")
+                .value_parser(value_parser!(usize))
+                .default_value("12")
+                .help("Number of newest snapshots to keep (0 for no limit)"),
+        )
+        .arg(
+            Arg::new("max_age_days")
+                .long("max-age-days")
+                .value_parser(value_parser!(i64))
+                .default_value("366")
+                .help("Delete snapshots older than this (0 for no limit)"),
+        )
+}
+
+fn main() -> Result<()> {
+    let matches = cli().get_matches();
+    let store = matches
+        .get_one::<PathBuf>("store")
+        .cloned()
+        .unwrap_or_else(|| std::env::temp_dir().join("hk_buildings_snapshots"));
+    let as_of = matches.get_one::<NaiveDate>("as_of");
+    // only the subcommands that read the buildings data look up the snapshot
+    let data = || -> Result<PathBuf> {
+        Ok(match as_of {
+            Some(date) => load_data_as_of(&store, *date)?,
+            None => matches
+                .get_one::<PathBuf>("data")
+                .cloned()
+                .unwrap_or_else(|| std::env::temp_dir().join("hk_buildings.parquet")),
+        })
+    };
+    match matches.subcommand() {
+        Some(("fetch", _)) if as_of.is_some() => {
+            Err("fetch works on the cache; use `snapshot refresh` for snapshots".into())
+        }
+        Some(("fetch", m)) => fetch(&data()?, m),
+        Some(("snapshot", m)) => snapshot(&store, m),
+        Some(("convert", m)) => convert_file(m),
+        Some(("report", m)) => report(&data()?, m),
+        Some(("diff", m)) => diff(&data()?, m),
+        Some(("query", m)) => query(&data()?, m),
+        Some(("sql", m)) => sql(&data()?, m),
+        Some(("inspect", m)) => inspect(&data()?, m),
+        Some(("check", m)) => check(&data()?, m),
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+fn fetch(data: &Path, m: &ArgMatches) -> Result<()> {
+    if m.get_flag("force") {
+        for path in [data.to_path_buf(), data.with_extension("geojson")] {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+    }
+    load_data(data)?;
+    let rows = scan(data)?.select([len()]).collect()?;
+    println!(
+        "{} ({} buildings)",
+        data.display(),
+        rows.column("len")?.get(0)?
+    );
+    Ok(())
+}
+
+fn snapshot(store: &Path, m: &ArgMatches) -> Result<()> {
+    let mut store = SnapshotStore::open(store)?;
+    let retention = |m: &ArgMatches| Retention {
+        keep_last: Some(*m.get_one::<usize>("keep").unwrap()).filter(|n| *n > 0),
+        max_age_days: Some(*m.get_one::<i64>("max_age_days").unwrap()).filter(|d| *d > 0),
+    };
+    match m.subcommand() {
+        Some(("list", _)) => {
+            for s in store.snapshots() {
+                println!(
+                    "{}  {:>7} rows  {}",
+                    s.taken_at,
+                    s.rows,
+                    store.path(s).display()
+                );
+            }
+        }
+        Some(("refresh", m)) => {
+            let s = store.refresh()?.clone();
+            println!("{} ({} buildings)", store.path(&s).display(), s.rows);
+            for s in store.prune(retention(m))? {
+                println!("Deleted {}", s.file);
+            }
+        }
+        Some(("prune", m)) => {
+            for s in store.prune(retention(m))? {
+                println!("Deleted {}", s.file);
+            }
+        }
+        Some(("history", m)) => {
+            let mut lf = store.history()?;
+            if let Some(id) = m.get_one::<i64>("objectid") {
+                lf = lf.filter(col("OBJECTID").eq(lit(*id)));
+            }
+            lf = match m.get_one::<String>("changes") {
+                Some(column) => changes(lf, column),
+                None => {
+                    lf.select([col("*").exclude(std::iter::once("geometry").chain(BBOX_COLUMNS))])
+                }
+            };
+            std::env::set_var("POLARS_FMT_MAX_ROWS", "50");
+            println!("{}", lf.collect()?);
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+    Ok(())
+}
+
+fn convert_file(m: &ArgMatches) -> Result<()> {
+    let input = m.get_one::<PathBuf>("input").unwrap();
+    let output = m.get_one::<PathBuf>("output").unwrap();
+    let format = match m.get_one::<OutputFormat>("format") {
+        Some(format) => *format,
+        None => OutputFormat::from_path(output)
+            .ok_or("can't tell the output format from its extension; pass --format")?,
+    };
+    convert(input, output, format, m.get_one::<Curve>("sort").copied())?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+fn report(data: &Path, m: &ArgMatches) -> Result<()> {
+    let config = match m.get_one::<PathBuf>("config") {
+        Some(path) => ReportConfig::from_path(path)?,
+        None => ReportConfig::default(),
+    };
+    let output = m.get_one::<PathBuf>("output");
+    let format = m
+        .get_one::<Format>("format")
+        .copied()
+        .or_else(|| output.and_then(|o| Format::from_path(o)))
+        .unwrap_or(Format::Text);
+
+    load_data(data)?;
+    let report = config.run(scan(data)?)?;
+    match output {
+        Some(output) => {
+            report.write(output, format)?;
+            println!("Wrote {} report to {}", format, output.display());
+        }
+        None => println!("{}", report.render(format)?),
+    }
+    Ok(())
+}
+
+fn diff(data: &Path, m: &ArgMatches) -> Result<()> {
+    let old = scan(m.get_one::<PathBuf>("old").unwrap())?;
+    let new = match m.get_one::<PathBuf>("new") {
+        Some(path) => scan(path)?,
+        None => {
+            load_data(data)?;
+            scan(data)?
+        }
+    };
+    let options = DiffOptions {
+        geometry_tolerance_m: *m.get_one::<f64>("tolerance").unwrap(),
+        ..DiffOptions::default()
+    };
+    let output = m.get_one::<PathBuf>("output");
+
+    if let Some(path) = output {
+        if let Some(table) = OutputFormat::from_path(path) {
+            let mut df = diff_snapshots(old, new, &options)?;
+            let file = std::fs::File::create(path)?;
+            match table {
+                OutputFormat::Parquet => {
+                    ParquetWriter::new(file).finish(&mut df)?;
+                }
+                OutputFormat::Ipc => IpcWriter::new(file).finish(&mut df)?,
+                OutputFormat::GeoParquet => return Err("the diff has no geometry column".into()),
+            }
+            println!("Wrote {} changes to {}", df.height(), path.display());
+            return Ok(());
+        }
+    }
+
+    let format = m
+        .get_one::<Format>("format")
+        .copied()
+        .or_else(|| output.and_then(|o| Format::from_path(o)))
+        .unwrap_or(Format::Text);
+    let mut section = SnapshotDiff::new(old);
+    section.options = options;
+    let report = Report::new("Snapshot Diff")
+        .with_section(section)
+        .run(new)?;
+    match output {
+        Some(output) => {
+            report.write(output, format)?;
+            println!("Wrote {} report to {}", format, output.display());
+        }
+        None => println!("{}", report.render(format)?),
+    }
+    Ok(())
+}
+
+fn query(data: &Path, m: &ArgMatches) -> Result<()> {
+    let lf = if let Some(sql) = m.get_one::<String>("sql") {
+        SpatialSql::buildings(data)?.query(sql)?
+    } else {
+        load_data(data)?;
+        let mut lf = scan(data)?;
+        for filter in m.get_many::<String>("filter").into_iter().flatten() {
+            lf = lf.filter(sql_expr(filter)?);
+        }
+        if let Some(columns) = m.get_many::<String>("select") {
+            lf = lf.select(columns.map(|c| col(c.as_str())).collect::<Vec<_>>());
+        }
+        lf
+    };
+    let limit = *m.get_one::<u32>("limit").unwrap();
+    println!("{}", lf.limit(limit).collect()?);
+    Ok(())
+}
+
+fn sql(data: &Path, m: &ArgMatches) -> Result<()> {
+    let mut ctx = SpatialSql::buildings(data)?;
+    match m.get_one::<String>("query") {
+        Some(sql) => println!("{}", ctx.query(sql)?.collect()?),
+        None => {
+            println!(
+                "Tables: {}. Type .help for commands.",
+                ctx.tables().join(", ")
+            );
+            ctx.repl(std::io::stdin().lock(), std::io::stdout())?;
+        }
+    }
+    Ok(())
+}
+
+fn inspect(data: &Path, m: &ArgMatches) -> Result<()> {
+    let path = file_or_cache(data, m)?;
+    let profile = profile(scan(&path)?, *m.get_one::<usize>("bins").unwrap())?;
+    println!(
+        "{}: {} rows, {} columns",
+        path.display(),
+        profile.rows,
+        profile.columns.height()
+    );
+    std::env::set_var("POLARS_FMT_MAX_ROWS", "-1");
+    println!("{}", profile.columns);
+    if m.get_flag("histograms") {
+        for (column, histogram) in &profile.histograms {
+            println!("\n{}\n{}", column, histogram);
+        }
+    }
+    Ok(())
+}
+
+fn check(data: &Path, m: &ArgMatches) -> Result<()> {
+    let path = file_or_cache(data, m)?;
+    let rules = default_rules();
+    let violations = check_rules(scan(&path)?, &rules)?;
+    std::env::set_var("POLARS_FMT_MAX_ROWS", "-1");
+    let summary = violations
+        .clone()
+        .lazy()
+        .group_by([col("rule"), col("severity")])
+        .agg([len().alias("violations")])
+        .sort(["rule"], SortMultipleOptions::default())
+        .collect()?;
+    println!("{}", summary);
+    if let Some(limit) = m.get_one::<usize>("show") {
+        println!("{}", violations.head(Some(*limit)));
+    }
+    let errors = violations
+        .column("severity")?
+        .str()?
+        .into_iter()
+        .filter(|s| *s == Some("error"))
+        .count();
+    if errors > 0 {
+        return Err(format!("{} violations of error-level rules", errors).into());
+    }
+    Ok(())
+}
+
+/// The `file` argument, or the cache, downloading it if needed.
+fn file_or_cache(data: &Path, m: &ArgMatches) -> Result<PathBuf> {
+    match m.get_one::<PathBuf>("file") {
+        Some(file) => Ok(file.clone()),
+        None => {
+            load_data(data)?;
+            Ok(data.to_path_buf())
+        }
+    }
+}
+
+/// Scan a Parquet or IPC file, or read a GeoJSON one.
+fn scan(path: &Path) -> Result<LazyFrame> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("parquet") => Ok(LazyFrame::scan_parquet(path, ScanArgsParquet::default())?),
+        Some("ipc" | "arrow" | "feather") => Ok(LazyFrame::scan_ipc(path, ScanArgsIpc::default())?),
+        Some("json" | "geojson") => Ok(read_geojson(path)?.lazy()),
+        _ => Err(format!("unsupported file type: {}", path.display()).into()),
+    }
+}
@@ -0,0 +1,252 @@
+use crate::crs::{keep_crs, keeps_crs, require_geographic};
+use crate::geometry::{decode_geometries, encode_as, geometry_dtype, LocalPlane};
+use geo::{BooleanOps, ConvexHull, Coord, CoordsIter, Geometry, LineString, MultiPolygon, Polygon};
+use polars::prelude::*;
+
+/// Vertices of the circles rounding buffer corners.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Smallest convex polygon around each geometry.
+pub fn convex_hull(geometry: Expr) -> Expr {
+    geometry.map(
+        |s| {
+            let decoded = decode_geometries(&s)?;
+            let hulls: Vec<Option<Geometry>> = decoded
+                .geometries
+                .iter()
+                .map(|g| g.as_ref().map(|g| Geometry::Polygon(g.convex_hull())))
+                .collect();
+            keep_crs(&s, encode_as(s.name().clone(), &hulls, "Polygon")?).map(Some)
+        },
+        keeps_crs(geometry_dtype("Polygon")),
+    )
+}
+
+/// Grow each geometry by `distance_m` metres as a MultiPolygon, or shrink
+/// polygons when it is negative. Coordinates must be longitude/latitude.
+///
+/// Corners are rounded with 32-sided circles. Buffering a footprint and testing
+/// other footprints against it answers "which buildings are within 50 m".
+pub fn buffer(geometry: Expr, distance_m: f64) -> Expr {
+    require_geographic(geometry, "buffer").map(
+        move |s| {
+            let decoded = decode_geometries(&s)?;
+            let buffered: Vec<Option<Geometry>> = decoded
+                .geometries
+                .iter()
+                .map(|g| {
+                    g.as_ref()
+                        .and_then(|g| buffer_geometry(g, distance_m))
+                        .map(Geometry::MultiPolygon)
+                })
+                .collect();
+            encode_as(s.name().clone(), &buffered, "MultiPolygon").map(Some)
+        },
+        GetOutput::from_type(geometry_dtype("MultiPolygon")),
+    )
+}
+
+fn buffer_geometry(g: &Geometry, distance: f64) -> Option<MultiPolygon> {
+    let plane = LocalPlane::around(g)?;
+    let projected = plane.project(g);
+    let polygons = polygons(&projected);
+    let result = if distance == 0.0 {
+        polygons
+    } else {
+        let strokes = union_all(stroke(&projected, distance.abs()));
+        if distance > 0.0 {
+            polygons.union(&strokes)
+        } else {
+            polygons.difference(&strokes)
+        }
+    };
+    match plane.unproject(&Geometry::MultiPolygon(result)) {
+        Geometry::MultiPolygon(mp) => Some(mp),
+        _ => None,
+    }
+}
+
+fn polygons(g: &Geometry) -> MultiPolygon {
+    match g {
+        Geometry::Polygon(p) => MultiPolygon(vec![p.clone()]),
+        Geometry::MultiPolygon(mp) => mp.clone(),
+        Geometry::Rect(r) => MultiPolygon(vec![r.to_polygon()]),
+        Geometry::Triangle(t) => MultiPolygon(vec![t.to_polygon()]),
+        Geometry::GeometryCollection(gc) => union_all(gc.iter().map(polygons).collect()),
+        _ => MultiPolygon(vec![]),
+    }
+}
+
+/// The area within `radius` of the outline of a projected geometry: a circle
+/// around every vertex and a rectangle along every edge.
+fn stroke(g: &Geometry, radius: f64) -> Vec<MultiPolygon> {
+    let circles = g.coords_iter().map(|c| circle(c, radius));
+    let lines: Vec<_> = outlines(g).iter().flat_map(|ls| ls.lines()).collect();
+    let rects = lines.into_iter().filter_map(|l| {
+        let (dx, dy) = (l.dx(), l.dy());
+        let len = dx.hypot(dy);
+        if len == 0.0 {
+            return None;
+        }
+        let n = Coord {
+            x: -dy / len * radius,
+            y: dx / len * radius,
+        };
+        Some(Polygon::new(
+            LineString::new(vec![l.start - n, l.end - n, l.end + n, l.start + n]),
+            vec![],
+        ))
+    });
+    circles
+        .chain(rects)
+        .map(|p| MultiPolygon(vec![p]))
+        .collect()
+}
+
+fn outlines(g: &Geometry) -> Vec<LineString> {
+    let rings = |p: &Polygon| {
+        std::iter::once(p.exterior().clone())
+            .chain(p.interiors().iter().cloned())
+            .collect::<Vec<_>>()
+    };
+    match g {
+        Geometry::Point(_) | Geometry::MultiPoint(_) => vec![],
+        Geometry::Line(l) => vec![LineString::new(vec![l.start, l.end])],
+        Geometry::LineString(ls) => vec![ls.clone()],
+        Geometry::MultiLineString(mls) => mls.0.clone(),
+        Geometry::Polygon(p) => rings(p),
+        Geometry::MultiPolygon(mp) => mp.iter().flat_map(rings).collect(),
+        Geometry::Rect(r) => rings(&r.to_polygon()),
+        Geometry::Triangle(t) => rings(&t.to_polygon()),
+        Geometry::GeometryCollection(gc) => gc.iter().flat_map(outlines).collect(),
+    }
+}
+
+fn circle(center: Coord, radius: f64) -> Polygon {
+    let ring = (0..CIRCLE_SEGMENTS)
+        .map(|i| {
+            let t = i as f64 / CIRCLE_SEGMENTS as f64 * std::f64::consts::TAU;
+            Coord {
+                x: center.x + radius * t.cos(),
+                y: center.y + radius * t.sin(),
+            }
+        })
+        .collect();
+    Polygon::new(LineString::new(ring), vec![])
+}
+
+/// Union many polygons pairwise, so each overlay stays small.
+fn union_all(mut parts: Vec<MultiPolygon>) -> MultiPolygon {
+    while parts.len() > 1 {
+        parts = parts
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => a.union(b),
+                [a] => a.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    parts.pop().unwrap_or_else(|| MultiPolygon(vec![]))
+}
+
+/// Aggregation that unions the polygonal geometries of each group into one
+/// MultiPolygon, e.g. the combined footprint of an estate:
+/// `.group_by([col("ESTATE")]).agg([unary_union(col("geometry"))])`.
+pub fn unary_union(geometry: Expr) -> Expr {
+    geometry
+        .apply(
+            |s| {
+                let decoded = decode_geometries(&s)?;
+                let parts: Vec<MultiPolygon> =
+                    decoded.geometries.iter().flatten().map(polygons).collect();
+                let union = Geometry::MultiPolygon(union_all(parts));
+                let out = encode_as(s.name().clone(), &[Some(union)], "MultiPolygon")?;
+                keep_crs(&s, out).map(Some)
+            },
+            keeps_crs(geometry_dtype("MultiPolygon")),
+        )
+        .first()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::area;
+    use crate::test_util::{list, polygons, ring, square};
+
+    fn side_m() -> f64 {
+        0.001f64.to_radians() * crate::distance::EARTH_RADIUS_M
+    }
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance * b.abs()
+    }
+
+    /// Area in m² of each row after applying `f` to a Polygon column near the equator.
+    fn areas(rows: Vec<Option<Series>>, f: impl Fn(Expr) -> Expr) -> Vec<Option<f64>> {
+        let df = DataFrame::new(vec![polygons(rows)]).unwrap();
+        let out = df
+            .lazy()
+            .select([area(f(col("geometry")))])
+            .collect()
+            .unwrap();
+        out.column("geometry").unwrap().f64().unwrap().to_vec()
+    }
+
+    #[test]
+    fn convex_hull_fills_in_concave_corners() {
+        let l_shape = ring(&[
+            (0.0, 0.0),
+            (0.002, 0.0),
+            (0.002, 0.001),
+            (0.001, 0.001),
+            (0.001, 0.002),
+            (0.0, 0.002),
+            (0.0, 0.0),
+        ]);
+        let out = areas(vec![Some(list(vec![l_shape])), None], convex_hull);
+        let s = side_m();
+        assert!(close(out[0].unwrap(), 3.5 * s * s, 0.01));
+        assert_eq!(out[1], None);
+    }
+
+    #[test]
+    fn buffer_grows_and_shrinks_in_metres() {
+        let s = side_m();
+        let rows = || vec![Some(list(vec![square(0.0, 0.0, 0.001)]))];
+        let grown = areas(rows(), |g| buffer(g, 10.0))[0].unwrap();
+        // the square, four 10 m strips and four quarter circles
+        let expected = s * s + 4.0 * s * 10.0 + std::f64::consts::PI * 100.0;
+        assert!(close(grown, expected, 0.01), "{} vs {}", grown, expected);
+        let shrunk = areas(rows(), |g| buffer(g, -10.0))[0].unwrap();
+        assert!(close(shrunk, (s - 20.0) * (s - 20.0), 0.01));
+        let same = areas(rows(), |g| buffer(g, 0.0))[0].unwrap();
+        assert!(close(same, s * s, 0.01));
+    }
+
+    #[test]
+    fn unary_union_merges_each_group() {
+        let df = DataFrame::new(vec![
+            Column::new("group".into(), ["a", "a", "b"]),
+            polygons(vec![
+                Some(list(vec![square(0.0, 0.0, 0.001)])),
+                Some(list(vec![square(0.0005, 0.0, 0.001)])),
+                Some(list(vec![square(0.01, 0.01, 0.001)])),
+            ]),
+        ])
+        .unwrap();
+        let out = df
+            .lazy()
+            .group_by([col("group")])
+            .agg([unary_union(col("geometry"))])
+            .with_column(area(col("geometry")).alias("area"))
+            .sort(["group"], SortMultipleOptions::default())
+            .collect()
+            .unwrap();
+        let areas = out.column("area").unwrap().f64().unwrap().to_vec();
+        let s = side_m();
+        assert!(close(areas[0].unwrap(), 1.5 * s * s, 0.01));
+        assert!(close(areas[1].unwrap(), s * s, 0.01));
+    }
+}
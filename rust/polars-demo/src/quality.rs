@@ -0,0 +1,608 @@
+use crate::bucket::{bucket, bucket_label, Binning, Closed};
+use polars::prelude::*;
+use polars::sql::sql_expr;
+use serde::Deserialize;
+use std::fmt;
+
+/// Column statistics from [`profile`].
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub rows: u64,
+    /// One row per column: `column`, `dtype`, `null_count`, `null_rate`, and for
+    /// numeric, text and temporal columns `distinct`, `min` and `max` (as text,
+    /// so every column fits).
+    pub columns: DataFrame,
+    /// Per column, counts of equal-width buckets for numbers or of the most
+    /// common values for text.
+    ///
+    /// Number buckets are a `bins`th of the column's range wide and aligned to
+    /// multiples of that width, so a column can span one bucket more than `bins`.
+    pub histograms: Vec<(String, DataFrame)>,
+}
+
+fn is_comparable(dtype: &DataType) -> bool {
+    dtype.is_numeric() || dtype.is_string() || dtype.is_temporal()
+}
+
+/// Profile every column of `lf`, with histograms of about `bins` buckets or the
+/// `bins` most common values.
+pub fn profile(mut lf: LazyFrame, bins: usize) -> PolarsResult<Profile> {
+    polars_ensure!(bins > 0, ComputeError: "need at least one histogram bin");
+    let schema = lf.collect_schema()?;
+    let mut exprs = vec![len().alias("_rows")];
+    for (i, (name, dtype)) in schema.iter().enumerate() {
+        let c = || col(name.clone());
+        exprs.push(c().null_count().alias(format!("{}_nulls", i)));
+        if is_comparable(dtype) {
+            exprs.push(c().n_unique().alias(format!("{}_distinct", i)));
+            exprs.push(c().min().cast(DataType::String).alias(format!("{}_min", i)));
+            exprs.push(c().max().cast(DataType::String).alias(format!("{}_max", i)));
+        }
+        if dtype.is_numeric() {
+            let c = || c().cast(DataType::Float64);
+            exprs.push(c().min().alias(format!("{}_low", i)));
+            exprs.push(c().max().alias(format!("{}_high", i)));
+        }
+    }
+    let stats = lf.clone().select(exprs).collect()?;
+    let get = |stat: &str| -> PolarsResult<AnyValue> {
+        Ok(match stats.column(stat) {
+            Ok(c) => c.get(0)?.into_static(),
+            Err(_) => AnyValue::Null,
+        })
+    };
+    let rows = get("_rows")?.extract::<u64>().unwrap_or(0);
+
+    let (mut nulls, mut null_rate, mut distinct, mut min, mut max) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut histograms = Vec::new();
+    for (i, (name, dtype)) in schema.iter().enumerate() {
+        let null_count = get(&format!("{}_nulls", i))?.extract::<u64>().unwrap_or(0);
+        nulls.push(null_count);
+        null_rate.push(if rows == 0 {
+            0.0
+        } else {
+            null_count as f64 / rows as f64
+        });
+        distinct.push(get(&format!("{}_distinct", i))?.extract::<u64>());
+        min.push(get(&format!("{}_min", i))?.get_str().map(str::to_string));
+        max.push(get(&format!("{}_max", i))?.get_str().map(str::to_string));
+
+        let histogram = if dtype.is_numeric() {
+            let low = get(&format!("{}_low", i))?.extract::<f64>();
+            let high = get(&format!("{}_high", i))?.extract::<f64>();
+            match (low, high) {
+                (Some(low), Some(high)) => Some(numeric_histogram(
+                    lf.clone(),
+                    name,
+                    (high - low) / bins as f64,
+                )?),
+                _ => None,
+            }
+        } else if dtype.is_string() {
+            Some(top_values(lf.clone(), name, bins)?)
+        } else {
+            None
+        };
+        if let Some(histogram) = histogram {
+            histograms.push((name.to_string(), histogram));
+        }
+    }
+
+    let columns = DataFrame::new(vec![
+        Column::new(
+            "column".into(),
+            schema.iter_names().map(|n| n.as_str()).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "dtype".into(),
+            schema
+                .iter_values()
+                .map(|dt| dt.to_string())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new("null_count".into(), nulls),
+        Column::new("null_rate".into(), null_rate),
+        Column::new("distinct".into(), distinct),
+        Column::new("min".into(), min),
+        Column::new("max".into(), max),
+    ])?;
+    Ok(Profile {
+        rows,
+        columns,
+        histograms,
+    })
+}
+
+fn numeric_histogram(lf: LazyFrame, name: &str, width: f64) -> PolarsResult<DataFrame> {
+    // a constant column still gets one bucket
+    let width = if width > 0.0 { width } else { 1.0 };
+    lf.filter(col(name).is_not_null())
+        .group_by([bucket(col(name), Binning::Width(width), Closed::Left).alias("bucket")])
+        .agg([len().alias("count")])
+        .sort_by_exprs(
+            [col("bucket").struct_().field_by_name("lower")],
+            SortMultipleOptions::default(),
+        )
+        .with_column(bucket_label(col("bucket"), Closed::Left))
+        .collect()
+}
+
+fn top_values(lf: LazyFrame, name: &str, limit: usize) -> PolarsResult<DataFrame> {
+    lf.filter(col(name).is_not_null())
+        .group_by([col(name).alias("value")])
+        .agg([len().alias("count")])
+        .sort(
+            ["count", "value"],
+            SortMultipleOptions::default().with_order_descending_multi([true, false]),
+        )
+        .limit(limit as IdxSize)
+        .collect()
+}
+
+/// What a failed [`QualityRule`] means for a report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// List the violations and carry on.
+    #[default]
+    Warn,
+    /// Fail the report.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warn => write!(f, "warn"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A check every row of the buildings data should pass, selected by `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Rule {
+    /// A SQL expression such as `TOPHEIGHT >= 0`. Rows where it is null pass,
+    /// so pair it with `not_null` if the value is required.
+    Check {
+        expr: String,
+    },
+    NotNull {
+        column: String,
+    },
+    /// No two rows share a non-null value.
+    Unique {
+        column: String,
+    },
+    /// `TOPHEIGHT` over `NUMABOVEGROUNDSTOREYS` is a plausible storey height in
+    /// metres, for rows with a positive height and storey count.
+    StoreysMatchHeight {
+        #[serde(default = "default_min_storey_height")]
+        min_storey_height: f64,
+        #[serde(default = "default_max_storey_height")]
+        max_storey_height: f64,
+    },
+}
+
+fn default_min_storey_height() -> f64 {
+    2.0
+}
+
+fn default_max_storey_height() -> f64 {
+    12.0
+}
+
+/// A [`Rule`] and how seriously to take its violations.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QualityRule {
+    #[serde(flatten)]
+    pub rule: Rule,
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+impl QualityRule {
+    pub fn warn(rule: Rule) -> Self {
+        QualityRule {
+            rule,
+            severity: Severity::Warn,
+        }
+    }
+
+    pub fn error(rule: Rule) -> Self {
+        QualityRule {
+            rule,
+            severity: Severity::Error,
+        }
+    }
+}
+
+/// The checks run when none are configured: non-negative heights and floor
+/// areas and plausible storey heights warn, a missing or repeated `OBJECTID` fails.
+pub fn default_rules() -> Vec<QualityRule> {
+    vec![
+        QualityRule::error(Rule::NotNull {
+            column: "OBJECTID".into(),
+        }),
+        QualityRule::error(Rule::Unique {
+            column: "OBJECTID".into(),
+        }),
+        QualityRule::warn(Rule::Check {
+            expr: "TOPHEIGHT >= 0".into(),
+        }),
+        QualityRule::warn(Rule::Check {
+            expr: "GROSSFLOORAREA >= 0".into(),
+        }),
+        QualityRule::warn(Rule::StoreysMatchHeight {
+            min_storey_height: default_min_storey_height(),
+            max_storey_height: default_max_storey_height(),
+        }),
+    ]
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Check { expr } => write!(f, "{}", expr),
+            Rule::NotNull { column } => write!(f, "{} is not null", column),
+            Rule::Unique { column } => write!(f, "{} is unique", column),
+            Rule::StoreysMatchHeight {
+                min_storey_height,
+                max_storey_height,
+            } => write!(
+                f,
+                "TOPHEIGHT / NUMABOVEGROUNDSTOREYS between {} and {} m",
+                min_storey_height, max_storey_height
+            ),
+        }
+    }
+}
+
+impl Rule {
+    /// Columns the rule reads, besides `OBJECTID`. Unknown for SQL checks.
+    pub fn columns(&self) -> Vec<&str> {
+        match self {
+            Rule::Check { .. } => Vec::new(),
+            Rule::NotNull { column } | Rule::Unique { column } => vec![column.as_str()],
+            Rule::StoreysMatchHeight { .. } => vec!["TOPHEIGHT", "NUMABOVEGROUNDSTOREYS"],
+        }
+    }
+
+    /// The rule's parameters are usable, e.g. its SQL parses.
+    pub fn check(&self) -> PolarsResult<()> {
+        match self {
+            Rule::Check { expr } => sql_expr(expr).map(|_| ()),
+            Rule::StoreysMatchHeight {
+                min_storey_height,
+                max_storey_height,
+            } => {
+                if !(*min_storey_height >= 0.0 && min_storey_height < max_storey_height) {
+                    polars_bail!(InvalidOperation: "rule '{}': min_storey_height must be at least 0 and below max_storey_height", self);
+                }
+                Ok(())
+            }
+            Rule::NotNull { .. } | Rule::Unique { .. } => Ok(()),
+        }
+    }
+
+    /// The failing rows as `OBJECTID` and `detail` columns.
+    fn violations(&self, lf: LazyFrame) -> PolarsResult<LazyFrame> {
+        let (failing, detail) = match self {
+            Rule::Check { expr } => (sql_expr(expr)?.not(), lit(expr.clone())),
+            Rule::NotNull { column } => (
+                col(column.as_str()).is_null(),
+                lit(format!("{} is null", column)),
+            ),
+            Rule::Unique { column } => {
+                let count = len().over([col(column.as_str())]);
+                (
+                    col(column.as_str())
+                        .is_not_null()
+                        .and(count.clone().gt(lit(1))),
+                    concat_str(
+                        [
+                            lit(format!("{} ", column)),
+                            col(column.as_str()).cast(DataType::String),
+                            lit(" appears "),
+                            count.cast(DataType::String),
+                            lit(" times"),
+                        ],
+                        "",
+                        false,
+                    ),
+                )
+            }
+            Rule::StoreysMatchHeight {
+                min_storey_height,
+                max_storey_height,
+            } => {
+                let per_storey = col("TOPHEIGHT") / col("NUMABOVEGROUNDSTOREYS");
+                (
+                    col("TOPHEIGHT")
+                        .gt(lit(0.0))
+                        .and(col("NUMABOVEGROUNDSTOREYS").gt(lit(0.0)))
+                        .and(
+                            per_storey
+                                .clone()
+                                .lt(lit(*min_storey_height))
+                                .or(per_storey.clone().gt(lit(*max_storey_height))),
+                        ),
+                    concat_str(
+                        [
+                            col("TOPHEIGHT").cast(DataType::String),
+                            lit(" m over "),
+                            col("NUMABOVEGROUNDSTOREYS").cast(DataType::String),
+                            lit(" storeys, "),
+                            per_storey.round(1).cast(DataType::String),
+                            lit(" m each"),
+                        ],
+                        "",
+                        false,
+                    ),
+                )
+            }
+        };
+        Ok(lf.filter(failing).select([
+            col("OBJECTID").cast(DataType::Int64),
+            detail.cast(DataType::String).alias("detail"),
+        ]))
+    }
+}
+
+/// Rows failing `rules`: one row per violation with `rule`, `severity`,
+/// `OBJECTID` and `detail`, in rule order.
+pub fn check_rules(lf: LazyFrame, rules: &[QualityRule]) -> PolarsResult<DataFrame> {
+    let checks = rules
+        .iter()
+        .map(|r| {
+            Ok(r.rule.violations(lf.clone())?.select([
+                lit(r.rule.to_string()).alias("rule"),
+                lit(r.severity.to_string()).alias("severity"),
+                col("OBJECTID"),
+                col("detail"),
+            ]))
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    if checks.is_empty() {
+        return Ok(DataFrame::empty_with_schema(&Schema::from_iter([
+            Field::new("rule".into(), DataType::String),
+            Field::new("severity".into(), DataType::String),
+            Field::new("OBJECTID".into(), DataType::Int64),
+            Field::new("detail".into(), DataType::String),
+        ])));
+    }
+    concat(checks, UnionArgs::default())?.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::buildings;
+
+    fn stat<'a>(profile: &'a Profile, column: &str, stat: &str) -> AnyValue<'a> {
+        let row = profile
+            .columns
+            .column("column")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .position(|c| c == Some(column))
+            .unwrap();
+        profile.columns.column(stat).unwrap().get(row).unwrap()
+    }
+
+    fn histogram<'a>(profile: &'a Profile, column: &str) -> &'a DataFrame {
+        &profile
+            .histograms
+            .iter()
+            .find(|(c, _)| c == column)
+            .unwrap()
+            .1
+    }
+
+    fn strings(df: &DataFrame, name: &str) -> Vec<Option<String>> {
+        df.column(name)
+            .unwrap()
+            .cast(&DataType::String)
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.map(String::from))
+            .collect()
+    }
+
+    #[test]
+    fn profile_counts_nulls_and_ranges() {
+        let profile = profile(buildings().lazy(), 4).unwrap();
+        assert_eq!(profile.rows, 5);
+        assert_eq!(profile.columns.height(), 7);
+        assert_eq!(
+            stat(&profile, "TOPHEIGHT", "null_count"),
+            AnyValue::UInt64(1)
+        );
+        assert_eq!(
+            stat(&profile, "TOPHEIGHT", "null_rate"),
+            AnyValue::Float64(0.2)
+        );
+        assert_eq!(stat(&profile, "TOPHEIGHT", "min"), AnyValue::String("-1.0"));
+        assert_eq!(
+            stat(&profile, "TOPHEIGHT", "max"),
+            AnyValue::String("120.0")
+        );
+        assert_eq!(stat(&profile, "OBJECTID", "distinct"), AnyValue::UInt64(5));
+        assert_eq!(
+            stat(&profile, "RECORDCREATIONDATE", "min"),
+            AnyValue::String("2005-01-01T00:00:00Z")
+        );
+        // structs have no order
+        assert_eq!(stat(&profile, "geometry", "distinct"), AnyValue::Null);
+        assert_eq!(stat(&profile, "geometry", "max"), AnyValue::Null);
+    }
+
+    #[test]
+    fn histograms_bucket_numbers_and_rank_text() {
+        let profile = profile(buildings().lazy(), 2).unwrap();
+        let columns: Vec<&str> = profile.histograms.iter().map(|(c, _)| c.as_str()).collect();
+        assert_eq!(
+            columns,
+            [
+                "OBJECTID",
+                "TOPHEIGHT",
+                "NUMABOVEGROUNDSTOREYS",
+                "GROSSFLOORAREA",
+                "OFFICIALBUILDINGNAMEEN",
+                "RECORDCREATIONDATE"
+            ]
+        );
+        // -1 to 120 in buckets 60.5 wide from zero, so three; nulls left out
+        let heights = histogram(&profile, "TOPHEIGHT");
+        assert_eq!(
+            strings(heights, "bucket"),
+            ["[-60.5, 0)", "[0, 60.5)", "[60.5, 121)"].map(|s| Some(s.into()))
+        );
+        assert_eq!(
+            strings(heights, "count"),
+            ["1", "2", "1"].map(|s| Some(s.into()))
+        );
+        // the most common values, ties by value
+        let dates = histogram(&profile, "RECORDCREATIONDATE");
+        assert_eq!(
+            strings(dates, "value"),
+            ["2005-01-01T00:00:00Z", "2005-05-05T00:00:00Z"].map(|s| Some(s.into()))
+        );
+        let names = histogram(&profile, "OFFICIALBUILDINGNAMEEN");
+        assert_eq!(strings(names, "count"), ["1", "1"].map(|s| Some(s.into())));
+    }
+
+    #[test]
+    fn profile_of_constant_and_empty_columns() {
+        let df = df!("a" => [3.0, 3.0], "b" => [None::<f64>, None]).unwrap();
+        let profile = profile(df.clone().lazy(), 10).unwrap();
+        assert_eq!(
+            strings(histogram(&profile, "a"), "bucket"),
+            [Some("[3, 4)".into())]
+        );
+        assert_eq!(stat(&profile, "b", "null_rate"), AnyValue::Float64(1.0));
+        assert!(profile.histograms.iter().all(|(c, _)| c != "b"));
+
+        let empty = super::profile(df.clear().lazy(), 10).unwrap();
+        assert_eq!(empty.rows, 0);
+        assert_eq!(stat(&empty, "a", "null_rate"), AnyValue::Float64(0.0));
+        assert!(empty.histograms.is_empty());
+    }
+
+    #[test]
+    fn default_rules_flag_the_negative_height() {
+        let violations = check_rules(buildings().lazy(), &default_rules()).unwrap();
+        assert_eq!(
+            strings(&violations, "rule"),
+            [Some("TOPHEIGHT >= 0".into())]
+        );
+        assert_eq!(strings(&violations, "severity"), [Some("warn".into())]);
+        assert_eq!(strings(&violations, "OBJECTID"), [Some("3".into())]);
+    }
+
+    #[test]
+    fn each_rule_reports_its_failing_rows() {
+        let df = buildings();
+        // a second copy of building 2, and building 1 given 24 m storeys
+        let mut df = df.vstack(&df.slice(1, 1)).unwrap();
+        df.with_column(Column::new(
+            "NUMABOVEGROUNDSTOREYS".into(),
+            &[
+                Some(5.0),
+                Some(10.0),
+                Some(3.0),
+                None,
+                Some(4.0),
+                Some(10.0),
+            ],
+        ))
+        .unwrap();
+        let rules = [
+            QualityRule::error(Rule::Unique {
+                column: "OBJECTID".into(),
+            }),
+            QualityRule::warn(Rule::NotNull {
+                column: "OFFICIALBUILDINGNAMEEN".into(),
+            }),
+            QualityRule::warn(Rule::StoreysMatchHeight {
+                min_storey_height: 2.0,
+                max_storey_height: 12.0,
+            }),
+        ];
+        let violations = check_rules(df.lazy(), &rules).unwrap();
+        assert_eq!(
+            strings(&violations, "OBJECTID"),
+            ["2", "2", "3", "4", "1"].map(|s| Some(s.into()))
+        );
+        assert_eq!(
+            strings(&violations, "severity"),
+            ["error", "error", "warn", "warn", "warn"].map(|s| Some(s.into()))
+        );
+        assert_eq!(
+            strings(&violations, "detail"),
+            [
+                "OBJECTID 2 appears 2 times",
+                "OBJECTID 2 appears 2 times",
+                "OFFICIALBUILDINGNAMEEN is null",
+                "OFFICIALBUILDINGNAMEEN is null",
+                "120.0 m over 5.0 storeys, 24.0 m each",
+            ]
+            .map(|s| Some(s.into()))
+        );
+        assert_eq!(
+            strings(&violations, "rule")[4],
+            Some("TOPHEIGHT / NUMABOVEGROUNDSTOREYS between 2 and 12 m".into())
+        );
+    }
+
+    #[test]
+    fn no_rules_give_an_empty_table() {
+        let violations = check_rules(buildings().lazy(), &[]).unwrap();
+        assert_eq!(violations.height(), 0);
+        assert_eq!(
+            violations.get_column_names(),
+            ["rule", "severity", "OBJECTID", "detail"]
+        );
+    }
+
+    #[test]
+    fn rules_are_checked_and_parsed() {
+        assert!(Rule::Check {
+            expr: "TOPHEIGHT >=".into()
+        }
+        .check()
+        .is_err());
+        let storeys = |min, max| Rule::StoreysMatchHeight {
+            min_storey_height: min,
+            max_storey_height: max,
+        };
+        assert!(storeys(2.0, 12.0).check().is_ok());
+        assert!(storeys(12.0, 12.0).check().is_err());
+        assert!(storeys(-1.0, 12.0).check().is_err());
+
+        let rule: QualityRule =
+            serde_json::from_str(r#"{"type": "storeys_match_height", "severity": "error"}"#)
+                .unwrap();
+        assert_eq!(rule.severity, Severity::Error);
+        assert_eq!(rule.rule.to_string(), storeys(2.0, 12.0).to_string());
+        let rule: QualityRule =
+            serde_json::from_str(r#"{"type": "not_null", "column": "OBJECTID"}"#).unwrap();
+        assert_eq!(rule.severity, Severity::Warn);
+        assert_eq!(rule.rule.columns(), ["OBJECTID"]);
+        assert!(serde_json::from_str::<QualityRule>(
+            r#"{"type": "not_null", "column": "OBJECTID", "colour": "red"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn zero_bins_are_refused() {
+        assert!(profile(buildings().lazy(), 0).is_err());
+    }
+}
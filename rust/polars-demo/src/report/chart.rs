@@ -0,0 +1,414 @@
+use super::{escape_html, format_number};
+use crate::bucket::Closed;
+use polars::prelude::*;
+use std::fmt::Write;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 320.0;
+const MARGIN_LEFT: f64 = 64.0;
+const MARGIN_RIGHT: f64 = 16.0;
+const MARGIN_TOP: f64 = 32.0;
+const MARGIN_BOTTOM: f64 = 72.0;
+const COLOR: &str = "#4c78a8";
+
+/// A chart rendered to a standalone SVG document.
+///
+/// Charts are drawn from a section's frame when the section runs, so renderers only
+/// need to embed the markup. The SVG has no blank lines, so it can be inlined in
+/// Markdown as a single HTML block.
+#[derive(Debug, Clone)]
+pub struct Chart {
+    pub title: String,
+    pub svg: String,
+}
+
+impl Chart {
+    /// One bar per row: `label` gives the category and `value` the bar height.
+    pub fn bar(
+        title: impl Into<String>,
+        frame: &DataFrame,
+        label: &str,
+        value: &str,
+    ) -> PolarsResult<Self> {
+        let labels = labels(frame.column(label)?)?;
+        let values = values(frame.column(value)?)?;
+        let points: Vec<(String, f64)> = labels
+            .into_iter()
+            .zip(values)
+            .filter_map(|(l, v)| Some((l, v?)))
+            .collect();
+
+        let title = title.into();
+        let y = Scale::new(0.0, max(points.iter().map(|(_, v)| *v)), plot_height());
+        let mut svg = Svg::new(&title, value);
+        svg.y_axis(&y);
+        let slot = plot_width() / points.len().max(1) as f64;
+        for (i, (label, v)) in points.iter().enumerate() {
+            let x = MARGIN_LEFT + slot * i as f64;
+            svg.rect(
+                x + slot * 0.1,
+                y.pos(*v),
+                slot * 0.8,
+                y.pos(0.0) - y.pos(*v),
+                label,
+                *v,
+            );
+            svg.x_label(x + slot / 2.0, label, true);
+        }
+        Ok(svg.finish(title))
+    }
+
+    /// A line through the rows, in frame order, with `x` and `y` both numeric.
+    pub fn line(
+        title: impl Into<String>,
+        frame: &DataFrame,
+        x: &str,
+        y: &str,
+    ) -> PolarsResult<Self> {
+        let xs = values(frame.column(x)?)?;
+        let ys = values(frame.column(y)?)?;
+        let points: Vec<(f64, f64)> = xs
+            .into_iter()
+            .zip(ys)
+            .filter_map(|(x, y)| Some((x?, y?)))
+            .collect();
+
+        let title = title.into();
+        let x_scale = Scale::new(
+            min(points.iter().map(|p| p.0)),
+            max(points.iter().map(|p| p.0)),
+            plot_width(),
+        );
+        let y_scale = Scale::new(0.0, max(points.iter().map(|p| p.1)), plot_height());
+        let mut svg = Svg::new(&title, y);
+        svg.y_axis(&y_scale);
+        svg.x_axis(&x_scale);
+        let path: Vec<String> = points
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", x_scale.pos_x(*x), y_scale.pos(*y)))
+            .collect();
+        let _ = write!(
+            svg.body,
+            r#"<polyline fill="none" stroke="{}" stroke-width="2" points="{}"/>"#,
+            COLOR,
+            path.join(" ")
+        );
+        for (x, y) in &points {
+            let _ = write!(
+                svg.body,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="3" fill="{}"><title>{}: {}</title></circle>"#,
+                x_scale.pos_x(*x),
+                y_scale.pos(*y),
+                COLOR,
+                number(*x),
+                number(*y)
+            );
+        }
+        Ok(svg.finish(title))
+    }
+
+    /// Adjacent bars spanning each bucket, for a `{lower, upper}` struct column from
+    /// [`bucket`](crate::bucket::bucket) and a count column. Rows may be in any order;
+    /// buckets with a null bound are left out. Tooltips show the buckets as
+    /// `closed` labels them.
+    pub fn histogram(
+        title: impl Into<String>,
+        frame: &DataFrame,
+        bucket: &str,
+        count: &str,
+        closed: Closed,
+    ) -> PolarsResult<Self> {
+        let bucket = frame.column(bucket)?.struct_()?.clone();
+        let lower = values(&bucket.field_by_name("lower")?.into_column())?;
+        let upper = values(&bucket.field_by_name("upper")?.into_column())?;
+        let counts = values(frame.column(count)?)?;
+        let mut bars: Vec<(f64, f64, f64)> = lower
+            .into_iter()
+            .zip(upper)
+            .zip(counts)
+            .filter_map(|((l, u), c)| Some((l?, u?, c?)))
+            .filter(|(l, u, _)| l.is_finite() && u.is_finite())
+            .collect();
+        bars.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let title = title.into();
+        let x = Scale::new(
+            min(bars.iter().map(|b| b.0)),
+            max(bars.iter().map(|b| b.1)),
+            plot_width(),
+        );
+        let y = Scale::new(0.0, max(bars.iter().map(|b| b.2)), plot_height());
+        let mut svg = Svg::new(&title, count);
+        svg.y_axis(&y);
+        svg.x_axis(&x);
+        let (open, close) = closed.brackets();
+        for (lower, upper, c) in &bars {
+            let label = format!("{}{}, {}{}", open, number(*lower), number(*upper), close);
+            let left = x.pos_x(*lower);
+            svg.rect(
+                left,
+                y.pos(*c),
+                x.pos_x(*upper) - left,
+                y.pos(0.0) - y.pos(*c),
+                &label,
+                *c,
+            );
+        }
+        Ok(svg.finish(title))
+    }
+}
+
+fn plot_width() -> f64 {
+    WIDTH - MARGIN_LEFT - MARGIN_RIGHT
+}
+
+fn plot_height() -> f64 {
+    HEIGHT - MARGIN_TOP - MARGIN_BOTTOM
+}
+
+fn min(values: impl Iterator<Item = f64>) -> f64 {
+    values.fold(f64::INFINITY, f64::min)
+}
+
+fn max(values: impl Iterator<Item = f64>) -> f64 {
+    values.fold(f64::NEG_INFINITY, f64::max)
+}
+
+fn labels(column: &Column) -> PolarsResult<Vec<String>> {
+    let s = column.cast(&DataType::String)?;
+    Ok(s.str()?
+        .into_iter()
+        .map(|v| v.unwrap_or_default().to_string())
+        .collect())
+}
+
+fn values(column: &Column) -> PolarsResult<Vec<Option<f64>>> {
+    let s = column.cast(&DataType::Float64)?;
+    Ok(s.f64()?
+        .into_iter()
+        .map(|v| v.filter(|v| v.is_finite()))
+        .collect())
+}
+
+/// Compact axis and tooltip numbers, to at most 3 decimals.
+fn number(v: f64) -> String {
+    format_number(v, 3)
+}
+
+/// A linear mapping from data values onto `length` pixels of the plot area,
+/// widened to round tick values.
+struct Scale {
+    min: f64,
+    max: f64,
+    step: f64,
+    length: f64,
+}
+
+impl Scale {
+    fn new(min: f64, max: f64, length: f64) -> Self {
+        let (min, max) = match (min.is_finite(), max.is_finite()) {
+            (true, true) if max > min => (min, max),
+            (true, true) if min == 0.0 => (0.0, 1.0),
+            (true, true) => (min - 0.5, min + 0.5),
+            _ => (0.0, 1.0),
+        };
+        let raw = (max - min) / 5.0;
+        let magnitude = 10f64.powf(raw.log10().floor());
+        let step = [1.0, 2.0, 2.5, 5.0, 10.0]
+            .iter()
+            .map(|m| m * magnitude)
+            .find(|step| *step >= raw)
+            .unwrap_or(raw);
+        Scale {
+            min: (min / step).floor() * step,
+            max: (max / step).ceil() * step,
+            step,
+            length,
+        }
+    }
+
+    fn fraction(&self, v: f64) -> f64 {
+        (v - self.min) / (self.max - self.min)
+    }
+
+    /// Vertical pixel position, growing upwards from the x axis.
+    fn pos(&self, v: f64) -> f64 {
+        MARGIN_TOP + self.length * (1.0 - self.fraction(v))
+    }
+
+    fn pos_x(&self, v: f64) -> f64 {
+        MARGIN_LEFT + self.length * self.fraction(v)
+    }
+
+    fn ticks(&self) -> impl Iterator<Item = f64> + '_ {
+        let n = ((self.max - self.min) / self.step).round() as usize;
+        (0..=n).map(move |i| self.min + self.step * i as f64)
+    }
+}
+
+struct Svg {
+    body: String,
+}
+
+impl Svg {
+    fn new(title: &str, y_label: &str) -> Self {
+        let mut body = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11" role="img" aria-label="{t}">"#,
+            w = WIDTH,
+            h = HEIGHT,
+            t = escape_html(title)
+        );
+        let _ = write!(
+            body,
+            r#"<rect width="100%" height="100%" fill="white"/><text x="{}" y="20" font-size="14" font-weight="bold">{}</text>"#,
+            MARGIN_LEFT,
+            escape_html(title)
+        );
+        let _ = write!(
+            body,
+            r#"<text transform="translate(14 {}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            MARGIN_TOP + plot_height() / 2.0,
+            escape_html(y_label)
+        );
+        Svg { body }
+    }
+
+    fn y_axis(&mut self, scale: &Scale) {
+        for tick in scale.ticks() {
+            let y = scale.pos(tick);
+            let _ = write!(
+                self.body,
+                r##"<line x1="{l}" x2="{r}" y1="{y:.1}" y2="{y:.1}" stroke="#ddd"/><text x="{t}" y="{y:.1}" dy="4" text-anchor="end">{v}</text>"##,
+                l = MARGIN_LEFT,
+                r = WIDTH - MARGIN_RIGHT,
+                t = MARGIN_LEFT - 6.0,
+                y = y,
+                v = number(tick)
+            );
+        }
+    }
+
+    fn x_axis(&mut self, scale: &Scale) {
+        for tick in scale.ticks() {
+            self.x_label(scale.pos_x(tick), &number(tick), false);
+        }
+    }
+
+    fn x_label(&mut self, x: f64, label: &str, rotate: bool) {
+        let y = HEIGHT - MARGIN_BOTTOM + 14.0;
+        if rotate {
+            let _ = write!(
+                self.body,
+                r#"<text transform="translate({:.1} {}) rotate(-35)" text-anchor="end">{}</text>"#,
+                x,
+                y,
+                escape_html(label)
+            );
+        } else {
+            let _ = write!(
+                self.body,
+                r#"<text x="{:.1}" y="{}" text-anchor="middle">{}</text>"#,
+                x,
+                y,
+                escape_html(label)
+            );
+        }
+    }
+
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, label: &str, value: f64) {
+        let _ = write!(
+            self.body,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="white"><title>{}: {}</title></rect>"#,
+            x,
+            y,
+            width.max(0.0),
+            height.max(0.0),
+            COLOR,
+            escape_html(label),
+            number(value)
+        );
+    }
+
+    fn finish(mut self, title: String) -> Chart {
+        let baseline = HEIGHT - MARGIN_BOTTOM;
+        let _ = write!(
+            self.body,
+            r##"<line x1="{}" x2="{}" y1="{b}" y2="{b}" stroke="#333"/></svg>"##,
+            MARGIN_LEFT,
+            WIDTH - MARGIN_RIGHT,
+            b = baseline
+        );
+        Chart {
+            title,
+            svg: self.body,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bucket::{bucket, Binning};
+
+    fn buckets(closed: Closed) -> DataFrame {
+        // out of edge order, as a group-by leaves them
+        df!["v" => [25.0, 5.0, 15.0], "count" => [1u32, 4, 2]]
+            .unwrap()
+            .lazy()
+            .with_column(bucket(col("v"), Binning::Width(10.0), closed).alias("bucket"))
+            .collect()
+            .unwrap()
+    }
+
+    fn tooltips(svg: &str) -> Vec<&str> {
+        svg.split("<title>")
+            .skip(1)
+            .map(|t| &t[..t.find("</title>").unwrap()])
+            .collect()
+    }
+
+    #[test]
+    fn histogram_bars_in_edge_order_labelled_by_closed_side() {
+        let chart =
+            Chart::histogram("t", &buckets(Closed::Left), "bucket", "count", Closed::Left).unwrap();
+        assert_eq!(
+            tooltips(&chart.svg),
+            ["[0, 10): 4", "[10, 20): 2", "[20, 30): 1"]
+        );
+        let chart = Chart::histogram(
+            "t",
+            &buckets(Closed::Right),
+            "bucket",
+            "count",
+            Closed::Right,
+        )
+        .unwrap();
+        assert_eq!(
+            tooltips(&chart.svg),
+            ["(0, 10]: 4", "(10, 20]: 2", "(20, 30]: 1"]
+        );
+    }
+
+    #[test]
+    fn bar_and_line_charts() {
+        let frame = df!["name" => ["a<b", "c"], "n" => [Some(2.0), None]].unwrap();
+        let bar = Chart::bar("Bars & more", &frame, "name", "n").unwrap();
+        assert_eq!(tooltips(&bar.svg), ["a&lt;b: 2"]);
+        assert!(bar.svg.contains(r#"aria-label="Bars &amp; more""#));
+        assert!(!bar.svg.contains("\n\n"));
+
+        let frame = df!["year" => [2001, 2002, 2003], "h" => [1.5, 2.0, 0.25]].unwrap();
+        let line = Chart::line("t", &frame, "year", "h").unwrap();
+        assert_eq!(tooltips(&line.svg), ["2001: 1.5", "2002: 2", "2003: 0.25"]);
+    }
+
+    #[test]
+    fn scales_round_to_ticks() {
+        let scale = Scale::new(0.0, 87.0, 100.0);
+        assert_eq!((scale.min, scale.max, scale.step), (0.0, 100.0, 20.0));
+        let flat = Scale::new(3.0, 3.0, 100.0);
+        assert!(flat.max > flat.min);
+        assert_eq!(number(1234.0), "1234");
+        assert_eq!(number(0.12345), "0.123");
+    }
+}
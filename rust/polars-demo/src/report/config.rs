@@ -0,0 +1,614 @@
+use super::{
+    prepare, DataQuality, Density, DistanceRings, FloorAreaDistribution, HeightByYear,
+    ImplausibleFloorArea, Report, ReportOutput, TallestBuildings,
+};
+use crate::bucket::Binning;
+use crate::grid::Grid;
+use crate::quality::QualityRule;
+use geo::Coord;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+
+/// A report definition read from TOML or YAML.
+///
+/// ```toml
+/// title = "Hong Kong Buildings Report"
+/// reference = { x = 114.1441448, y = 22.2878391 }
+///
+/// [[filters]]
+/// column = "distance_km"
+/// op = "lt"
+/// value = 10
+///
+/// [[sections]]
+/// type = "tallest"
+/// min_height = 150
+/// limit = 20
+/// ```
+///
+/// Omitted fields take the defaults of the matching section; omitting `sections`
+/// gives the sections of [`Report::default`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportConfig {
+    #[serde(default = "default_title")]
+    pub title: String,
+    /// The point `distance_km` is measured from, as longitude/latitude.
+    #[serde(default = "default_reference")]
+    pub reference: Reference,
+    /// Row filters applied to the prepared frame before any section runs.
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default = "default_sections")]
+    pub sections: Vec<SectionConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Reference {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filter {
+    pub column: String,
+    pub op: FilterOp,
+    /// Required by every operator except `is_null` and `is_not_null`.
+    pub value: Option<FilterValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    IsNull,
+    IsNotNull,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FilterValue {
+    Number(f64),
+    Text(String),
+}
+
+/// One section and its parameters, selected by `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SectionConfig {
+    FloorArea {
+        /// Bucket width in m².
+        bucket_width: Option<f64>,
+        limit: Option<u32>,
+    },
+    Tallest {
+        min_height: Option<f64>,
+        min_storeys: Option<f64>,
+        limit: Option<u32>,
+    },
+    HeightByYear {
+        min_count: Option<u32>,
+    },
+    Density {
+        /// Exactly one of `h3_resolution` and `geohash_precision`; H3 by default.
+        h3_resolution: Option<u8>,
+        geohash_precision: Option<usize>,
+        limit: Option<u32>,
+    },
+    DistanceRings {
+        width_km: Option<f64>,
+        limit: Option<u32>,
+    },
+    ImplausibleFloorArea {
+        max_ratio: Option<f64>,
+        min_ratio: Option<f64>,
+        limit: Option<u32>,
+    },
+    DataQuality {
+        /// Defaults to [`crate::quality::default_rules`].
+        rules: Option<Vec<QualityRule>>,
+        limit: Option<u32>,
+    },
+}
+
+impl Default for ReportConfig {
+    /// The sections of [`Report::default`] around SoHo House, without filters.
+    fn default() -> Self {
+        ReportConfig {
+            title: default_title(),
+            reference: default_reference(),
+            filters: Vec::new(),
+            sections: default_sections(),
+        }
+    }
+}
+
+fn default_title() -> String {
+    Report::default().title
+}
+
+fn default_reference() -> Reference {
+    // SoHo House, Sheung Wan
+    Reference {
+        x: 114.1441448,
+        y: 22.2878391,
+    }
+}
+
+fn default_sections() -> Vec<SectionConfig> {
+    vec![
+        SectionConfig::FloorArea {
+            bucket_width: None,
+            limit: None,
+        },
+        SectionConfig::Tallest {
+            min_height: None,
+            min_storeys: None,
+            limit: None,
+        },
+        SectionConfig::HeightByYear { min_count: None },
+        SectionConfig::Density {
+            h3_resolution: None,
+            geohash_precision: None,
+            limit: None,
+        },
+        SectionConfig::ImplausibleFloorArea {
+            max_ratio: None,
+            min_ratio: None,
+            limit: None,
+        },
+    ]
+}
+
+impl ReportConfig {
+    /// Read a definition, choosing TOML or YAML by the `.toml`, `.yaml` or `.yml`
+    /// extension. The result is checked with [`ReportConfig::check`].
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let config: ReportConfig = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&text)?,
+            Some("yaml") | Some("yml") => serde_norway::from_str(&text)?,
+            _ => return Err("report config must be a .toml, .yaml or .yml file".into()),
+        };
+        config.check()?;
+        Ok(config)
+    }
+
+    pub fn reference(&self) -> Coord {
+        Coord {
+            x: self.reference.x,
+            y: self.reference.y,
+        }
+    }
+
+    /// Check values that don't depend on the data, such as positive limits and
+    /// bucket widths. All problems are reported together.
+    pub fn check(&self) -> PolarsResult<()> {
+        let mut problems = Vec::new();
+        let Reference { x, y } = self.reference;
+        if !(-180.0..=180.0).contains(&x) || !(-90.0..=90.0).contains(&y) {
+            problems.push(format!(
+                "reference ({}, {}) is not a longitude/latitude",
+                x, y
+            ));
+        }
+        for filter in &self.filters {
+            let needs_value = !matches!(filter.op, FilterOp::IsNull | FilterOp::IsNotNull);
+            if needs_value != filter.value.is_some() {
+                let expects = if needs_value { "needs a" } else { "takes no" };
+                problems.push(format!(
+                    "filter on '{}': {:?} {} value",
+                    filter.column, filter.op, expects
+                ));
+            }
+        }
+        let mut names = Vec::new();
+        for section in &self.sections {
+            let name = section.name();
+            if names.contains(&name) {
+                problems.push(format!("section '{}' is listed twice", name));
+            }
+            names.push(name);
+            section.check(&mut problems);
+        }
+        if !problems.is_empty() {
+            polars_bail!(InvalidOperation: "invalid report config: {}", problems.join("; "));
+        }
+        Ok(())
+    }
+
+    /// Check the filters and sections against the schema of the [`prepare`]d frame.
+    pub fn validate(&self, schema: &Schema) -> PolarsResult<()> {
+        let mut problems = Vec::new();
+        for filter in &self.filters {
+            let Some(dtype) = schema.get(&filter.column) else {
+                problems.push(format!("filter column '{}' not found", filter.column));
+                continue;
+            };
+            let matches = match &filter.value {
+                Some(FilterValue::Number(_)) => dtype.is_numeric(),
+                Some(FilterValue::Text(_)) => dtype.is_string(),
+                None => true,
+            };
+            if !matches {
+                problems.push(format!(
+                    "filter on '{}' compares a {} column with {:?}",
+                    filter.column,
+                    dtype,
+                    filter.value.as_ref().unwrap()
+                ));
+            }
+        }
+        for missing in self.report().missing_columns(schema) {
+            problems.push(format!("column {} not found", missing));
+        }
+        if !problems.is_empty() {
+            polars_bail!(SchemaMismatch: "report config doesn't fit the data: {}", problems.join("; "));
+        }
+        Ok(())
+    }
+
+    /// The configured [`Report`], without filters.
+    pub fn report(&self) -> Report {
+        self.sections
+            .iter()
+            .fold(Report::new(self.title.clone()), |report, section| {
+                section.add_to(report)
+            })
+    }
+
+    /// [`prepare`] `lf`, validate against its schema, apply the filters and run the
+    /// sections. Nothing is collected if validation fails.
+    pub fn run(&self, lf: LazyFrame) -> PolarsResult<ReportOutput> {
+        self.check()?;
+        let mut lf = prepare(lf, self.reference());
+        let schema = lf.collect_schema()?;
+        self.validate(&schema)?;
+        for filter in &self.filters {
+            lf = lf.filter(filter.expr());
+        }
+        self.report().run(lf)
+    }
+}
+
+impl Filter {
+    pub fn expr(&self) -> Expr {
+        let column = col(self.column.as_str());
+        let value = match &self.value {
+            Some(FilterValue::Number(v)) => lit(*v),
+            Some(FilterValue::Text(v)) => lit(v.clone()),
+            None => lit(NULL),
+        };
+        match self.op {
+            FilterOp::Eq => column.eq(value),
+            FilterOp::Ne => column.neq(value),
+            FilterOp::Gt => column.gt(value),
+            FilterOp::Ge => column.gt_eq(value),
+            FilterOp::Lt => column.lt(value),
+            FilterOp::Le => column.lt_eq(value),
+            FilterOp::IsNull => column.is_null(),
+            FilterOp::IsNotNull => column.is_not_null(),
+        }
+    }
+}
+
+impl SectionConfig {
+    fn name(&self) -> &'static str {
+        match self {
+            SectionConfig::FloorArea { .. } => "floor_area",
+            SectionConfig::Tallest { .. } => "tallest",
+            SectionConfig::HeightByYear { .. } => "height_by_year",
+            SectionConfig::Density { .. } => "density",
+            SectionConfig::DistanceRings { .. } => "distance_rings",
+            SectionConfig::ImplausibleFloorArea { .. } => "implausible_floor_area",
+            SectionConfig::DataQuality { .. } => "data_quality",
+        }
+    }
+
+    fn check(&self, problems: &mut Vec<String>) {
+        let name = self.name();
+        let mut positive = |field: &str, value: Option<f64>| {
+            if value.is_some_and(|v| !(v.is_finite() && v > 0.0)) {
+                problems.push(format!("{}.{} must be positive", name, field));
+            }
+        };
+        match self {
+            SectionConfig::FloorArea {
+                bucket_width,
+                limit,
+            } => {
+                positive("bucket_width", *bucket_width);
+                positive("limit", limit.map(f64::from));
+            }
+            SectionConfig::Tallest { limit, .. } => {
+                positive("limit", limit.map(f64::from));
+            }
+            SectionConfig::ImplausibleFloorArea {
+                max_ratio,
+                min_ratio,
+                limit,
+            } => {
+                positive("limit", limit.map(f64::from));
+                let d = ImplausibleFloorArea::default();
+                if min_ratio.unwrap_or(d.min_ratio) >= max_ratio.unwrap_or(d.max_ratio) {
+                    problems.push(format!("{}.min_ratio must be below max_ratio", name));
+                }
+            }
+            SectionConfig::HeightByYear { .. } => {}
+            SectionConfig::Density {
+                h3_resolution,
+                geohash_precision,
+                limit,
+            } => {
+                positive("limit", limit.map(f64::from));
+                positive("geohash_precision", geohash_precision.map(|p| p as f64));
+                if h3_resolution.is_some() && geohash_precision.is_some() {
+                    problems.push(format!(
+                        "{}: set h3_resolution or geohash_precision, not both",
+                        name
+                    ));
+                }
+                if h3_resolution.is_some_and(|r| r > 15) {
+                    problems.push(format!("{}.h3_resolution must be at most 15", name));
+                }
+                if geohash_precision.is_some_and(|p| p > 12) {
+                    problems.push(format!("{}.geohash_precision must be at most 12", name));
+                }
+            }
+            SectionConfig::DistanceRings { width_km, limit } => {
+                positive("width_km", *width_km);
+                positive("limit", limit.map(f64::from));
+            }
+            SectionConfig::DataQuality { rules, limit } => {
+                positive("limit", limit.map(f64::from));
+                for rule in rules.iter().flatten() {
+                    if let Err(e) = rule.rule.check() {
+                        problems.push(format!("{}: {}", name, e));
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_to(&self, report: Report) -> Report {
+        match *self {
+            SectionConfig::FloorArea {
+                bucket_width,
+                limit,
+            } => {
+                let mut section = FloorAreaDistribution::default();
+                if let Some(width) = bucket_width {
+                    section.binning = Binning::Width(width);
+                }
+                section.limit = limit.unwrap_or(section.limit);
+                report.with_section(section)
+            }
+            SectionConfig::Tallest {
+                min_height,
+                min_storeys,
+                limit,
+            } => {
+                let d = TallestBuildings::default();
+                report.with_section(TallestBuildings {
+                    min_height: min_height.unwrap_or(d.min_height),
+                    min_storeys: min_storeys.unwrap_or(d.min_storeys),
+                    limit: limit.unwrap_or(d.limit),
+                })
+            }
+            SectionConfig::HeightByYear { min_count } => {
+                let d = HeightByYear::default();
+                report.with_section(HeightByYear {
+                    min_count: min_count.unwrap_or(d.min_count),
+                })
+            }
+            SectionConfig::Density {
+                h3_resolution,
+                geohash_precision,
+                limit,
+            } => {
+                let d = Density::default();
+                let grid = match (h3_resolution, geohash_precision) {
+                    (_, Some(precision)) => Grid::Geohash { precision },
+                    (Some(resolution), None) => Grid::H3 { resolution },
+                    (None, None) => d.grid,
+                };
+                report.with_section(Density {
+                    grid,
+                    limit: limit.unwrap_or(d.limit),
+                })
+            }
+            SectionConfig::DistanceRings { width_km, limit } => {
+                let d = DistanceRings::default();
+                report.with_section(DistanceRings {
+                    width_km: width_km.unwrap_or(d.width_km),
+                    limit: limit.unwrap_or(d.limit),
+                })
+            }
+            SectionConfig::ImplausibleFloorArea {
+                max_ratio,
+                min_ratio,
+                limit,
+            } => {
+                let d = ImplausibleFloorArea::default();
+                report.with_section(ImplausibleFloorArea {
+                    max_ratio: max_ratio.unwrap_or(d.max_ratio),
+                    min_ratio: min_ratio.unwrap_or(d.min_ratio),
+                    limit: limit.unwrap_or(d.limit),
+                })
+            }
+            SectionConfig::DataQuality { ref rules, limit } => {
+                let d = DataQuality::default();
+                report.with_section(DataQuality {
+                    rules: rules.clone().unwrap_or(d.rules),
+                    limit: limit.unwrap_or(d.limit),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{buildings, temp_dir};
+
+    const TOML: &str = r#"
+title = "Tall buildings"
+reference = { x = 114.1441448, y = 22.2878391 }
+
+[[filters]]
+column = "distance_km"
+op = "lt"
+value = 5
+
+[[sections]]
+type = "tallest"
+min_height = 20
+min_storeys = 5
+
+[[sections]]
+type = "density"
+geohash_precision = 5
+"#;
+
+    const YAML: &str = r#"
+title: Tall buildings
+reference: { x: 114.1441448, y: 22.2878391 }
+filters:
+  - column: distance_km
+    op: lt
+    value: 5
+sections:
+  - type: tallest
+    min_height: 20
+    min_storeys: 5
+  - type: density
+    geohash_precision: 5
+"#;
+
+    /// Read `text` from a file called `name`, which must be unique across tests.
+    fn read(name: &str, text: &str) -> Result<ReportConfig, Box<dyn std::error::Error>> {
+        let path = temp_dir(&format!("report-config-{}", name)).join(name);
+        std::fs::write(&path, text)?;
+        ReportConfig::from_path(&path)
+    }
+
+    #[test]
+    fn toml_and_yaml_read_the_same() {
+        for config in [
+            read("same.toml", TOML).unwrap(),
+            read("same.yml", YAML).unwrap(),
+        ] {
+            assert_eq!(config.title, "Tall buildings");
+            assert_eq!(config.filters[0].op, FilterOp::Lt);
+            assert!(matches!(config.filters[0].value, Some(FilterValue::Number(v)) if v == 5.0));
+            assert_eq!(config.report().section_names(), ["tallest", "density"]);
+        }
+        assert!(read("config.json", "{}").is_err());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let err = read(
+            "unknown.toml",
+            "[[sections]]\ntype = \"tallest\"\nmin_hieght = 20\n",
+        );
+        assert!(err.unwrap_err().to_string().contains("min_hieght"));
+        assert!(read("unknown.yaml", "sections:\n  - type: skyline\n").is_err());
+    }
+
+    #[test]
+    fn check_reports_every_problem() {
+        let config = ReportConfig {
+            reference: Reference { x: 200.0, y: 0.0 },
+            filters: vec![Filter {
+                column: "TOPHEIGHT".into(),
+                op: FilterOp::IsNull,
+                value: Some(FilterValue::Number(1.0)),
+            }],
+            sections: vec![
+                SectionConfig::Tallest {
+                    min_height: None,
+                    min_storeys: None,
+                    limit: Some(0),
+                },
+                SectionConfig::Density {
+                    h3_resolution: Some(16),
+                    geohash_precision: Some(5),
+                    limit: None,
+                },
+                SectionConfig::HeightByYear { min_count: None },
+                SectionConfig::HeightByYear { min_count: None },
+            ],
+            ..ReportConfig::default()
+        };
+        let message = config.check().unwrap_err().to_string();
+        for problem in [
+            "reference (200, 0)",
+            "IsNull takes no value",
+            "tallest.limit must be positive",
+            "not both",
+            "h3_resolution must be at most 15",
+            "'height_by_year' is listed twice",
+        ] {
+            assert!(message.contains(problem), "{} in {}", problem, message);
+        }
+        ReportConfig::default().check().unwrap();
+    }
+
+    #[test]
+    fn validate_against_the_prepared_schema() {
+        let config = ReportConfig {
+            filters: vec![
+                Filter {
+                    column: "HEIGHT".into(),
+                    op: FilterOp::Gt,
+                    value: Some(FilterValue::Number(1.0)),
+                },
+                Filter {
+                    column: "TOPHEIGHT".into(),
+                    op: FilterOp::Eq,
+                    value: Some(FilterValue::Text("tall".into())),
+                },
+            ],
+            ..ReportConfig::default()
+        };
+        let mut lf = prepare(buildings().lazy(), config.reference());
+        let message = config
+            .validate(&lf.collect_schema().unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            message.contains("filter column 'HEIGHT' not found"),
+            "{}",
+            message
+        );
+        assert!(message.contains("compares a f64 column"), "{}", message);
+    }
+
+    #[test]
+    fn run_applies_the_filters() {
+        let config = read("run.toml", TOML).unwrap();
+        let output = config.run(buildings().lazy()).unwrap();
+        let names = output.sections[0]
+            .frame
+            .column("OFFICIALBUILDINGNAMEEN")
+            .unwrap();
+        assert_eq!(names.str().unwrap().get(1), Some("B House"));
+        let density = &output.sections[1].frame;
+        let counts = density
+            .column("building_count")
+            .unwrap()
+            .cast(&DataType::UInt32);
+        // the block 11 km away is filtered out
+        assert_eq!(counts.unwrap().u32().unwrap().sum(), Some(3));
+    }
+}
@@ -0,0 +1,301 @@
+mod chart;
+mod config;
+mod render;
+mod sections;
+
+pub use chart::Chart;
+pub use config::{Filter, FilterOp, FilterValue, Reference, ReportConfig, SectionConfig};
+pub use render::{Format, JSON_SCHEMA_VERSION};
+pub use sections::{
+    DataQuality, Density, DistanceRings, FloorAreaDistribution, HeightByYear, ImplausibleFloorArea,
+    SnapshotDiff, TallestBuildings,
+};
+
+use crate::centroid::centroid;
+use crate::distance::{distance_to, DistanceMethod};
+use geo::Coord;
+use polars::prelude::*;
+use std::fmt;
+
+/// The result of one report section: a named table plus metadata describing
+/// how it was produced.
+#[derive(Debug, Clone)]
+pub struct SectionOutput {
+    pub name: String,
+    pub title: String,
+    pub frame: DataFrame,
+    /// Ordered key/value notes such as the parameters used.
+    pub metadata: Vec<(String, String)>,
+    /// Charts drawn from the section's data, embedded by the HTML and Markdown renderers.
+    pub charts: Vec<Chart>,
+}
+
+impl SectionOutput {
+    pub fn new(name: impl Into<String>, title: impl Into<String>, frame: DataFrame) -> Self {
+        SectionOutput {
+            name: name.into(),
+            title: title.into(),
+            frame,
+            metadata: Vec::new(),
+            charts: Vec::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.metadata.push((key.into(), value.to_string()));
+        self
+    }
+
+    pub fn with_chart(mut self, chart: Chart) -> Self {
+        self.charts.push(chart);
+        self
+    }
+}
+
+/// One analysis in a [`Report`].
+///
+/// Sections receive the frame from [`prepare`], so they can rely on the
+/// `coords`, `creation_year` and `distance_km` columns as well as the raw data.
+pub trait ReportSection {
+    /// Identifier used to find, remove or reorder the section.
+    fn name(&self) -> &str;
+
+    /// Columns of the prepared frame the section reads, checked before any query runs.
+    fn required_columns(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput>;
+}
+
+/// An ordered list of [`ReportSection`]s run against the same prepared frame.
+pub struct Report {
+    pub title: String,
+    sections: Vec<Box<dyn ReportSection>>,
+}
+
+impl Report {
+    pub fn new(title: impl Into<String>) -> Self {
+        Report {
+            title: title.into(),
+            sections: Vec::new(),
+        }
+    }
+
+    pub fn with_section(mut self, section: impl ReportSection + 'static) -> Self {
+        self.sections.push(Box::new(section));
+        self
+    }
+
+    pub fn push(&mut self, section: Box<dyn ReportSection>) {
+        self.sections.push(section);
+    }
+
+    pub fn insert(&mut self, index: usize, section: Box<dyn ReportSection>) {
+        self.sections.insert(index, section);
+    }
+
+    /// Remove the section called `name`, if there is one.
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn ReportSection>> {
+        let index = self.sections.iter().position(|s| s.name() == name)?;
+        Some(self.sections.remove(index))
+    }
+
+    /// Keep only the named sections, in the order given. Unknown names are ignored.
+    pub fn select(&mut self, names: &[&str]) {
+        let mut sections = std::mem::take(&mut self.sections);
+        for name in names {
+            if let Some(index) = sections.iter().position(|s| s.name() == *name) {
+                self.sections.push(sections.remove(index));
+            }
+        }
+    }
+
+    pub fn section_names(&self) -> Vec<&str> {
+        self.sections.iter().map(|s| s.name()).collect()
+    }
+
+    /// Check that `schema` has every column the sections read.
+    pub fn validate(&self, schema: &Schema) -> PolarsResult<()> {
+        let missing = self.missing_columns(schema);
+        if !missing.is_empty() {
+            polars_bail!(ColumnNotFound: "report needs missing columns: {}", missing.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Required columns absent from `schema`, as `column (section 'name')`.
+    pub fn missing_columns(&self, schema: &Schema) -> Vec<String> {
+        self.sections
+            .iter()
+            .flat_map(|s| {
+                s.required_columns()
+                    .into_iter()
+                    .filter(|c| !schema.contains(c))
+                    .map(move |c| format!("{} (section '{}')", c, s.name()))
+            })
+            .collect()
+    }
+
+    /// Run every section against `lf`, which should come from [`prepare`].
+    pub fn run(&self, lf: LazyFrame) -> PolarsResult<ReportOutput> {
+        let sections = self
+            .sections
+            .iter()
+            .map(|s| s.run(lf.clone()))
+            .collect::<PolarsResult<_>>()?;
+        Ok(ReportOutput {
+            title: self.title.clone(),
+            sections,
+        })
+    }
+}
+
+impl Default for Report {
+    /// The buildings report: floor area distribution, tallest buildings, height
+    /// by creation year, density and implausible floor areas.
+    fn default() -> Self {
+        Report::new("Hong Kong Buildings Report")
+            .with_section(FloorAreaDistribution::default())
+            .with_section(TallestBuildings::default())
+            .with_section(HeightByYear::default())
+            .with_section(Density::default())
+            .with_section(ImplausibleFloorArea::default())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportOutput {
+    pub title: String,
+    pub sections: Vec<SectionOutput>,
+}
+
+impl fmt::Display for ReportOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# {}", self.title)?;
+        for section in &self.sections {
+            writeln!(f, "\n=== {} ===", section.title)?;
+            writeln!(f, "{}", section.frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Add the columns sections rely on: the `coords` centroid, `creation_year`
+/// and `distance_km` from `reference`.
+pub fn prepare(lf: LazyFrame, reference: Coord) -> LazyFrame {
+    let coords = centroid(col("geometry")).alias("coords");
+
+    let creation_year = col("RECORDCREATIONDATE")
+        .str()
+        .strptime(
+            DataType::Datetime(TimeUnit::Milliseconds, None),
+            StrptimeOptions {
+                format: Some("%FT%H:%M:%SZ".into()),
+                strict: false,
+                exact: true,
+                cache: false,
+            },
+            lit("raise"),
+        )
+        .dt()
+        .year()
+        .alias("creation_year");
+
+    lf.with_columns([coords, creation_year]).with_column(
+        (distance_to(
+            col("coords"),
+            reference.x,
+            reference.y,
+            DistanceMethod::Haversine,
+        ) / lit(1000.0))
+        .alias("distance_km"),
+    )
+}
+
+/// `v` to at most `decimals` places, without trailing zeros.
+fn format_number(v: f64, decimals: usize) -> String {
+    if !v.is_finite() {
+        return v.to_string();
+    }
+    let s = format!("{:.*}", decimals, v);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{buildings, ORIGIN};
+
+    #[test]
+    fn sections_can_be_removed_inserted_and_selected() {
+        let mut report = Report::default();
+        assert_eq!(
+            report.section_names(),
+            [
+                "floor_area",
+                "tallest",
+                "height_by_year",
+                "density",
+                "implausible_floor_area"
+            ]
+        );
+        assert!(report.remove("density").is_some());
+        assert!(report.remove("density").is_none());
+        report.insert(0, Box::new(DistanceRings::default()));
+        report.select(&["tallest", "unknown", "distance_rings"]);
+        assert_eq!(report.section_names(), ["tallest", "distance_rings"]);
+    }
+
+    #[test]
+    fn validate_names_missing_columns_and_their_sections() {
+        let report = Report::default();
+        let mut lf = buildings().lazy().drop(["GROSSFLOORAREA"]);
+        let err = report.validate(&lf.collect_schema().unwrap()).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("GROSSFLOORAREA (section 'floor_area')"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("coords (section 'density')"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn runs_sections_in_order_on_the_prepared_frame() {
+        let (x, y) = ORIGIN;
+        let mut lf = prepare(buildings().lazy(), Coord { x, y });
+        let report = Report::default();
+        report.validate(&lf.collect_schema().unwrap()).unwrap();
+        let output = report.run(lf).unwrap();
+        let names: Vec<_> = output.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, report.section_names());
+        assert!(output
+            .to_string()
+            .starts_with("# Hong Kong Buildings Report"));
+    }
+
+    #[test]
+    fn prepare_adds_year_and_distance() {
+        let (x, y) = ORIGIN;
+        let df = prepare(buildings().lazy(), Coord { x, y })
+            .collect()
+            .unwrap();
+        let years = df.column("creation_year").unwrap().i32().unwrap().to_vec();
+        assert_eq!(years[..2], [Some(2005), Some(2010)]);
+        let km = df.column("distance_km").unwrap().f64().unwrap();
+        assert!(km.get(0).unwrap() < 0.1);
+        assert_eq!(km.get(3), None);
+    }
+}
@@ -0,0 +1,445 @@
+use super::{escape_html, format_number, ReportOutput, SectionOutput};
+use polars::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Version of the JSON layout written by [`Format::Json`]. Bumped whenever a
+/// field is renamed, removed or changes type.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// An output format for a [`ReportOutput`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Polars' own table layout, as printed to a terminal.
+    Text,
+    Markdown,
+    /// A single page with inline styles and no external assets.
+    Html,
+    Json,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::Json => "json",
+        }
+    }
+
+    /// The format implied by a file extension, if any.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "txt" | "text" => Ok(Format::Text),
+            "md" | "markdown" => Ok(Format::Markdown),
+            "html" | "htm" => Ok(Format::Html),
+            "json" => Ok(Format::Json),
+            other => Err(format!("unknown report format '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl ReportOutput {
+    pub fn render(&self, format: Format) -> PolarsResult<String> {
+        match format {
+            Format::Text => Ok(self.to_string()),
+            Format::Markdown => markdown(self),
+            Format::Html => html(self),
+            Format::Json => json(self),
+        }
+    }
+
+    /// Render in `format` and write to `path`.
+    pub fn write(&self, path: &Path, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, self.render(format)?)?;
+        Ok(())
+    }
+}
+
+/// Cell text for each row of a column, blank for nulls.
+fn column_text(s: &Series) -> PolarsResult<Vec<String>> {
+    match s.dtype() {
+        DataType::Struct(_) => {
+            let fields = s
+                .struct_()?
+                .fields_as_series()
+                .iter()
+                .map(column_text)
+                .collect::<PolarsResult<Vec<_>>>()?;
+            let nulls = s.is_null();
+            Ok((0..s.len())
+                .map(|i| {
+                    if nulls.get(i) == Some(true) {
+                        return String::new();
+                    }
+                    let parts: Vec<&str> = fields.iter().map(|f| f[i].as_str()).collect();
+                    format!("{{{}}}", parts.join(", "))
+                })
+                .collect())
+        }
+        DataType::List(_) => s
+            .list()?
+            .into_iter()
+            .map(|item| match item {
+                Some(item) => Ok(format!("[{}]", column_text(&item)?.join(", "))),
+                None => Ok(String::new()),
+            })
+            .collect(),
+        DataType::Float32 | DataType::Float64 => {
+            let s = s.cast(&DataType::Float64)?;
+            Ok(s.f64()?
+                .into_iter()
+                .map(|v| v.map(|v| format_number(v, 6)).unwrap_or_default())
+                .collect())
+        }
+        // `Series::iter` needs a single chunk, which concatenated frames don't have
+        _ => Ok(s
+            .rechunk()
+            .iter()
+            .map(|v| match v {
+                AnyValue::Null => String::new(),
+                AnyValue::String(v) => v.to_string(),
+                AnyValue::StringOwned(v) => v.to_string(),
+                v => v.to_string(),
+            })
+            .collect()),
+    }
+}
+
+fn is_numeric(dtype: &DataType) -> bool {
+    dtype.is_numeric()
+}
+
+fn table_text(frame: &DataFrame) -> PolarsResult<Vec<Vec<String>>> {
+    frame
+        .get_columns()
+        .iter()
+        .map(|c| column_text(c.as_materialized_series()))
+        .collect()
+}
+
+fn markdown(report: &ReportOutput) -> PolarsResult<String> {
+    // inline HTML is live in Markdown, so text is HTML-escaped before the table syntax
+    let escape = |s: &str| escape_html(s).replace('|', "\\|").replace('\n', " ");
+    let mut out = format!("# {}\n", escape(&report.title));
+    for section in &report.sections {
+        out += &format!("\n## {}\n\n", escape(&section.title));
+        if !section.metadata.is_empty() {
+            let notes: Vec<String> = section
+                .metadata
+                .iter()
+                .map(|(k, v)| format!("{}: {}", escape(k), escape(v)))
+                .collect();
+            out += &format!("_{}_\n\n", notes.join(" · "));
+        }
+        for chart in &section.charts {
+            // an HTML block, which ends at the first blank line; the SVG has none
+            out += &format!("{}\n\n", chart.svg);
+        }
+        let frame = &section.frame;
+        let columns = table_text(frame)?;
+        let names: Vec<String> = frame.get_column_names().iter().map(|n| escape(n)).collect();
+        out += &format!("| {} |\n", names.join(" | "));
+        let rule: Vec<&str> = frame
+            .dtypes()
+            .iter()
+            .map(|dt| if is_numeric(dt) { "---:" } else { "---" })
+            .collect();
+        out += &format!("| {} |\n", rule.join(" | "));
+        for row in 0..frame.height() {
+            let cells: Vec<String> = columns.iter().map(|c| escape(&c[row])).collect();
+            out += &format!("| {} |\n", cells.join(" | "));
+        }
+    }
+    Ok(out)
+}
+
+const HTML_STYLE: &str = "\
+body { font-family: system-ui, sans-serif; margin: 2rem auto; max-width: 72rem; color: #222; }
+h1 { border-bottom: 2px solid #333; padding-bottom: .3rem; }
+h2 { margin-top: 2.5rem; }
+.meta { color: #666; font-size: .9rem; }
+table { border-collapse: collapse; margin-top: .5rem; font-size: .9rem; }
+th, td { border: 1px solid #ddd; padding: .3rem .6rem; }
+th { background: #f3f3f3; text-align: left; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+tr:nth-child(even) td { background: #fafafa; }
+figure { margin: 1rem 0; }
+figcaption { color: #666; font-size: .85rem; }
+svg { max-width: 100%; height: auto; }
+";
+
+fn html(report: &ReportOutput) -> PolarsResult<String> {
+    let title = escape_html(&report.title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, HTML_STYLE, title
+    );
+    for section in &report.sections {
+        out += &html_section(section)?;
+    }
+    out += "</body>\n</html>\n";
+    Ok(out)
+}
+
+fn html_section(section: &SectionOutput) -> PolarsResult<String> {
+    let mut out = format!(
+        "<section id=\"{}\">\n<h2>{}</h2>\n",
+        escape_html(&section.name),
+        escape_html(&section.title)
+    );
+    if !section.metadata.is_empty() {
+        let notes: Vec<String> = section
+            .metadata
+            .iter()
+            .map(|(k, v)| format!("{}: {}", escape_html(k), escape_html(v)))
+            .collect();
+        out += &format!("<p class=\"meta\">{}</p>\n", notes.join(" · "));
+    }
+    for chart in &section.charts {
+        out += &format!(
+            "<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>\n",
+            chart.svg,
+            escape_html(&chart.title)
+        );
+    }
+    let frame = &section.frame;
+    let columns = table_text(frame)?;
+    let numeric: Vec<bool> = frame.dtypes().iter().map(is_numeric).collect();
+    out += "<table>\n<thead><tr>";
+    for name in frame.get_column_names() {
+        out += &format!("<th>{}</th>", escape_html(name));
+    }
+    out += "</tr></thead>\n<tbody>\n";
+    for row in 0..frame.height() {
+        out += "<tr>";
+        for (column, numeric) in columns.iter().zip(&numeric) {
+            let class = if *numeric { " class=\"num\"" } else { "" };
+            out += &format!("<td{}>{}</td>", class, escape_html(&column[row]));
+        }
+        out += "</tr>\n";
+    }
+    out += "</tbody>\n</table>\n</section>\n";
+    Ok(out)
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    title: &'a str,
+    sections: Vec<JsonSection<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonSection<'a> {
+    name: &'a str,
+    title: &'a str,
+    /// In the order the section added them; keys may repeat.
+    metadata: Vec<JsonMetadata<'a>>,
+    columns: Vec<JsonColumn>,
+    /// Row-major values, in `columns` order.
+    rows: Vec<Vec<Value>>,
+}
+
+#[derive(Serialize)]
+struct JsonMetadata<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonColumn {
+    name: String,
+    dtype: String,
+}
+
+fn json(report: &ReportOutput) -> PolarsResult<String> {
+    let sections = report
+        .sections
+        .iter()
+        .map(|section| {
+            let frame = &section.frame;
+            let columns = frame
+                .get_columns()
+                .iter()
+                .map(|c| column_values(c.as_materialized_series()))
+                .collect::<PolarsResult<Vec<_>>>()?;
+            Ok(JsonSection {
+                name: &section.name,
+                title: &section.title,
+                metadata: section
+                    .metadata
+                    .iter()
+                    .map(|(key, value)| JsonMetadata { key, value })
+                    .collect(),
+                columns: frame
+                    .get_columns()
+                    .iter()
+                    .map(|c| JsonColumn {
+                        name: c.name().to_string(),
+                        dtype: c.dtype().to_string(),
+                    })
+                    .collect(),
+                rows: (0..frame.height())
+                    .map(|row| columns.iter().map(|c| c[row].clone()).collect())
+                    .collect(),
+            })
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    let report = JsonReport {
+        schema_version: JSON_SCHEMA_VERSION,
+        title: &report.title,
+        sections,
+    };
+    serde_json::to_string_pretty(&report).map_err(|e| polars_err!(ComputeError: "{}", e))
+}
+
+/// JSON values for each row of a column: structs become objects, lists arrays.
+fn column_values(s: &Series) -> PolarsResult<Vec<Value>> {
+    match s.dtype() {
+        DataType::Struct(_) => {
+            let fields = s.struct_()?.fields_as_series();
+            let values = fields
+                .iter()
+                .map(column_values)
+                .collect::<PolarsResult<Vec<_>>>()?;
+            let nulls = s.is_null();
+            Ok((0..s.len())
+                .map(|i| {
+                    if nulls.get(i) == Some(true) {
+                        return Value::Null;
+                    }
+                    Value::Object(
+                        fields
+                            .iter()
+                            .zip(&values)
+                            .map(|(f, v)| (f.name().to_string(), v[i].clone()))
+                            .collect(),
+                    )
+                })
+                .collect())
+        }
+        DataType::List(_) => s
+            .list()?
+            .into_iter()
+            .map(|item| match item {
+                Some(item) => Ok(Value::Array(column_values(&item)?)),
+                None => Ok(Value::Null),
+            })
+            .collect(),
+        _ => Ok(s.rechunk().iter().map(any_value_json).collect()),
+    }
+}
+
+fn any_value_json(v: AnyValue) -> Value {
+    match v {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(b) => Value::Bool(b),
+        AnyValue::String(s) => Value::String(s.to_string()),
+        AnyValue::StringOwned(s) => Value::String(s.to_string()),
+        AnyValue::Float32(f) => {
+            serde_json::Number::from_f64(f as f64).map_or(Value::Null, Value::Number)
+        }
+        AnyValue::Float64(f) => serde_json::Number::from_f64(f).map_or(Value::Null, Value::Number),
+        v if v.is_signed_integer() => v.extract::<i64>().map_or(Value::Null, Value::from),
+        v if v.is_unsigned_integer() => v.extract::<u64>().map_or(Value::Null, Value::from),
+        v => Value::String(v.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> ReportOutput {
+        let frame = df![
+            "name" => [Some("<b>A & B</b> | C"), None],
+            "height" => [Some(1.5), Some(120.0)],
+        ]
+        .unwrap();
+        let section = SectionOutput::new("s", "Heights <m>", frame)
+            .with_metadata("zeta", 1)
+            .with_metadata("alpha", "x < y")
+            .with_metadata("zeta", 2);
+        ReportOutput {
+            title: "Report & co".into(),
+            sections: vec![section],
+        }
+    }
+
+    #[test]
+    fn formats_from_names_and_paths() {
+        assert_eq!("MD".parse::<Format>(), Ok(Format::Markdown));
+        assert_eq!(
+            Format::from_path(Path::new("out/r.htm")),
+            Some(Format::Html)
+        );
+        assert_eq!(Format::from_path(Path::new("r")), None);
+        assert!("pdf".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn markdown_escapes_html_and_table_syntax() {
+        let md = report().render(Format::Markdown).unwrap();
+        assert!(md.starts_with("# Report &amp; co\n"));
+        assert!(md.contains("## Heights &lt;m&gt;"));
+        assert!(md.contains("_zeta: 1 · alpha: x &lt; y · zeta: 2_"));
+        assert!(
+            md.contains("| &lt;b&gt;A &amp; B&lt;/b&gt; \\| C | 1.5 |"),
+            "{}",
+            md
+        );
+        assert!(md.contains("|  | 120 |"));
+        assert!(md.contains("| --- | ---: |"));
+    }
+
+    #[test]
+    fn html_escapes_text() {
+        let html = report().render(Format::Html).unwrap();
+        assert!(html.contains("<title>Report &amp; co</title>"));
+        assert!(
+            html.contains("<td>&lt;b&gt;A &amp; B&lt;/b&gt; | C</td><td class=\"num\">1.5</td>")
+        );
+        assert!(!html.contains("<b>"));
+    }
+
+    #[test]
+    fn json_keeps_metadata_order_and_repeats() {
+        let json: Value = serde_json::from_str(&report().render(Format::Json).unwrap()).unwrap();
+        assert_eq!(json["schema_version"], JSON_SCHEMA_VERSION);
+        let section = &json["sections"][0];
+        let keys: Vec<_> = section["metadata"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                format!(
+                    "{}={}",
+                    m["key"].as_str().unwrap(),
+                    m["value"].as_str().unwrap()
+                )
+            })
+            .collect();
+        assert_eq!(keys, ["zeta=1", "alpha=x < y", "zeta=2"]);
+        assert_eq!(section["rows"][1], serde_json::json!([null, 120.0]));
+        assert_eq!(section["columns"][1]["dtype"], "f64");
+    }
+}
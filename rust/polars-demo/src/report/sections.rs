@@ -0,0 +1,657 @@
+use super::{Chart, ReportSection, SectionOutput};
+use crate::bucket::{bucket, bucket_label, Binning, Closed};
+use crate::diff::{diff_snapshots, DiffOptions};
+use crate::grid::{group_by_cell, Grid};
+use crate::quality::{check_rules, default_rules, QualityRule, Severity};
+use crate::shape::area;
+use polars::prelude::*;
+
+/// Number of buildings per gross floor area bucket, most common first.
+#[derive(Debug, Clone)]
+pub struct FloorAreaDistribution {
+    pub binning: Binning,
+    /// Buckets listed; the histogram draws every bucket.
+    pub limit: u32,
+}
+
+impl Default for FloorAreaDistribution {
+    fn default() -> Self {
+        FloorAreaDistribution {
+            binning: Binning::Width(1000.0),
+            limit: 10,
+        }
+    }
+}
+
+impl ReportSection for FloorAreaDistribution {
+    fn name(&self) -> &str {
+        "floor_area"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        vec!["GROSSFLOORAREA", "OBJECTID"]
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let counts = lf
+            .filter(col("GROSSFLOORAREA").is_not_null())
+            .group_by([
+                bucket(col("GROSSFLOORAREA"), self.binning.clone(), Closed::Left)
+                    .alias("GROSSFLOORAREA_bucket"),
+            ])
+            .agg([col("OBJECTID").count().alias("count")])
+            .collect()?;
+        // drawn from the bucket bounds of every bucket, before they are replaced by labels
+        let histogram = Chart::histogram(
+            "Buildings by gross floor area (m²)",
+            &counts,
+            "GROSSFLOORAREA_bucket",
+            "count",
+            Closed::Left,
+        )?;
+        let frame = counts
+            .lazy()
+            .sort_by_exprs(
+                [
+                    col("count"),
+                    col("GROSSFLOORAREA_bucket")
+                        .struct_()
+                        .field_by_name("lower"),
+                ],
+                SortMultipleOptions::default().with_order_descending_multi([true, false]),
+            )
+            .limit(self.limit)
+            .with_column(bucket_label(col("GROSSFLOORAREA_bucket"), Closed::Left))
+            .collect()?;
+        Ok(
+            SectionOutput::new(self.name(), "Floor Area Distribution (in sq meters)", frame)
+                .with_metadata("binning", format!("{:?}", self.binning))
+                .with_metadata("limit", self.limit)
+                .with_chart(histogram),
+        )
+    }
+}
+
+/// The tallest named buildings above a height and storey count.
+#[derive(Debug, Clone)]
+pub struct TallestBuildings {
+    pub min_height: f64,
+    pub min_storeys: f64,
+    pub limit: u32,
+}
+
+impl Default for TallestBuildings {
+    fn default() -> Self {
+        TallestBuildings {
+            min_height: 100.0,
+            min_storeys: 50.0,
+            limit: 10,
+        }
+    }
+}
+
+impl ReportSection for TallestBuildings {
+    fn name(&self) -> &str {
+        "tallest"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        vec![
+            "TOPHEIGHT",
+            "NUMABOVEGROUNDSTOREYS",
+            "OFFICIALBUILDINGNAMEEN",
+        ]
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let frame = lf
+            .filter(col("TOPHEIGHT").gt(lit(self.min_height)))
+            .filter(col("NUMABOVEGROUNDSTOREYS").gt(lit(self.min_storeys)))
+            .select([
+                col("OFFICIALBUILDINGNAMEEN"),
+                col("TOPHEIGHT"),
+                col("NUMABOVEGROUNDSTOREYS"),
+            ])
+            .sort(
+                ["TOPHEIGHT"],
+                SortMultipleOptions::default().with_order_descending(true),
+            )
+            .drop_nulls(Some(vec![
+                col("OFFICIALBUILDINGNAMEEN"),
+                col("NUMABOVEGROUNDSTOREYS"),
+            ]))
+            .limit(self.limit)
+            .collect()?;
+        Ok(SectionOutput::new(
+            self.name(),
+            format!("Tallest Buildings (>{}m)", self.min_height),
+            frame,
+        )
+        .with_metadata("min_height", self.min_height)
+        .with_metadata("min_storeys", self.min_storeys)
+        .with_metadata("limit", self.limit))
+    }
+}
+
+/// Average height per record creation year, for years with enough buildings.
+#[derive(Debug, Clone)]
+pub struct HeightByYear {
+    pub min_count: u32,
+}
+
+impl Default for HeightByYear {
+    fn default() -> Self {
+        HeightByYear { min_count: 100 }
+    }
+}
+
+impl ReportSection for HeightByYear {
+    fn name(&self) -> &str {
+        "height_by_year"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        vec!["TOPHEIGHT", "creation_year", "OBJECTID"]
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let frame = lf
+            .filter(col("TOPHEIGHT").is_not_null())
+            .group_by([col("creation_year")])
+            .agg([
+                col("TOPHEIGHT").mean().alias("avg_height"),
+                col("OBJECTID").count().alias("building_count"),
+            ])
+            .filter(col("building_count").gt(lit(self.min_count))) // filter out years with few buildings
+            .sort(
+                ["creation_year"],
+                SortMultipleOptions::default().with_order_descending(false),
+            )
+            .collect()?;
+        let chart = Chart::line(
+            "Average height (m) by creation year",
+            &frame,
+            "creation_year",
+            "avg_height",
+        )?;
+        Ok(SectionOutput::new(
+            self.name(),
+            "Average Building Height by record creation period",
+            frame,
+        )
+        .with_metadata("min_count", self.min_count)
+        .with_chart(chart))
+    }
+}
+
+/// The densest grid cells by building centroid.
+#[derive(Debug, Clone)]
+pub struct Density {
+    pub grid: Grid,
+    pub limit: u32,
+}
+
+impl Default for Density {
+    fn default() -> Self {
+        Density {
+            grid: Grid::H3 { resolution: 8 },
+            limit: 10,
+        }
+    }
+}
+
+impl ReportSection for Density {
+    fn name(&self) -> &str {
+        "density"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        vec![
+            "coords",
+            "OBJECTID",
+            "TOPHEIGHT",
+            "GROSSFLOORAREA",
+            "distance_km",
+        ]
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let frame = group_by_cell(lf, col("coords"), self.grid)
+            .agg([
+                col("OBJECTID").count().alias("building_count"),
+                col("TOPHEIGHT").mean().alias("avg_height"),
+                col("GROSSFLOORAREA").mean().alias("avg_floor_area"),
+                col("distance_km").mean().alias("avg_distance_km"),
+            ])
+            .with_column(self.grid.center(col("cell")).alias("cell_center"))
+            .sort(
+                ["building_count"],
+                SortMultipleOptions::default().with_order_descending(true),
+            )
+            .limit(self.limit)
+            .collect()?;
+        let grid = match self.grid {
+            Grid::H3 { resolution } => format!("H3 resolution {}", resolution),
+            Grid::Geohash { precision } => format!("geohash precision {}", precision),
+        };
+        let chart = Chart::bar("Buildings per cell", &frame, "cell", "building_count")?;
+        Ok(SectionOutput::new(
+            self.name(),
+            format!("Building Density Analysis ({})", grid),
+            frame,
+        )
+        .with_metadata("grid", grid)
+        .with_metadata("limit", self.limit)
+        .with_chart(chart))
+    }
+}
+
+/// Building counts in rings of equal width around the reference point.
+#[derive(Debug, Clone)]
+pub struct DistanceRings {
+    pub width_km: f64,
+    pub limit: u32,
+}
+
+impl Default for DistanceRings {
+    fn default() -> Self {
+        DistanceRings {
+            width_km: 1.0,
+            limit: 10,
+        }
+    }
+}
+
+impl ReportSection for DistanceRings {
+    fn name(&self) -> &str {
+        "distance_rings"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        vec!["distance_km", "OBJECTID", "TOPHEIGHT"]
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let counts = lf
+            .filter(col("distance_km").is_not_null())
+            .group_by([bucket(
+                col("distance_km"),
+                Binning::Width(self.width_km),
+                Closed::Left,
+            )
+            .alias("distance_km_bucket")])
+            .agg([
+                col("OBJECTID").count().alias("building_count"),
+                col("TOPHEIGHT").mean().alias("avg_height"),
+            ])
+            .sort_by_exprs(
+                [col("distance_km_bucket").struct_().field_by_name("lower")],
+                SortMultipleOptions::default(),
+            )
+            .limit(self.limit)
+            .collect()?;
+        let histogram = Chart::histogram(
+            "Buildings by distance (km)",
+            &counts,
+            "distance_km_bucket",
+            "building_count",
+            Closed::Left,
+        )?;
+        let frame = counts
+            .lazy()
+            .with_column(bucket_label(col("distance_km_bucket"), Closed::Left))
+            .collect()?;
+        Ok(SectionOutput::new(
+            self.name(),
+            format!("Building Density by Distance ({} km rings)", self.width_km),
+            frame,
+        )
+        .with_metadata("width_km", self.width_km)
+        .with_metadata("limit", self.limit)
+        .with_chart(histogram))
+    }
+}
+
+/// Buildings whose recorded gross floor area doesn't fit their footprint and
+/// storey count.
+#[derive(Debug, Clone)]
+pub struct ImplausibleFloorArea {
+    /// Ratios of floor area to footprint × storeys above this are flagged.
+    pub max_ratio: f64,
+    /// Ratios below this are flagged.
+    pub min_ratio: f64,
+    pub limit: u32,
+}
+
+impl Default for ImplausibleFloorArea {
+    fn default() -> Self {
+        // more floor area than the footprint allows, or less than a fifth of it
+        ImplausibleFloorArea {
+            max_ratio: 1.5,
+            min_ratio: 0.2,
+            limit: 10,
+        }
+    }
+}
+
+impl ReportSection for ImplausibleFloorArea {
+    fn name(&self) -> &str {
+        "implausible_floor_area"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        vec![
+            "geometry",
+            "OBJECTID",
+            "OFFICIALBUILDINGNAMEEN",
+            "NUMABOVEGROUNDSTOREYS",
+            "GROSSFLOORAREA",
+        ]
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let frame = lf
+            .with_column(area(col("geometry")).alias("footprint_area"))
+            .with_column(
+                (col("GROSSFLOORAREA") / (col("footprint_area") * col("NUMABOVEGROUNDSTOREYS")))
+                    .alias("floor_area_ratio"),
+            )
+            .filter(
+                col("floor_area_ratio")
+                    .gt(lit(self.max_ratio))
+                    .or(col("floor_area_ratio").lt(lit(self.min_ratio))),
+            )
+            .select([
+                col("OBJECTID"),
+                col("OFFICIALBUILDINGNAMEEN"),
+                col("footprint_area"),
+                col("NUMABOVEGROUNDSTOREYS"),
+                col("GROSSFLOORAREA"),
+                col("floor_area_ratio"),
+            ])
+            .sort(
+                ["floor_area_ratio"],
+                SortMultipleOptions::default().with_order_descending(true),
+            )
+            .limit(self.limit)
+            .collect()?;
+        Ok(
+            SectionOutput::new(self.name(), "Implausible Floor Area Records", frame)
+                .with_metadata("max_ratio", self.max_ratio)
+                .with_metadata("min_ratio", self.min_ratio)
+                .with_metadata("limit", self.limit),
+        )
+    }
+}
+
+/// Buildings added, removed or modified since an earlier snapshot of the data,
+/// see [`diff_snapshots`].
+#[derive(Clone)]
+pub struct SnapshotDiff {
+    pub previous: LazyFrame,
+    pub options: DiffOptions,
+    /// Rows listed; the counts in the metadata and chart cover every change.
+    pub limit: u32,
+}
+
+impl SnapshotDiff {
+    pub fn new(previous: LazyFrame) -> Self {
+        SnapshotDiff {
+            previous,
+            options: DiffOptions::default(),
+            limit: 50,
+        }
+    }
+}
+
+impl ReportSection for SnapshotDiff {
+    fn name(&self) -> &str {
+        "snapshot_diff"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        let mut columns = vec!["OBJECTID", "geometry"];
+        columns.extend(self.options.columns.iter().map(|c| c.as_str()));
+        columns
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let diff = diff_snapshots(self.previous.clone(), lf, &self.options)?;
+        let counts = diff
+            .clone()
+            .lazy()
+            .group_by(["change"])
+            .agg([len().alias("count")])
+            .sort(["change"], SortMultipleOptions::default())
+            .collect()?;
+        let chart = Chart::bar("Changed buildings", &counts, "change", "count")?;
+        let mut output = SectionOutput::new(
+            self.name(),
+            "Changes Since the Previous Snapshot",
+            diff.head(Some(self.limit as usize)),
+        );
+        for change in ["added", "removed", "modified"] {
+            let count = diff
+                .column("change")?
+                .str()?
+                .into_iter()
+                .filter(|c| *c == Some(change))
+                .count();
+            output = output.with_metadata(change, count);
+        }
+        Ok(output
+            .with_metadata("columns", self.options.columns.join(", "))
+            .with_metadata("geometry_tolerance_m", self.options.geometry_tolerance_m)
+            .with_metadata("limit", self.limit)
+            .with_chart(chart))
+    }
+}
+
+/// Rows failing data quality rules. Any violation of a rule with
+/// [`Severity::Error`] fails the section, and so the report; put it first to
+/// fail before the other sections run.
+#[derive(Debug, Clone)]
+pub struct DataQuality {
+    pub rules: Vec<QualityRule>,
+    /// Violations listed; the metadata counts every violation per rule.
+    pub limit: u32,
+}
+
+impl Default for DataQuality {
+    fn default() -> Self {
+        DataQuality {
+            rules: default_rules(),
+            limit: 50,
+        }
+    }
+}
+
+impl ReportSection for DataQuality {
+    fn name(&self) -> &str {
+        "data_quality"
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        let mut columns = vec!["OBJECTID"];
+        columns.extend(self.rules.iter().flat_map(|r| r.rule.columns()));
+        columns
+    }
+
+    fn run(&self, lf: LazyFrame) -> PolarsResult<SectionOutput> {
+        let violations = check_rules(lf, &self.rules)?;
+        let rules = violations.column("rule")?.str()?;
+        let mut output = SectionOutput::new(
+            self.name(),
+            "Data Quality",
+            violations.head(Some(self.limit as usize)),
+        );
+        let mut failed = Vec::new();
+        for r in &self.rules {
+            let name = r.rule.to_string();
+            let count = rules
+                .into_iter()
+                .filter(|v| *v == Some(name.as_str()))
+                .count();
+            if r.severity == Severity::Error && count > 0 {
+                failed.push(format!("'{}' ({} rows)", name, count));
+            }
+            output = output.with_metadata(format!("{} [{}]", name, r.severity), count);
+        }
+        if !failed.is_empty() {
+            polars_bail!(ComputeError: "data quality rules failed: {}", failed.join(", "));
+        }
+        Ok(output.with_metadata("limit", self.limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::prepare;
+    use crate::test_util::{buildings, ORIGIN};
+    use geo::Coord;
+
+    fn run(section: impl ReportSection) -> SectionOutput {
+        let (x, y) = ORIGIN;
+        section
+            .run(prepare(buildings().lazy(), Coord { x, y }))
+            .unwrap()
+    }
+
+    fn strings(frame: &DataFrame, name: &str) -> Vec<Option<String>> {
+        let s = frame.column(name).unwrap().cast(&DataType::String).unwrap();
+        s.str()
+            .unwrap()
+            .into_iter()
+            .map(|v| v.map(String::from))
+            .collect()
+    }
+
+    #[test]
+    fn floor_area_lists_the_most_common_and_draws_every_bucket() {
+        let out = run(FloorAreaDistribution {
+            binning: Binning::Width(10000.0),
+            limit: 1,
+        });
+        assert_eq!(
+            strings(&out.frame, "GROSSFLOORAREA_bucket"),
+            [Some("[0, 10000)".into())]
+        );
+        let svg = &out.charts[0].svg;
+        let bars: Vec<usize> = ["[0, 10000)", "[10000, 20000)", "[20000, 30000)"]
+            .iter()
+            .map(|label| svg.find(&format!("<title>{}: ", label)).unwrap())
+            .collect();
+        assert!(bars.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn tallest_needs_a_name_and_both_thresholds() {
+        let out = run(TallestBuildings::default());
+        assert_eq!(
+            strings(&out.frame, "OFFICIALBUILDINGNAMEEN"),
+            [Some("A Tower".into())]
+        );
+        let out = run(TallestBuildings {
+            min_height: 10.0,
+            min_storeys: 3.0,
+            limit: 10,
+        });
+        let names = strings(&out.frame, "OFFICIALBUILDINGNAMEEN");
+        assert_eq!(
+            names,
+            ["A Tower", "B House", "E Block"].map(|n| Some(n.into()))
+        );
+        assert!(out.metadata.contains(&("min_storeys".into(), "3".into())));
+    }
+
+    #[test]
+    fn height_by_year_averages_each_year() {
+        let out = run(HeightByYear { min_count: 0 });
+        assert_eq!(
+            strings(&out.frame, "creation_year"),
+            [Some("2005".into()), Some("2010".into())]
+        );
+        let avg = out
+            .frame
+            .column("avg_height")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .to_vec();
+        assert_eq!(avg, [Some(67.5), Some(14.5)]);
+        assert_eq!(out.charts.len(), 1);
+        assert_eq!(run(HeightByYear::default()).frame.height(), 0);
+    }
+
+    #[test]
+    fn distance_rings_in_ring_order() {
+        let out = run(DistanceRings::default());
+        let rings = strings(&out.frame, "distance_km_bucket");
+        assert_eq!(rings[0].as_deref(), Some("[0, 1)"));
+        assert_eq!(rings.len(), 4);
+        let counts = out
+            .frame
+            .column("building_count")
+            .unwrap()
+            .cast(&DataType::UInt32);
+        assert!(counts
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .all(|c| c == 1));
+    }
+
+    #[test]
+    fn density_counts_every_building() {
+        let out = run(Density {
+            grid: Grid::Geohash { precision: 4 },
+            limit: 10,
+        });
+        let counts = out
+            .frame
+            .column("building_count")
+            .unwrap()
+            .cast(&DataType::UInt32);
+        assert_eq!(counts.unwrap().u32().unwrap().sum(), Some(5));
+        assert!(out
+            .metadata
+            .contains(&("grid".into(), "geohash precision 4".into())));
+    }
+
+    #[test]
+    fn implausible_floor_area_flags_both_ways() {
+        let out = run(ImplausibleFloorArea::default());
+        let ids = out
+            .frame
+            .column("OBJECTID")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .to_vec();
+        // too much floor area first, then too little
+        assert_eq!(ids, [Some(5), Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn data_quality_counts_warnings_and_fails_on_errors() {
+        let out = run(DataQuality::default());
+        assert_eq!(strings(&out.frame, "rule"), [Some("TOPHEIGHT >= 0".into())]);
+        assert!(out
+            .metadata
+            .contains(&("TOPHEIGHT >= 0 [warn]".into(), "1".into())));
+
+        let (x, y) = ORIGIN;
+        let strict = DataQuality {
+            rules: vec![QualityRule::error(crate::quality::Rule::NotNull {
+                column: "OFFICIALBUILDINGNAMEEN".into(),
+            })],
+            limit: 50,
+        };
+        let err = strict
+            .run(prepare(buildings().lazy(), Coord { x, y }))
+            .unwrap_err();
+        assert!(err.to_string().contains("(2 rows)"), "{}", err);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::crs::require_geographic;
+use crate::geometry::{decode_geometries, map_geometry_f64, LocalPlane};
+use geo::orient::Direction;
+use geo::{BoundingRect, GeodesicArea, Geometry, MinimumRotatedRect, Orient};
+use polars::prelude::*;
+use std::f64::consts::PI;
+
+/// Geodesic area in square metres on the WGS84 ellipsoid, net of holes.
+///
+/// Like the other metrics here it needs longitude/latitude geometries and fails
+/// on a column reprojected with [`crate::crs::to_crs`].
+pub fn area(geometry: Expr) -> Expr {
+    map_geometry_f64(require_geographic(geometry, "area"), |g| {
+        Some(oriented(g).geodesic_area_unsigned())
+    })
+}
+
+/// Geodesic perimeter in metres, including the boundary of any holes.
+pub fn perimeter(geometry: Expr) -> Expr {
+    map_geometry_f64(require_geographic(geometry, "perimeter"), |g| {
+        Some(oriented(g).geodesic_perimeter())
+    })
+}
+
+/// Polsby-Popper compactness `4πA / P²`: 1 for a circle, towards 0 for elongated shapes.
+pub fn compactness(geometry: Expr) -> Expr {
+    map_geometry_f64(require_geographic(geometry, "compactness"), |g| {
+        let (perimeter, area) = oriented(g).geodesic_perimeter_area_unsigned();
+        (perimeter > 0.0).then(|| 4.0 * PI * area / perimeter.powi(2))
+    })
+}
+
+// GeoJSON in the wild does not always follow the right-hand rule, and the
+// geodesic area of a hole with the wrong winding is the rest of the globe.
+fn oriented(g: &Geometry) -> Geometry {
+    match g {
+        Geometry::Polygon(p) => Geometry::Polygon(p.orient(Direction::Default)),
+        Geometry::MultiPolygon(p) => Geometry::MultiPolygon(p.orient(Direction::Default)),
+        Geometry::GeometryCollection(c) => {
+            Geometry::GeometryCollection(c.iter().map(oriented).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+/// Orientation of the long axis of the minimum rotated rectangle, in degrees
+/// clockwise from north in `[0, 180)`.
+pub fn orientation(geometry: Expr) -> Expr {
+    map_geometry_f64(require_geographic(geometry, "orientation"), |g| {
+        let plane = LocalPlane::around(g)?;
+        let rect = plane.project(g).minimum_rotated_rect()?;
+        let (dx, dy) = rect
+            .exterior()
+            .lines()
+            .map(|l| (l.dx(), l.dy()))
+            .max_by(|a, b| a.0.hypot(a.1).total_cmp(&b.0.hypot(b.1)))?;
+        Some(dx.atan2(dy).to_degrees().rem_euclid(180.0))
+    })
+}
+
+/// The `{min_x, min_y, max_x, max_y}` struct type returned by [`bounding_box`].
+pub fn bbox_dtype() -> DataType {
+    DataType::Struct(
+        ["min_x", "min_y", "max_x", "max_y"]
+            .into_iter()
+            .map(|name| Field::new(name.into(), DataType::Float64))
+            .collect(),
+    )
+}
+
+/// Axis-aligned bounding box of each geometry as a `{min_x, min_y, max_x, max_y}` struct.
+pub fn bounding_box(geometry: Expr) -> Expr {
+    geometry.map(
+        |s| {
+            let decoded = decode_geometries(&s)?;
+            let rects: Vec<_> = decoded
+                .geometries
+                .iter()
+                .map(|g| g.as_ref().and_then(|g| g.bounding_rect()))
+                .collect();
+            let field = |name: &str, f: fn(&geo::Rect) -> f64| {
+                rects
+                    .iter()
+                    .map(|r| r.as_ref().map(f))
+                    .collect::<Float64Chunked>()
+                    .with_name(name.into())
+                    .into_series()
+            };
+            let fields = [
+                field("min_x", |r| r.min().x),
+                field("min_y", |r| r.min().y),
+                field("max_x", |r| r.max().x),
+                field("max_y", |r| r.max().y),
+            ];
+            let ca = StructChunked::from_series(s.name().clone(), rects.len(), fields.iter())?;
+            Ok(Some(ca.into_column()))
+        },
+        GetOutput::from_type(bbox_dtype()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{list, polygons, ring, square};
+
+    /// The metric of each row of a Polygon column.
+    fn metric(rows: Vec<Series>, f: fn(Expr) -> Expr) -> Vec<Option<f64>> {
+        let geometry = polygons(rows.into_iter().map(Some).collect());
+        let df = DataFrame::new(vec![geometry])
+            .unwrap()
+            .lazy()
+            .select([f(col("geometry"))])
+            .collect()
+            .unwrap();
+        df.column("geometry").unwrap().f64().unwrap().to_vec()
+    }
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance * b.abs()
+    }
+
+    #[test]
+    fn area_and_perimeter_of_a_square_near_the_equator() {
+        // 0.001° is about 111 m on both axes at the equator
+        let side = 0.001f64.to_radians() * crate::distance::EARTH_RADIUS_M;
+        let rows = || vec![list(vec![square(0.0, 0.0, 0.001)])];
+        assert!(close(metric(rows(), area)[0].unwrap(), side * side, 0.01));
+        assert!(close(
+            metric(rows(), perimeter)[0].unwrap(),
+            4.0 * side,
+            0.01
+        ));
+    }
+
+    #[test]
+    fn holes_are_subtracted_whatever_their_winding() {
+        let outer = square(0.0, 0.0, 0.002);
+        let hole = square(0.0005, 0.0005, 0.001);
+        let reversed = hole.reverse();
+        let full = metric(vec![list(vec![outer.clone()])], area)[0].unwrap();
+        for hole in [hole, reversed] {
+            let net = metric(vec![list(vec![outer.clone(), hole])], area)[0].unwrap();
+            assert!(close(net, full * 0.75, 0.01), "{} of {}", net, full);
+        }
+    }
+
+    #[test]
+    fn a_square_is_less_compact_than_a_circle() {
+        let c = metric(vec![list(vec![square(0.0, 0.0, 0.001)])], compactness)[0].unwrap();
+        assert!(close(c, PI / 4.0, 0.01), "{}", c);
+    }
+
+    #[test]
+    fn orientation_follows_the_long_axis() {
+        let north_south = ring(&[
+            (0.0, 0.0),
+            (0.0001, 0.0),
+            (0.0001, 0.001),
+            (0.0, 0.001),
+            (0.0, 0.0),
+        ]);
+        let east_west = ring(&[
+            (0.0, 0.0),
+            (0.001, 0.0),
+            (0.001, 0.0001),
+            (0.0, 0.0001),
+            (0.0, 0.0),
+        ]);
+        let got = metric(
+            vec![list(vec![north_south]), list(vec![east_west])],
+            orientation,
+        );
+        let got: Vec<f64> = got.into_iter().map(Option::unwrap).collect();
+        assert!(got[0] < 0.5 || got[0] > 179.5, "{:?}", got);
+        assert!((got[1] - 90.0).abs() < 0.5, "{:?}", got);
+    }
+
+    #[test]
+    fn bounding_box_of_each_row() {
+        let geometry = polygons(vec![Some(list(vec![square(1.0, 2.0, 0.5)])), None]);
+        let df = DataFrame::new(vec![geometry])
+            .unwrap()
+            .lazy()
+            .select([bounding_box(col("geometry"))])
+            .unnest(["geometry"])
+            .collect()
+            .unwrap();
+        let get = |name: &str| df.column(name).unwrap().f64().unwrap().to_vec();
+        assert_eq!(get("min_x"), [Some(1.0), None]);
+        assert_eq!(get("min_y"), [Some(2.0), None]);
+        assert_eq!(get("max_x"), [Some(1.5), None]);
+        assert_eq!(get("max_y"), [Some(2.5), None]);
+    }
+}
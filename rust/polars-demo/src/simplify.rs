@@ -0,0 +1,198 @@
+use crate::crs::require_geographic;
+use crate::geometry::{decode_geometries, encode_like, encoded_like_dtype, LocalPlane};
+use crate::validate::self_intersects;
+use geo::{Geometry, LineString, MultiPolygon, Polygon, Simplify, SimplifyVwPreserve};
+use polars::prelude::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SimplifyMethod {
+    /// Ramer-Douglas-Peucker: drops vertices closer than the tolerance to the
+    /// simplified line.
+    #[default]
+    DouglasPeucker,
+    /// Visvalingam-Whyatt: drops vertices whose triangle with their neighbours
+    /// is smaller than the tolerance squared.
+    Visvalingam,
+}
+
+/// Simplify each geometry with a tolerance in metres, e.g. to lighten
+/// footprints for web exports. Coordinates must be longitude/latitude.
+///
+/// Rings are kept valid: Visvalingam uses the topology-preserving variant, and
+/// a Douglas-Peucker ring that would collapse or cross itself is left as it was.
+/// Points are unchanged, undecodable rows become null and the column keeps its
+/// coordinate nesting, with float coordinates.
+pub fn simplify(geometry: Expr, tolerance_m: f64, method: SimplifyMethod) -> Expr {
+    require_geographic(geometry, "simplify").map(
+        move |s| {
+            let decoded = decode_geometries(&s)?;
+            let simplified: Vec<Option<Geometry>> = decoded
+                .geometries
+                .iter()
+                .map(|g| {
+                    g.as_ref()
+                        .map(|g| simplify_geometry(g, tolerance_m, method))
+                })
+                .collect();
+            encode_like(&s, &simplified).map(Some)
+        },
+        GetOutput::map_dtype(|dtype| Ok(encoded_like_dtype(dtype))),
+    )
+}
+
+fn simplify_geometry(g: &Geometry, tolerance: f64, method: SimplifyMethod) -> Geometry {
+    let Some(plane) = LocalPlane::around(g) else {
+        return g.clone();
+    };
+    let simplified = match plane.project(g) {
+        Geometry::LineString(ls) => Geometry::LineString(simplify_line(&ls, tolerance, method)),
+        Geometry::MultiLineString(mls) => Geometry::MultiLineString(
+            mls.iter()
+                .map(|ls| simplify_line(ls, tolerance, method))
+                .collect(),
+        ),
+        Geometry::Polygon(p) => Geometry::Polygon(simplify_polygon(&p, tolerance, method)),
+        Geometry::MultiPolygon(mp) => Geometry::MultiPolygon(MultiPolygon(
+            mp.iter()
+                .map(|p| simplify_polygon(p, tolerance, method))
+                .collect(),
+        )),
+        _ => return g.clone(),
+    };
+    plane.unproject(&simplified)
+}
+
+fn simplify_line(ls: &LineString, tolerance: f64, method: SimplifyMethod) -> LineString {
+    match method {
+        SimplifyMethod::DouglasPeucker => ls.simplify(&tolerance),
+        SimplifyMethod::Visvalingam => ls.simplify_vw_preserve(&(tolerance * tolerance)),
+    }
+}
+
+fn simplify_polygon(p: &Polygon, tolerance: f64, method: SimplifyMethod) -> Polygon {
+    match method {
+        SimplifyMethod::Visvalingam => p.simplify_vw_preserve(&(tolerance * tolerance)),
+        SimplifyMethod::DouglasPeucker => {
+            let keep_valid = |ring: &LineString| {
+                let simplified = ring.simplify(&tolerance);
+                if simplified.0.len() < 4 || self_intersects(&simplified) {
+                    ring.clone()
+                } else {
+                    simplified
+                }
+            };
+            Polygon::new(
+                keep_valid(p.exterior()),
+                p.interiors().iter().map(keep_valid).collect(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::geometry_dtype;
+    use crate::test_util::{geometries, list, polygons, ring, square};
+    use crate::validate::validate_geometries;
+
+    const X: f64 = 114.144;
+    const Y: f64 = 22.288;
+
+    /// A 100 m-ish square with an extra vertex about 0.1 m off its bottom edge.
+    fn bumpy() -> Series {
+        let d = 0.001;
+        ring(&[
+            (X, Y),
+            (X + d / 2.0, Y - 0.000001),
+            (X + d, Y),
+            (X + d, Y + d),
+            (X, Y + d),
+            (X, Y),
+        ])
+    }
+
+    fn vertices(column: &Column) -> Vec<Option<usize>> {
+        use geo::CoordsIter;
+        decode_geometries(column)
+            .unwrap()
+            .geometries
+            .iter()
+            .map(|g| g.as_ref().map(|g| g.coords_count()))
+            .collect()
+    }
+
+    fn run(column: Column, tolerance_m: f64, method: SimplifyMethod) -> Column {
+        let df = DataFrame::new(vec![column]).unwrap();
+        df.lazy()
+            .select([simplify(col("geometry"), tolerance_m, method)])
+            .collect()
+            .unwrap()
+            .column("geometry")
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn drops_vertices_within_the_tolerance() {
+        for method in [SimplifyMethod::DouglasPeucker, SimplifyMethod::Visvalingam] {
+            let column = polygons(vec![Some(list(vec![bumpy()])), None]);
+            assert_eq!(vertices(&column), [Some(6), None]);
+            assert_eq!(vertices(&run(column.clone(), 5.0, method)), [Some(5), None]);
+            assert_eq!(vertices(&run(column, 0.01, method)), [Some(6), None]);
+        }
+    }
+
+    #[test]
+    fn rings_never_collapse() {
+        let tiny = polygons(vec![Some(list(vec![square(X, Y, 0.0001)]))]);
+        for method in [SimplifyMethod::DouglasPeucker, SimplifyMethod::Visvalingam] {
+            let out = run(tiny.clone(), 1000.0, method);
+            assert!(vertices(&out)[0].unwrap() >= 4);
+            assert!(validate_geometries(&out).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn lines_and_points() {
+        let line = ring(&[(X, Y), (X + 0.0005, Y + 0.000001), (X + 0.001, Y)]);
+        let lines = geometries("geometry", vec![Some(("LineString", line))]);
+        let out = run(lines, 5.0, SimplifyMethod::DouglasPeucker);
+        assert_eq!(vertices(&out), [Some(2)]);
+        let points = ring(&[(X, Y), (X + 0.000001, Y)]);
+        let points = geometries("geometry", vec![Some(("MultiPoint", points))]);
+        let out = run(points, 5.0, SimplifyMethod::DouglasPeucker);
+        assert_eq!(vertices(&out), [Some(2)]);
+    }
+
+    #[test]
+    fn declares_the_type_it_returns() {
+        let polygon = polygons(vec![Some(list(vec![bumpy()])), None]);
+        let DataType::Struct(fields) = polygon.dtype() else {
+            unreachable!()
+        };
+        let list_of = |dt| DataType::List(Box::new(dt));
+        let ints = DataType::Struct(vec![
+            fields[0].clone(),
+            Field::new(
+                "coordinates".into(),
+                list_of(list_of(list_of(DataType::Int64))),
+            ),
+        ]);
+        let lf = DataFrame::new(vec![polygon.cast(&ints).unwrap()])
+            .unwrap()
+            .lazy()
+            .select([simplify(
+                col("geometry"),
+                5.0,
+                SimplifyMethod::DouglasPeucker,
+            )]);
+        let declared = lf.clone().collect_schema().unwrap();
+        let df = lf.collect().unwrap();
+        assert_eq!(
+            declared.get("geometry"),
+            Some(df.column("geometry").unwrap().dtype())
+        );
+        assert_eq!(declared.get("geometry"), Some(&geometry_dtype("Polygon")));
+    }
+}
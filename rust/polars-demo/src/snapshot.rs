@@ -0,0 +1,449 @@
+use crate::history::{scan_history, stage_history, update_history};
+use crate::sort::Curve;
+use crate::{cache_layout, download_data, read_geojson, write_parquet};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "manifest.json";
+const HISTORY: &str = "history.parquet";
+
+/// One refresh of the buildings data, stored as a Parquet file that is never
+/// rewritten.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// File name inside the store directory.
+    pub file: String,
+    pub taken_at: DateTime<Utc>,
+    pub rows: usize,
+}
+
+impl Snapshot {
+    pub fn date(&self) -> NaiveDate {
+        self.taken_at.date_naive()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    snapshots: Vec<Snapshot>,
+}
+
+/// Which snapshots [`SnapshotStore::prune`] keeps. The newest snapshot is always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Keep at most this many of the newest snapshots.
+    pub keep_last: Option<usize>,
+    /// Drop snapshots taken more than this many days ago.
+    pub max_age_days: Option<i64>,
+}
+
+impl Default for Retention {
+    /// A year of monthly refreshes.
+    fn default() -> Self {
+        Retention {
+            keep_last: Some(12),
+            max_age_days: Some(366),
+        }
+    }
+}
+
+/// A directory of dated buildings snapshots, cache-formatted Parquet files listed
+/// oldest first in `manifest.json`.
+///
+/// Refreshing adds a snapshot instead of replacing the data, so a report can be
+/// rerun against the snapshot it first used with [`SnapshotStore::as_of`]. Each
+/// snapshot is also folded into `history.parquet`, see [`crate::history`].
+#[derive(Debug)]
+pub struct SnapshotStore {
+    root: PathBuf,
+    manifest: Manifest,
+}
+
+impl SnapshotStore {
+    /// Open the store at `root`, creating the directory if needed.
+    pub fn open(root: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(root)?;
+        let path = root.join(MANIFEST);
+        let manifest = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            Manifest::default()
+        };
+        Ok(SnapshotStore {
+            root: root.to_path_buf(),
+            manifest,
+        })
+    }
+
+    /// Snapshots oldest first.
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.manifest.snapshots
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.manifest.snapshots.last()
+    }
+
+    /// The newest snapshot taken on or before `date`.
+    pub fn as_of(&self, date: NaiveDate) -> Option<&Snapshot> {
+        self.manifest
+            .snapshots
+            .iter()
+            .rev()
+            .find(|s| s.date() <= date)
+    }
+
+    pub fn path(&self, snapshot: &Snapshot) -> PathBuf {
+        self.root.join(&snapshot.file)
+    }
+
+    /// Download the data and store it as a new snapshot.
+    pub fn refresh(&mut self) -> Result<&Snapshot, Box<dyn std::error::Error>> {
+        let json_path = self.root.join("download.geojson");
+        download_data(&json_path)?;
+        let result = self.ingest(&json_path, Utc::now());
+        std::fs::remove_file(&json_path)?;
+        result
+    }
+
+    /// Store a buildings GeoJSON file as the snapshot taken at `taken_at`, which
+    /// must be later than the newest snapshot.
+    pub fn ingest(
+        &mut self,
+        geojson: &Path,
+        taken_at: DateTime<Utc>,
+    ) -> Result<&Snapshot, Box<dyn std::error::Error>> {
+        self.ingest_frame(read_geojson(geojson)?.lazy(), taken_at)
+    }
+
+    /// Store an unnested buildings frame, as [`crate::read_geojson`] gives, as the
+    /// snapshot taken at `taken_at`.
+    pub fn ingest_frame(
+        &mut self,
+        lf: LazyFrame,
+        taken_at: DateTime<Utc>,
+    ) -> Result<&Snapshot, Box<dyn std::error::Error>> {
+        if let Some(latest) = self.latest() {
+            if taken_at <= latest.taken_at {
+                return Err(format!(
+                    "snapshot time {} is not after the newest snapshot ({})",
+                    taken_at, latest.taken_at
+                )
+                .into());
+            }
+        }
+        let file = format!("hk_buildings_{}.parquet", taken_at.format("%Y%m%dT%H%M%SZ"));
+        let path = self.root.join(&file);
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()).into());
+        }
+        let mut df = cache_layout(lf, Some(Curve::Hilbert))?.collect()?;
+        // stage the files and publish the snapshot only once the manifest lists
+        // it, so a failure leaves neither a stray file nor a half-updated history
+        let tmp = path.with_extension("parquet.tmp");
+        let staged = write_parquet(&mut df, &tmp)
+            .and_then(|_| stage_history(&self.history_path(), df.clone().lazy(), taken_at));
+        let history = match staged {
+            Ok(history) => history,
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp);
+                return Err(e);
+            }
+        };
+        self.manifest.snapshots.push(Snapshot {
+            file,
+            taken_at,
+            rows: df.height(),
+        });
+        // the history goes in before the manifest, with the old one kept aside
+        // until the manifest is saved, so the two never disagree
+        let current = self.history_path();
+        let backup = current.with_extension("parquet.bak");
+        let had_history = current.exists();
+        let published = (if had_history {
+            std::fs::rename(&current, &backup)
+        } else {
+            Ok(())
+        })
+        .and_then(|_| std::fs::rename(&history, &current))
+        .and_then(|_| std::fs::rename(&tmp, &path))
+        .map_err(Into::into)
+        .and_then(|_| self.save());
+        if let Err(e) = published {
+            self.manifest.snapshots.pop();
+            for file in [&tmp, &path, &history] {
+                let _ = std::fs::remove_file(file);
+            }
+            if had_history {
+                let _ = std::fs::rename(&backup, &current);
+            } else {
+                let _ = std::fs::remove_file(&current);
+            }
+            return Err(e);
+        }
+        if had_history {
+            let _ = std::fs::remove_file(&backup);
+        }
+        Ok(self.latest().unwrap())
+    }
+
+    /// The history table of every snapshot ingested, including pruned ones.
+    pub fn history(&self) -> PolarsResult<LazyFrame> {
+        scan_history(&self.history_path())
+    }
+
+    pub fn history_path(&self) -> PathBuf {
+        self.root.join(HISTORY)
+    }
+
+    /// Rebuild the history table from the snapshots still in the store, e.g. for
+    /// a store created before histories were kept.
+    pub fn rebuild_history(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.history_path();
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        for snapshot in self.snapshots() {
+            let lf = LazyFrame::scan_parquet(self.path(snapshot), ScanArgsParquet::default())?;
+            update_history(&path, lf, snapshot.taken_at)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the snapshots `retention` doesn't keep, returning them. The history
+    /// table keeps their versions.
+    pub fn prune(
+        &mut self,
+        retention: Retention,
+    ) -> Result<Vec<Snapshot>, Box<dyn std::error::Error>> {
+        let count = self.manifest.snapshots.len();
+        let now = Utc::now();
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.manifest.snapshots)
+            .into_iter()
+            .enumerate()
+            .partition(|(i, s)| {
+                let newer = count - 1 - i;
+                let too_many = retention.keep_last.is_some_and(|n| newer >= n);
+                let too_old = retention
+                    .max_age_days
+                    .is_some_and(|days| s.taken_at < now - Duration::days(days));
+                newer == 0 || !(too_many || too_old)
+            });
+        self.manifest.snapshots = kept.into_iter().map(|(_, s)| s).collect();
+        // the manifest stops listing a snapshot before its file goes
+        self.save()?;
+        let removed: Vec<Snapshot> = removed.into_iter().map(|(_, s)| s).collect();
+        for snapshot in &removed {
+            let path = self.path(snapshot);
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // write then rename, so readers never see a half-written manifest
+        let tmp = self.root.join(format!("{}.tmp", MANIFEST));
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.manifest)?)?;
+        std::fs::rename(tmp, self.root.join(MANIFEST))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_data_as_of;
+    use crate::test_util::{buildings, temp_dir};
+    use chrono::TimeZone;
+
+    fn at(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, 12, 0, 0).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn files(root: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn ingested_snapshots_are_listed_and_found_by_date() {
+        let root = temp_dir("snapshot-ingest");
+        let mut store = SnapshotStore::open(&root).unwrap();
+        store
+            .ingest_frame(buildings().lazy(), at(2024, 1, 1))
+            .unwrap();
+        let smaller = buildings().lazy().filter(col("OBJECTID").neq(lit(5)));
+        store.ingest_frame(smaller, at(2024, 2, 1)).unwrap();
+
+        let store = SnapshotStore::open(&root).unwrap();
+        let rows: Vec<_> = store.snapshots().iter().map(|s| s.rows).collect();
+        assert_eq!(rows, [5, 4]);
+        assert_eq!(store.as_of(date(2023, 12, 31)), None);
+        assert_eq!(
+            store.as_of(date(2024, 1, 31)).unwrap().taken_at,
+            at(2024, 1, 1)
+        );
+        assert_eq!(
+            store.as_of(date(2024, 2, 1)).unwrap().taken_at,
+            at(2024, 2, 1)
+        );
+        assert_eq!(
+            files(&root),
+            [
+                "history.parquet",
+                "hk_buildings_20240101T120000Z.parquet",
+                "hk_buildings_20240201T120000Z.parquet",
+                "manifest.json"
+            ]
+        );
+        let snapshot =
+            LazyFrame::scan_parquet(store.path(store.latest().unwrap()), Default::default())
+                .unwrap()
+                .collect()
+                .unwrap();
+        assert_eq!(snapshot.height(), 4);
+        assert!(snapshot.column(crate::dataset::BBOX_COLUMNS[0]).is_ok());
+
+        let history = store.history().unwrap().collect().unwrap();
+        // five versions, the demolished one closed by the second snapshot
+        assert_eq!(history.height(), 5);
+        assert_eq!(history.column("valid_to").unwrap().null_count(), 4);
+    }
+
+    #[test]
+    fn snapshots_must_be_newer_than_the_latest() {
+        let root = temp_dir("snapshot-order");
+        let mut store = SnapshotStore::open(&root).unwrap();
+        store
+            .ingest_frame(buildings().lazy(), at(2024, 2, 1))
+            .unwrap();
+        for taken_at in [at(2024, 1, 1), at(2024, 2, 1)] {
+            let err = store
+                .ingest_frame(buildings().lazy(), taken_at)
+                .unwrap_err();
+            assert!(
+                err.to_string().contains("is not after the newest snapshot"),
+                "{}",
+                err
+            );
+        }
+        assert_eq!(store.snapshots().len(), 1);
+        assert_eq!(files(&root).len(), 3);
+    }
+
+    #[test]
+    fn failed_ingest_leaves_the_store_unchanged() {
+        let root = temp_dir("snapshot-failed");
+        let mut store = SnapshotStore::open(&root).unwrap();
+        store
+            .ingest_frame(buildings().lazy(), at(2024, 1, 1))
+            .unwrap();
+        let before = files(&root);
+        // a frame whose history update fails: OBJECTID of another type
+        let broken = buildings()
+            .lazy()
+            .with_column(col("OBJECTID").cast(DataType::String));
+        assert!(store.ingest_frame(broken, at(2024, 2, 1)).is_err());
+
+        assert_eq!(files(&root), before);
+        assert_eq!(store.snapshots().len(), 1);
+        assert_eq!(SnapshotStore::open(&root).unwrap().snapshots().len(), 1);
+        // and the next ingest works
+        store
+            .ingest_frame(buildings().lazy(), at(2024, 3, 1))
+            .unwrap();
+        assert_eq!(store.snapshots().len(), 2);
+    }
+
+    #[test]
+    fn failed_manifest_save_restores_the_history() {
+        let root = temp_dir("snapshot-failed-save");
+        let mut store = SnapshotStore::open(&root).unwrap();
+        store
+            .ingest_frame(buildings().lazy(), at(2024, 1, 1))
+            .unwrap();
+        let before = files(&root);
+        let history = store.history().unwrap().collect().unwrap();
+        // a directory where the manifest is staged makes saving it fail
+        let blocker = root.join(format!("{}.tmp", MANIFEST));
+        std::fs::create_dir(&blocker).unwrap();
+        let changed = buildings().lazy().with_column(col("TOPHEIGHT") + lit(1));
+        assert!(store.ingest_frame(changed, at(2024, 2, 1)).is_err());
+        std::fs::remove_dir(&blocker).unwrap();
+
+        assert_eq!(files(&root), before);
+        assert_eq!(store.snapshots().len(), 1);
+        assert!(store
+            .history()
+            .unwrap()
+            .collect()
+            .unwrap()
+            .equals_missing(&history));
+    }
+
+    #[test]
+    fn prune_deletes_files_but_keeps_the_newest_and_the_history() {
+        let root = temp_dir("snapshot-prune");
+        let mut store = SnapshotStore::open(&root).unwrap();
+        let now = Utc::now();
+        for days in [400, 60, 30, 1] {
+            store
+                .ingest_frame(buildings().lazy(), now - Duration::days(days))
+                .unwrap();
+        }
+        let removed = store.prune(Retention::default()).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!store.path(&removed[0]).exists());
+
+        let removed = store
+            .prune(Retention {
+                keep_last: Some(1),
+                max_age_days: Some(0),
+            })
+            .unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(store.snapshots().len(), 1);
+        assert_eq!(store.latest().unwrap().taken_at, now - Duration::days(1));
+        assert_eq!(SnapshotStore::open(&root).unwrap().snapshots().len(), 1);
+        assert_eq!(store.history().unwrap().collect().unwrap().height(), 5);
+    }
+
+    #[test]
+    fn load_data_as_of_never_downloads() {
+        let root = temp_dir("snapshot-load-as-of");
+        let err = load_data_as_of(&root, date(2024, 1, 1)).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("no snapshot on or before 2024-01-01"),
+            "{}",
+            err
+        );
+        assert!(files(&root).is_empty());
+
+        let mut store = SnapshotStore::open(&root).unwrap();
+        store
+            .ingest_frame(buildings().lazy(), at(2024, 1, 1))
+            .unwrap();
+        assert_eq!(
+            load_data_as_of(&root, date(2024, 6, 1)).unwrap(),
+            root.join("hk_buildings_20240101T120000Z.parquet")
+        );
+        let err = load_data_as_of(&root, date(2023, 6, 1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no snapshot on or before 2023-06-01; the oldest is from 2024-01-01"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::centroid::centroid;
+use crate::geometry::point_coords;
+use geo::{Coord, Rect};
+use polars::prelude::*;
+
+/// Bits per axis of the grid the curves are laid over.
+const ORDER: u32 = 16;
+
+/// A space-filling curve for ordering rows so that nearby buildings end up
+/// next to each other, and so in the same Parquet row groups.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Curve {
+    /// Keeps neighbours closer together than Z-order, at a slightly higher cost.
+    #[default]
+    Hilbert,
+    ZOrder,
+}
+
+impl Curve {
+    fn index(self, x: u32, y: u32) -> u64 {
+        match self {
+            Curve::Hilbert => hilbert_index(x, y),
+            Curve::ZOrder => z_index(x, y),
+        }
+    }
+}
+
+impl std::str::FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hilbert" => Ok(Curve::Hilbert),
+            "z-order" | "zorder" | "z" => Ok(Curve::ZOrder),
+            other => Err(format!("unknown curve '{}'", other)),
+        }
+    }
+}
+
+fn hilbert_index(mut x: u32, mut y: u32) -> u64 {
+    let n = 1u32 << ORDER;
+    let mut d = 0u64;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = x & s > 0;
+        let ry = y & s > 0;
+        d += (s as u64) * (s as u64) * ((3 * rx as u64) ^ ry as u64);
+        // rotate the quadrant so the curve stays continuous
+        if !ry {
+            if rx {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+fn z_index(x: u32, y: u32) -> u64 {
+    (0..ORDER).fold(0u64, |d, bit| {
+        d | (((x >> bit) & 1) as u64) << (2 * bit) | (((y >> bit) & 1) as u64) << (2 * bit + 1)
+    })
+}
+
+/// Position of each `{x, y}` point along `curve`, laid over `extent`, as
+/// [`point_extent`] gives it for the whole column.
+///
+/// Null and non-finite points get a null key; points outside `extent` are clamped to it.
+pub fn curve_key(point: Expr, curve: Curve, extent: Rect) -> Expr {
+    point.map(
+        move |s| {
+            let cells = ((1u32 << ORDER) - 1) as f64;
+            let cell = |v: f64, min: f64, max: f64| {
+                if max > min {
+                    (((v - min) / (max - min)).clamp(0.0, 1.0) * cells) as u32
+                } else {
+                    0
+                }
+            };
+            let (min, max) = (extent.min(), extent.max());
+            let out: UInt64Chunked = point_coords(&s)?
+                .iter()
+                .map(|p| {
+                    p.filter(|c| c.x.is_finite() && c.y.is_finite())
+                        .map(|c| curve.index(cell(c.x, min.x, max.x), cell(c.y, min.y, max.y)))
+                })
+                .collect();
+            Ok(Some(out.with_name(s.name().clone()).into_column()))
+        },
+        GetOutput::from_type(DataType::UInt64),
+    )
+}
+
+/// The extent of the finite `{x, y}` points of `point` over all of `lf`, or `None`
+/// when there are none.
+pub fn point_extent(lf: LazyFrame, point: Expr) -> PolarsResult<Option<Rect>> {
+    let axis = |name: &str| {
+        let v = point.clone().struct_().field_by_name(name);
+        let v = v.clone().filter(v.is_finite());
+        [
+            v.clone().min().alias(format!("min_{}", name)),
+            v.max().alias(format!("max_{}", name)),
+        ]
+    };
+    let df = lf
+        .select(axis("x").into_iter().chain(axis("y")).collect::<Vec<_>>())
+        .collect()?;
+    let get = |name: &str| -> PolarsResult<Option<f64>> {
+        Ok(df.column(name)?.cast(&DataType::Float64)?.f64()?.get(0))
+    };
+    Ok(
+        match (get("min_x")?, get("min_y")?, get("max_x")?, get("max_y")?) {
+            (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) => Some(Rect::new(
+                Coord { x: min_x, y: min_y },
+                Coord { x: max_x, y: max_y },
+            )),
+            _ => None,
+        },
+    )
+}
+
+/// Sort rows along `curve` by the centroids of their `geometry`.
+///
+/// The extent of the centroids is computed first, in its own pass over `lf`, so
+/// every row is keyed on the same grid however the sort is executed.
+pub fn sort_spatially(lf: LazyFrame, curve: Curve) -> PolarsResult<LazyFrame> {
+    let point = centroid(col("geometry"));
+    let Some(extent) = point_extent(lf.clone(), point.clone())? else {
+        return Ok(lf);
+    };
+    Ok(lf
+        .with_column(curve_key(point, curve, extent).alias("_curve_key"))
+        .sort(
+            ["_curve_key"],
+            SortMultipleOptions::default().with_nulls_last(true),
+        )
+        .drop(["_curve_key"]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::points_column;
+    use crate::test_util::{list, polygons, square};
+
+    fn points(coords: &[Option<(f64, f64)>]) -> DataFrame {
+        let coords: Vec<_> = coords
+            .iter()
+            .map(|c| c.map(|(x, y)| Coord { x, y }))
+            .collect();
+        DataFrame::new(vec![points_column("point".into(), &coords).unwrap()]).unwrap()
+    }
+
+    fn keys(df: &DataFrame, curve: Curve, extent: Rect) -> Vec<Option<u64>> {
+        let out = df
+            .clone()
+            .lazy()
+            .select([curve_key(col("point"), curve, extent)])
+            .collect()
+            .unwrap();
+        out.column("point").unwrap().u64().unwrap().to_vec()
+    }
+
+    fn unit() -> Rect {
+        Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 1.0, y: 1.0 })
+    }
+
+    /// The centres of the four quadrants of the unit square, in Hilbert order.
+    const QUADRANTS: [(f64, f64); 4] = [(0.25, 0.25), (0.25, 0.75), (0.75, 0.75), (0.75, 0.25)];
+
+    #[test]
+    fn hilbert_visits_quadrants_in_order() {
+        let df = points(&QUADRANTS.map(Some));
+        let quarter = 1u64 << (2 * ORDER - 2);
+        for (q, key) in keys(&df, Curve::Hilbert, unit()).into_iter().enumerate() {
+            assert_eq!(key.unwrap() / quarter, q as u64);
+        }
+        let mut first: Vec<_> = [(0, 0), (0, 1), (1, 1), (1, 0)]
+            .map(|(x, y)| hilbert_index(x, y))
+            .to_vec();
+        first.sort();
+        assert_eq!(first, [0, 1, 2, 3]);
+        assert_eq!(hilbert_index((1 << ORDER) - 1, 0), (1 << (2 * ORDER)) - 1);
+    }
+
+    #[test]
+    fn z_order_interleaves_x_and_y() {
+        assert_eq!(z_index(0b011, 0b101), 0b100111);
+        assert_eq!(z_index(1, 0), 1);
+        assert_eq!(z_index(0, 1), 2);
+        let df = points(&[
+            Some((0.25, 0.25)),
+            Some((0.75, 0.25)),
+            Some((0.25, 0.75)),
+            Some((0.75, 0.75)),
+        ]);
+        let quarter = 1u64 << (2 * ORDER - 2);
+        for (q, key) in keys(&df, Curve::ZOrder, unit()).into_iter().enumerate() {
+            assert_eq!(key.unwrap() / quarter, q as u64);
+        }
+    }
+
+    #[test]
+    fn corners_of_the_extent_and_outliers() {
+        let df = points(&[Some((0.0, 0.0)), Some((1.0, 1.0)), Some((2.0, -1.0)), None]);
+        let last = (1u64 << (2 * ORDER)) - 1;
+        let max = (1u32 << ORDER) - 1;
+        assert_eq!(
+            keys(&df, Curve::ZOrder, unit()),
+            [Some(0), Some(last), Some(z_index(max, 0)), None]
+        );
+        assert_eq!(keys(&df, Curve::Hilbert, unit())[2], Some(last));
+    }
+
+    #[test]
+    fn extent_skips_missing_and_non_finite_points() {
+        let df = points(&[
+            Some((3.0, -1.0)),
+            None,
+            Some((f64::NAN, 9.0)),
+            Some((5.0, 2.0)),
+        ]);
+        let extent = point_extent(df.lazy(), col("point")).unwrap().unwrap();
+        assert_eq!(extent.min(), Coord { x: 3.0, y: -1.0 });
+        assert_eq!(extent.max(), Coord { x: 5.0, y: 2.0 });
+        assert!(point_extent(points(&[None]).lazy(), col("point"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn sort_spatially_follows_the_curve() {
+        // footprints in the four quadrants of a 2 x 2 box, listed out of Hilbert order
+        let corners = [
+            Some((1.0, 0.0)),
+            None,
+            Some((0.0, 1.0)),
+            Some((0.0, 0.0)),
+            Some((1.0, 1.0)),
+        ];
+        let rows: Vec<_> = corners
+            .iter()
+            .map(|c| c.map(|(x, y)| list(vec![square(x, y, 1.0)])))
+            .collect();
+        let ids = Column::new("id".into(), [0i32, 1, 2, 3, 4]);
+        let df = DataFrame::new(vec![ids, polygons(rows)]).unwrap();
+        let sorted = |curve| {
+            let out = sort_spatially(df.clone().lazy(), curve)
+                .unwrap()
+                .collect()
+                .unwrap();
+            out.column("id")
+                .unwrap()
+                .i32()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(Curve::Hilbert), [3, 2, 4, 0, 1]);
+        assert_eq!(sorted(Curve::ZOrder), [3, 0, 2, 4, 1]);
+    }
+}
@@ -0,0 +1,689 @@
+use crate::centroid::centroid;
+use crate::dataset::Dataset;
+use crate::distance::{pairwise_distance, DistanceMethod};
+use crate::geometry::{decode_geometries, encode_as, geometry_dtype, point_coords, point_dtype};
+use crate::load_data;
+use crate::shape::area;
+use geo::{Coord, Geometry, Intersects, Point, Rect, Relate};
+use polars::prelude::*;
+use polars::sql::{FunctionRegistry, SQLContext};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::keywords::Keyword;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A spatial SQL function: its arity, result type and implementation.
+struct Function {
+    name: &'static str,
+    args: &'static str,
+    doc: &'static str,
+    output: fn() -> DataType,
+    eval: Eval,
+}
+
+enum Eval {
+    /// Built from the library's expressions over the arguments.
+    Expr(fn(Vec<Expr>) -> Expr),
+    /// Row by row over the argument columns, broadcast to a common length.
+    Columns(fn(&[Column]) -> PolarsResult<Column>),
+}
+
+impl Function {
+    fn arity(&self) -> usize {
+        if self.args.is_empty() {
+            0
+        } else {
+            self.args.split(',').count()
+        }
+    }
+}
+
+const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "st_point",
+        args: "x, y",
+        doc: "Point struct {x, y} from longitude and latitude",
+        output: point_dtype,
+        eval: Eval::Expr(|a| as_struct(vec![a[0].clone().alias("x"), a[1].clone().alias("y")])),
+    },
+    Function {
+        name: "st_x",
+        args: "point",
+        doc: "Longitude of a point",
+        output: float,
+        eval: Eval::Expr(|a| a[0].clone().struct_().field_by_name("x")),
+    },
+    Function {
+        name: "st_y",
+        args: "point",
+        doc: "Latitude of a point",
+        output: float,
+        eval: Eval::Expr(|a| a[0].clone().struct_().field_by_name("y")),
+    },
+    Function {
+        name: "st_centroid",
+        args: "geometry",
+        doc: "Centroid of a geometry as a point",
+        output: point_dtype,
+        eval: Eval::Expr(|a| centroid(a[0].clone())),
+    },
+    Function {
+        name: "centroid_x",
+        args: "geometry",
+        doc: "Longitude of a geometry's centroid",
+        output: float,
+        eval: Eval::Expr(|a| centroid(a[0].clone()).struct_().field_by_name("x")),
+    },
+    Function {
+        name: "centroid_y",
+        args: "geometry",
+        doc: "Latitude of a geometry's centroid",
+        output: float,
+        eval: Eval::Expr(|a| centroid(a[0].clone()).struct_().field_by_name("y")),
+    },
+    Function {
+        name: "st_area",
+        args: "geometry",
+        doc: "Geodesic area in m²",
+        output: float,
+        eval: Eval::Expr(|a| area(a[0].clone())),
+    },
+    Function {
+        name: "st_distance",
+        args: "a, b",
+        doc: "Haversine distance in metres between two points",
+        output: float,
+        eval: Eval::Expr(|a| {
+            pairwise_distance(a[0].clone(), a[1].clone(), DistanceMethod::Haversine)
+        }),
+    },
+    Function {
+        name: "st_dwithin",
+        args: "a, b, metres",
+        doc: "Whether two points are at most `metres` apart",
+        output: boolean,
+        eval: Eval::Expr(|a| {
+            pairwise_distance(a[0].clone(), a[1].clone(), DistanceMethod::Haversine)
+                .lt_eq(a[2].clone())
+        }),
+    },
+    Function {
+        name: "st_makeenvelope",
+        args: "min_x, min_y, max_x, max_y",
+        doc: "Rectangular polygon geometry",
+        output: polygon,
+        eval: Eval::Columns(make_envelope),
+    },
+    Function {
+        name: "st_within",
+        args: "a, b",
+        doc: "Whether geometry or point `a` lies within `b`",
+        output: boolean,
+        eval: Eval::Columns(|a| relate(a, |a, b| b.relate(a).is_contains())),
+    },
+    Function {
+        name: "st_intersects",
+        args: "a, b",
+        doc: "Whether geometries or points `a` and `b` intersect",
+        output: boolean,
+        eval: Eval::Columns(|a| relate(a, |a, b| a.intersects(b))),
+    },
+];
+
+fn float() -> DataType {
+    DataType::Float64
+}
+
+fn boolean() -> DataType {
+    DataType::Boolean
+}
+
+fn polygon() -> DataType {
+    geometry_dtype("Polygon")
+}
+
+/// The names and descriptions of the spatial SQL functions, as `(signature, doc)`.
+pub fn functions() -> Vec<(String, &'static str)> {
+    FUNCTIONS
+        .iter()
+        .map(|f| (format!("{}({})", f.name.to_uppercase(), f.args), f.doc))
+        .collect()
+}
+
+/// Repeat length-1 columns (SQL literals) to the longest argument.
+fn broadcast(columns: &[Column]) -> Vec<Column> {
+    let len = columns.iter().map(|c| c.len()).max().unwrap_or(0);
+    columns
+        .iter()
+        .map(|c| {
+            if c.len() == 1 && len != 1 {
+                c.new_from_index(0, len)
+            } else {
+                c.clone()
+            }
+        })
+        .collect()
+}
+
+/// Geometries of a GeoJSON `geometry` column, or points of an `{x, y}` column.
+fn geometries(column: &Column) -> PolarsResult<Vec<Option<Geometry>>> {
+    let is_point = matches!(
+        column.dtype(),
+        DataType::Struct(fields) if fields.iter().any(|f| f.name() == "x")
+    );
+    if is_point {
+        Ok(point_coords(column)?
+            .into_iter()
+            .map(|c| c.map(|c| Geometry::Point(Point(c))))
+            .collect())
+    } else {
+        Ok(decode_geometries(column)?.geometries)
+    }
+}
+
+fn relate(args: &[Column], f: fn(&Geometry, &Geometry) -> bool) -> PolarsResult<Column> {
+    let a = geometries(&args[0])?;
+    let b = geometries(&args[1])?;
+    let out: BooleanChunked = a
+        .iter()
+        .zip(&b)
+        .map(|(a, b)| Some(f(a.as_ref()?, b.as_ref()?)))
+        .collect();
+    Ok(out.into_column())
+}
+
+fn make_envelope(args: &[Column]) -> PolarsResult<Column> {
+    let values = args
+        .iter()
+        .map(|c| Ok(c.cast(&DataType::Float64)?.f64()?.clone()))
+        .collect::<PolarsResult<Vec<_>>>()?;
+    let envelopes: Vec<Option<Geometry>> = (0..values[0].len())
+        .map(|i| {
+            let [min_x, min_y, max_x, max_y] = [0, 1, 2, 3].map(|j| values[j].get(i));
+            let rect = Rect::new(
+                Coord {
+                    x: min_x?,
+                    y: min_y?,
+                },
+                Coord {
+                    x: max_x?,
+                    y: max_y?,
+                },
+            );
+            Some(Geometry::Polygon(rect.to_polygon()))
+        })
+        .collect();
+    encode_as("envelope".into(), &envelopes, "Polygon")
+}
+
+fn call(function: &Function, args: &mut [Column]) -> PolarsResult<Option<Column>> {
+    // arguments past the function's arity only carry columns for polars' check
+    let args = broadcast(&args[..function.arity().min(args.len())]);
+    let name = args
+        .first()
+        .map(|c| c.name().clone())
+        .unwrap_or_else(|| function.name.into());
+    let out = match function.eval {
+        Eval::Columns(f) => f(&args)?,
+        Eval::Expr(f) => {
+            let names: Vec<PlSmallStr> =
+                (0..args.len()).map(|i| format!("_{}", i).into()).collect();
+            let frame = DataFrame::new(
+                args.into_iter()
+                    .zip(&names)
+                    .map(|(c, n)| c.with_name(n.clone()))
+                    .collect(),
+            )?;
+            let expr = f(names.iter().map(|n| col(n.clone())).collect());
+            frame
+                .lazy()
+                .select([expr.alias(name.clone())])
+                .collect()?
+                .get_columns()[0]
+                .clone()
+        }
+    };
+    Ok(Some(out.with_name(name)))
+}
+
+/// A spatial function call in the query being planned, with the input fields
+/// polars checks its arguments against.
+struct Call {
+    function: &'static Function,
+    fields: Vec<Field>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Columns of every registered table, for the argument types.
+    columns: Schema,
+    /// The spatial function calls of the query being planned, in order.
+    calls: Vec<Call>,
+}
+
+fn read(state: &RwLock<State>) -> PolarsResult<RwLockReadGuard<'_, State>> {
+    state
+        .read()
+        .map_err(|_| polars_err!(ComputeError: "the SQL context was poisoned by an earlier panic"))
+}
+
+fn write(state: &RwLock<State>) -> PolarsResult<RwLockWriteGuard<'_, State>> {
+    state
+        .write()
+        .map_err(|_| polars_err!(ComputeError: "the SQL context was poisoned by an earlier panic"))
+}
+
+/// The spatial function and call number of a UDF name: `st_area` or, as written
+/// by [`number_calls`], `st_area__0`.
+fn parse_name(name: &str) -> Option<(&'static Function, Option<usize>)> {
+    let (name, call) = match name.rsplit_once("__") {
+        Some((name, n)) => (name, Some(n.parse().ok()?)),
+        None => (name, None),
+    };
+    let function = FUNCTIONS.iter().find(|f| f.name == name)?;
+    Some((function, call))
+}
+
+/// The spatial functions as polars SQL UDFs.
+///
+/// Polars checks a UDF call by resolving its arguments against the UDF's input
+/// fields, so those must name every column the arguments use, nested calls
+/// included. [`SpatialSql::query`] gives each call its own UDF and fields by
+/// renaming it, and passes the columns past the function's arity as extra
+/// arguments, which the function ignores.
+struct Registry {
+    state: Arc<RwLock<State>>,
+}
+
+impl FunctionRegistry for Registry {
+    fn register(&mut self, name: &str, _fun: UserDefinedFunction) -> PolarsResult<()> {
+        polars_bail!(InvalidOperation: "can't register '{}': only the spatial functions are available", name)
+    }
+
+    fn get_udf(&self, name: &str) -> PolarsResult<Option<UserDefinedFunction>> {
+        let Some((function, number)) = parse_name(name) else {
+            return Ok(None);
+        };
+        let fields = match number {
+            Some(n) => match read(&self.state)?.calls.get(n) {
+                Some(call) if call.function.name == function.name => call.fields.clone(),
+                _ => polars_bail!(SQLInterface: "unknown call '{}'", name),
+            },
+            None => (0..function.arity())
+                .map(|i| Field::new(format!("_arg{}", i).into(), DataType::Null))
+                .collect(),
+        };
+        let output = (function.output)();
+        Ok(Some(UserDefinedFunction::new(
+            function.name.into(),
+            fields,
+            GetOutput::from_type(output),
+            move |args: &mut [Column]| call(function, args),
+        )))
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        parse_name(name).is_some()
+    }
+}
+
+/// Rename each spatial function call in `sql` to `<function>__<n>`, and collect
+/// the identifiers inside its arguments as its input fields, skipping keywords,
+/// function names and table qualifiers unless they name a known column. A call
+/// using more columns than the function has arguments gets the rest appended.
+fn number_calls(sql: &str, columns: &Schema) -> PolarsResult<(String, Vec<Call>)> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql)
+        .tokenize()
+        .map_err(|e| polars_err!(SQLSyntax: "{}", e))?;
+    let code: Vec<&Token> = tokens
+        .iter()
+        .filter(|t| !matches!(t, Token::Whitespace(_)))
+        .collect();
+    let mut calls = Vec::new();
+    // by position in `code`: the new names of the calls, and the columns to
+    // append before their closing parentheses
+    let mut renamed: HashMap<usize, String> = HashMap::new();
+    let mut appended: HashMap<usize, Vec<String>> = HashMap::new();
+    for (start, token) in code.iter().enumerate() {
+        let Token::Word(word) = token else { continue };
+        let Some(function) = FUNCTIONS
+            .iter()
+            .find(|f| f.name == word.value.to_lowercase())
+        else {
+            continue;
+        };
+        if word.quote_style.is_some() || code.get(start + 1) != Some(&&Token::LParen) {
+            continue;
+        }
+        let mut ids: Vec<String> = Vec::new();
+        let mut depth = 0;
+        let mut end = None;
+        for (i, token) in code.iter().enumerate().skip(start + 1) {
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(i);
+                        break;
+                    }
+                }
+                Token::Word(w) => {
+                    let next = code.get(i + 1);
+                    let known = columns.contains(&w.value);
+                    let plain = w.quote_style.is_some() || w.keyword == Keyword::NoKeyword;
+                    let call_or_qualifier = matches!(next, Some(Token::LParen | Token::Period));
+                    if (known || (plain && !call_or_qualifier)) && !ids.contains(&w.value) {
+                        ids.push(w.value.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        // unbalanced parentheses are left for the SQL parser to report
+        let Some(end) = end else { continue };
+        let arity = function.arity();
+        if ids.len() > arity {
+            appended.insert(end, ids[arity..].to_vec());
+        }
+        let mut fields: Vec<Field> = ids
+            .iter()
+            .map(|id| {
+                let dtype = columns.get(id).cloned().unwrap_or(DataType::Null);
+                Field::new(id.as_str().into(), dtype)
+            })
+            .collect();
+        while fields.len() < arity {
+            fields.push(Field::new(
+                format!("_arg{}", fields.len()).into(),
+                DataType::Null,
+            ));
+        }
+        renamed.insert(start, format!("{}__{}", function.name, calls.len()));
+        calls.push(Call { function, fields });
+    }
+    let mut out = String::with_capacity(sql.len());
+    let mut i = 0;
+    for token in &tokens {
+        if matches!(token, Token::Whitespace(_)) {
+            out.push_str(&token.to_string());
+            continue;
+        }
+        if let Some(ids) = appended.get(&i) {
+            for id in ids {
+                out.push_str(&format!(", {}", Token::make_word(id, Some('"'))));
+            }
+        }
+        match renamed.get(&i) {
+            Some(name) => out.push_str(name),
+            None => out.push_str(&token.to_string()),
+        }
+        i += 1;
+    }
+    Ok((out, calls))
+}
+
+/// A polars [`SQLContext`] with spatial functions such as `ST_Distance`,
+/// `ST_Within` and `centroid_x` (see [`functions`]).
+///
+/// Geometry arguments are GeoJSON `geometry` columns; points are `{x, y}` structs
+/// such as those from `ST_Point` or `ST_Centroid`.
+pub struct SpatialSql {
+    ctx: SQLContext,
+    state: Arc<RwLock<State>>,
+}
+
+impl Default for SpatialSql {
+    fn default() -> Self {
+        SpatialSql::new()
+    }
+}
+
+impl SpatialSql {
+    pub fn new() -> Self {
+        let state = Arc::new(RwLock::new(State::default()));
+        let registry = Registry {
+            state: state.clone(),
+        };
+        SpatialSql {
+            ctx: SQLContext::new().with_function_registry(Arc::new(registry)),
+            state,
+        }
+    }
+
+    /// A context with the buildings cache at `path` registered as `buildings`,
+    /// downloading and converting it if needed.
+    pub fn buildings(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        load_data(path)?;
+        let mut sql = SpatialSql::new();
+        sql.register(
+            "buildings",
+            LazyFrame::scan_parquet(path, ScanArgsParquet::default())?,
+        )?;
+        Ok(sql)
+    }
+
+    pub fn register(&mut self, name: &str, mut lf: LazyFrame) -> PolarsResult<()> {
+        let schema = lf.collect_schema()?;
+        write(&self.state)?.columns.merge(Schema::clone(&schema));
+        self.ctx.register(name, lf);
+        Ok(())
+    }
+
+    /// Register a dataset's frame. Its geometries stay in the dataset's CRS, while
+    /// the distance functions expect longitude/latitude.
+    pub fn register_dataset(&mut self, name: &str, dataset: &Dataset) -> PolarsResult<()> {
+        self.register(name, dataset.lazy())
+    }
+
+    pub fn unregister(&mut self, name: &str) {
+        self.ctx.unregister(name);
+    }
+
+    pub fn tables(&self) -> Vec<String> {
+        self.ctx.get_tables()
+    }
+
+    pub fn schema(&self, name: &str) -> PolarsResult<SchemaRef> {
+        match self.ctx.get_table_map().get_mut(name) {
+            Some(lf) => lf.collect_schema(),
+            None => polars_bail!(SQLInterface: "no table named '{}'", name),
+        }
+    }
+
+    /// Plan `sql` against the registered tables.
+    pub fn query(&mut self, sql: &str) -> PolarsResult<LazyFrame> {
+        let (sql, calls) = number_calls(sql, &read(&self.state)?.columns)?;
+        write(&self.state)?.calls = calls;
+        self.ctx.execute(&sql)
+    }
+
+    /// Read `;`-terminated statements from `input` and print their results, along
+    /// with the `.tables`, `.schema <table>`, `.functions` and `.quit` commands.
+    /// Query errors are printed and the session carries on.
+    pub fn repl(&mut self, input: impl BufRead, mut out: impl Write) -> std::io::Result<()> {
+        let mut statement = String::new();
+        write!(out, "sql> ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let trimmed = line.trim();
+            if statement.is_empty() && trimmed.starts_with('.') {
+                let mut words = trimmed.split_whitespace();
+                match (words.next(), words.next()) {
+                    (Some(".quit" | ".exit"), _) => return Ok(()),
+                    (Some(".tables"), _) => {
+                        let mut tables = self.tables();
+                        tables.sort();
+                        for table in tables {
+                            writeln!(out, "{}", table)?;
+                        }
+                    }
+                    (Some(".schema"), Some(table)) => match self.schema(table) {
+                        Ok(schema) => {
+                            for (name, dtype) in schema.iter() {
+                                writeln!(out, "{}: {}", name, dtype)?;
+                            }
+                        }
+                        Err(e) => writeln!(out, "Error: {}", e)?,
+                    },
+                    (Some(".functions"), _) => {
+                        for (signature, doc) in functions() {
+                            writeln!(out, "{:<40} {}", signature, doc)?;
+                        }
+                    }
+                    _ => writeln!(out, "Commands: .tables, .schema <table>, .functions, .quit")?,
+                }
+            } else if !trimmed.is_empty() {
+                statement.push_str(&line);
+                statement.push('\n');
+                if trimmed.ends_with(';') {
+                    let sql = statement.trim().trim_end_matches(';').to_string();
+                    statement.clear();
+                    match self.query(&sql).and_then(|lf| lf.collect()) {
+                        Ok(df) => writeln!(out, "{}", df)?,
+                        Err(e) => writeln!(out, "Error: {}", e)?,
+                    }
+                }
+            }
+            write!(
+                out,
+                "{}",
+                if statement.is_empty() {
+                    "sql> "
+                } else {
+                    "...> "
+                }
+            )?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{buildings, ORIGIN};
+
+    fn sql() -> SpatialSql {
+        let (x, y) = ORIGIN;
+        let lf = buildings()
+            .lazy()
+            .with_columns([lit(x).alias("lon"), lit(y).alias("lat")]);
+        let mut sql = SpatialSql::new();
+        sql.register("buildings", lf).unwrap();
+        sql
+    }
+
+    fn floats(df: &DataFrame, name: &str) -> Vec<Option<f64>> {
+        df.column(name)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn repeated_calls_get_their_own_columns() {
+        let df = sql()
+            .query(
+                "SELECT ST_Area(geometry) AS a, ST_Area(geometry) AS b, \
+                 ST_Distance(ST_Centroid(geometry), ST_Point(lon, lat)) AS to_origin, \
+                 ST_Distance(ST_Point(lon, lat), ST_Centroid(geometry)) AS from_origin \
+                 FROM buildings",
+            )
+            .unwrap()
+            .collect()
+            .unwrap();
+        assert_eq!(floats(&df, "a"), floats(&df, "b"));
+        assert_eq!(floats(&df, "to_origin"), floats(&df, "from_origin"));
+        let to_origin = floats(&df, "to_origin");
+        assert!(to_origin[0].unwrap() < 100.0);
+        assert!(to_origin[1].unwrap() > 1000.0);
+        assert_eq!(to_origin[3], None);
+    }
+
+    #[test]
+    fn nested_calls_may_use_more_columns_than_arguments() {
+        let df = sql()
+            .query(
+                "SELECT OBJECTID, ST_X(ST_Centroid(geometry)) AS x, centroid_x(geometry) AS cx \
+                 FROM buildings \
+                 WHERE ST_DWithin(ST_Point(lon, lat), ST_Point(centroid_x(geometry), centroid_y(geometry)), 2000)",
+            )
+            .unwrap()
+            .collect()
+            .unwrap();
+        let ids: Vec<_> = df
+            .column("OBJECTID")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(ids, [Some(1), Some(2)]);
+        assert_eq!(floats(&df, "x"), floats(&df, "cx"));
+    }
+
+    #[test]
+    fn relations_against_literal_envelopes() {
+        let (x, y) = ORIGIN;
+        let df = sql()
+            .query(&format!(
+                "SELECT OBJECTID FROM buildings \
+                 WHERE ST_Within(geometry, ST_MakeEnvelope({}, {}, {}, {})) \
+                 OR ST_Intersects(ST_Point(lon, lat), geometry)",
+                x + 0.005,
+                y + 0.005,
+                x + 0.015,
+                y + 0.015
+            ))
+            .unwrap()
+            .collect()
+            .unwrap();
+        let ids: Vec<_> = df
+            .column("OBJECTID")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(ids, [Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn unknown_calls_and_registration_are_errors() {
+        let mut sql = sql();
+        assert!(sql
+            .query("SELECT st_area__7(geometry) FROM buildings")
+            .is_err());
+        assert!(sql
+            .query("SELECT ST_Area(geometry, OBJECTID) FROM buildings")
+            .is_err());
+        assert!(number_calls("SELECT ST_Area(geometry", &Schema::default())
+            .unwrap()
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn calls_are_renamed_with_extra_columns() {
+        let (sql, calls) = number_calls(
+            "SELECT st_distance(ST_Point(a, b), st_point(c, \"d\")) FROM t",
+            &Schema::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT st_distance__0(st_point__1(a, b), st_point__2(c, \"d\"), \"c\", \"d\") FROM t"
+        );
+        let names: Vec<_> = calls[0].fields.iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["a", "b", "c", "d"]);
+        assert_eq!(calls[1].fields.len(), 2);
+    }
+}
@@ -0,0 +1,115 @@
+//! Small in-memory fixtures shaped like the buildings data.
+
+use polars::prelude::*;
+
+/// A `[x, y]` GeoJSON position.
+pub fn pos(x: f64, y: f64) -> Series {
+    Series::new(PlSmallStr::EMPTY, &[x, y])
+}
+
+/// A list series of `items`, one nesting level up.
+pub fn list(items: Vec<Series>) -> Series {
+    Series::new(PlSmallStr::EMPTY, items)
+}
+
+pub fn ring(points: &[(f64, f64)]) -> Series {
+    list(points.iter().map(|&(x, y)| pos(x, y)).collect())
+}
+
+/// A closed ring around the square with corner `(x, y)` and side `d`.
+pub fn square(x: f64, y: f64, d: f64) -> Series {
+    ring(&[(x, y), (x + d, y), (x + d, y + d), (x, y + d), (x, y)])
+}
+
+/// A GeoJSON `geometry` struct column with one `kind` and coordinates per row.
+pub fn geometries(name: &str, rows: Vec<Option<(&str, Series)>>) -> Column {
+    let types: Vec<Option<&str>> = rows.iter().map(|r| r.as_ref().map(|(t, _)| *t)).collect();
+    let coordinates: Vec<Option<Series>> = rows.into_iter().map(|r| r.map(|(_, c)| c)).collect();
+    let fields = [
+        Series::new("type".into(), types),
+        Series::new("coordinates".into(), coordinates),
+    ];
+    StructChunked::from_series(name.into(), fields[0].len(), fields.iter())
+        .unwrap()
+        .into_column()
+}
+
+/// A Polygon `geometry` column, one ring list per row.
+pub fn polygons(rows: Vec<Option<Series>>) -> Column {
+    geometries(
+        "geometry",
+        rows.into_iter()
+            .map(|r| r.map(|c| ("Polygon", c)))
+            .collect(),
+    )
+}
+
+pub const ORIGIN: (f64, f64) = (114.1441448, 22.2878391);
+
+/// Five buildings around [`ORIGIN`]: a tower, a house, a block with a courtyard
+/// and an implausible height, a row with no attributes or footprint, and a
+/// small block with a large floor area.
+pub fn buildings() -> DataFrame {
+    let (x, y) = ORIGIN;
+    let geometry = polygons(vec![
+        Some(list(vec![square(x, y, 0.0005)])),
+        Some(list(vec![square(x + 0.01, y + 0.01, 0.0003)])),
+        Some(list(vec![
+            square(x + 0.1, y + 0.05, 0.001),
+            square(x + 0.1002, y + 0.0502, 0.0002).reverse(),
+        ])),
+        None,
+        Some(list(vec![square(x - 0.02, y - 0.01, 0.0004)])),
+    ]);
+    DataFrame::new(vec![
+        Column::new("OBJECTID".into(), &[1i64, 2, 3, 4, 5]),
+        Column::new(
+            "TOPHEIGHT".into(),
+            &[Some(120.0), Some(30.0), Some(-1.0), None, Some(15.0)],
+        ),
+        Column::new(
+            "NUMABOVEGROUNDSTOREYS".into(),
+            &[Some(55.0), Some(10.0), Some(3.0), None, Some(4.0)],
+        ),
+        Column::new(
+            "GROSSFLOORAREA".into(),
+            &[
+                Some(25000.0),
+                Some(3000.0),
+                Some(900.0),
+                None,
+                Some(12000.0),
+            ],
+        ),
+        Column::new(
+            "OFFICIALBUILDINGNAMEEN".into(),
+            &[
+                Some("A Tower"),
+                Some("B House"),
+                None,
+                None,
+                Some("E Block"),
+            ],
+        ),
+        Column::new(
+            "RECORDCREATIONDATE".into(),
+            &[
+                "2005-01-01T00:00:00Z",
+                "2010-06-01T00:00:00Z",
+                "2010-07-01T00:00:00Z",
+                "2020-01-01T00:00:00Z",
+                "2005-05-05T00:00:00Z",
+            ],
+        ),
+        geometry,
+    ])
+    .unwrap()
+}
+
+/// A fresh, empty directory under the system temp dir for one test.
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("polars-demo-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
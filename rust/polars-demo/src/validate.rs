@@ -0,0 +1,351 @@
+use crate::crs::keep_crs;
+use crate::geometry::{
+    decode_geometries, encode_like, encoded_like_dtype, raw_polygon_rings, DecodeError,
+};
+use geo::orient::{Direction, Orient};
+use geo::{Geometry, Line, LineIntersection, LineString, RemoveRepeatedPoints, Winding};
+use polars::prelude::*;
+use std::fmt;
+
+/// Why a polygon ring is invalid.
+///
+/// Winding follows GeoJSON (RFC 7946): exterior rings counter-clockwise,
+/// holes clockwise.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    Undecodable(DecodeError),
+    UnclosedRing,
+    TooFewPositions(usize),
+    DuplicateVertex,
+    SelfIntersection,
+    WrongWinding,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Undecodable(e) => write!(f, "undecodable: {}", e),
+            Issue::UnclosedRing => write!(f, "unclosed ring"),
+            Issue::TooFewPositions(n) => write!(f, "ring has {} positions, fewer than 4", n),
+            Issue::DuplicateVertex => write!(f, "repeated consecutive vertex"),
+            Issue::SelfIntersection => write!(f, "self-intersection"),
+            Issue::WrongWinding => write!(f, "wrong winding order"),
+        }
+    }
+}
+
+/// An [`Issue`] found on ring `ring` (0 is the exterior) of polygon `part` of a row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalid {
+    pub row: usize,
+    pub part: usize,
+    pub ring: usize,
+    pub issue: Issue,
+}
+
+/// Check the rings of every Polygon and MultiPolygon in a `geometry` column.
+///
+/// Rings are read as stored, so unclosed rings are reported rather than
+/// silently closed as they are when decoding.
+pub fn validate_geometries(geometry: &Column) -> PolarsResult<Vec<Invalid>> {
+    let mut found = Vec::new();
+    for (row, polygons) in raw_polygon_rings(geometry)?.into_iter().enumerate() {
+        let polygons = match polygons {
+            Ok(polygons) => polygons,
+            Err(e) => {
+                let issue = Issue::Undecodable(e);
+                found.push(Invalid {
+                    row,
+                    part: 0,
+                    ring: 0,
+                    issue,
+                });
+                continue;
+            }
+        };
+        for (part, rings) in polygons.iter().enumerate() {
+            for (ring, ls) in rings.iter().enumerate() {
+                for issue in ring_issues(ls, ring == 0) {
+                    found.push(Invalid {
+                        row,
+                        part,
+                        ring,
+                        issue,
+                    });
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn ring_issues(ring: &LineString, exterior: bool) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    if !ring.is_closed() {
+        issues.push(Issue::UnclosedRing);
+    }
+    if ring.0.len() < 4 {
+        issues.push(Issue::TooFewPositions(ring.0.len()));
+    }
+    if ring.0.windows(2).any(|w| w[0] == w[1]) {
+        issues.push(Issue::DuplicateVertex);
+    }
+    let mut closed = ring.remove_repeated_points();
+    closed.close();
+    if closed.0.len() < 4 {
+        return issues;
+    }
+    if self_intersects(&closed) {
+        issues.push(Issue::SelfIntersection);
+    }
+    if !issues.contains(&Issue::SelfIntersection) {
+        let ccw = closed.is_ccw();
+        if ccw != exterior && closed.winding_order().is_some() {
+            issues.push(Issue::WrongWinding);
+        }
+    }
+    issues
+}
+
+/// Whether any two edges of a closed ring without repeated vertices cross,
+/// touch away from their shared vertex, or overlap.
+pub(crate) fn self_intersects(ring: &LineString) -> bool {
+    let lines: Vec<Line> = ring.lines().collect();
+    let n = lines.len();
+    for i in 0..n {
+        for j in i + 1..n {
+            let adjacent = j == i + 1 || (i == 0 && j == n - 1);
+            match geo::line_intersection::line_intersection(lines[i], lines[j]) {
+                None => {}
+                Some(LineIntersection::Collinear { .. }) => return true,
+                Some(LineIntersection::SinglePoint { .. }) if adjacent => {}
+                Some(LineIntersection::SinglePoint { .. }) => return true,
+            }
+        }
+    }
+    false
+}
+
+/// Validation failures as an `OBJECTID`/`part`/`ring`/`reason` frame, one row
+/// per issue, for a frame with `OBJECTID` and `geometry` columns.
+pub fn invalid_features(lf: LazyFrame) -> PolarsResult<DataFrame> {
+    let df = lf.select([col("OBJECTID"), col("geometry")]).collect()?;
+    let found = validate_geometries(df.column("geometry")?)?;
+    let rows = IdxCa::from_vec(
+        "row".into(),
+        found.iter().map(|f| f.row as IdxSize).collect(),
+    );
+    let ids = df.column("OBJECTID")?.take(&rows)?;
+    DataFrame::new(vec![
+        ids,
+        Column::new(
+            "part".into(),
+            found.iter().map(|f| f.part as u32).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "ring".into(),
+            found.iter().map(|f| f.ring as u32).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "reason".into(),
+            found
+                .iter()
+                .map(|f| f.issue.to_string())
+                .collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+/// Repair polygon geometries: close rings, drop repeated consecutive vertices
+/// and orient rings as GeoJSON expects.
+///
+/// Self-intersections are not fixed and rings that are still too short are
+/// kept, so validate again afterwards. Undecodable rows become null, and the
+/// column keeps its coordinate nesting, with float coordinates.
+pub fn repair(geometry: Expr) -> Expr {
+    geometry.map(
+        |s| {
+            let decoded = decode_geometries(&s)?;
+            let repaired: Vec<Option<Geometry>> = decoded
+                .geometries
+                .into_iter()
+                .map(|g| g.map(repair_geometry))
+                .collect();
+            keep_crs(&s, encode_like(&s, &repaired)?).map(Some)
+        },
+        GetOutput::map_dtype(|dtype| Ok(encoded_like_dtype(dtype))),
+    )
+}
+
+fn repair_geometry(g: Geometry) -> Geometry {
+    // decoding already closed every ring
+    match g.remove_repeated_points() {
+        Geometry::Polygon(p) => Geometry::Polygon(p.orient(Direction::Default)),
+        Geometry::MultiPolygon(mp) => Geometry::MultiPolygon(mp.orient(Direction::Default)),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{geometries, list, polygons, ring, square};
+
+    const CW: [(f64, f64); 5] = [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+
+    fn issues(rings: Vec<Series>) -> Vec<(usize, Issue)> {
+        let found = validate_geometries(&polygons(vec![Some(list(rings))])).unwrap();
+        found.into_iter().map(|f| (f.ring, f.issue)).collect()
+    }
+
+    #[test]
+    fn valid_polygon_with_a_hole() {
+        let hole = ring(&[(1.0, 1.0), (1.0, 2.0), (2.0, 2.0), (2.0, 1.0), (1.0, 1.0)]);
+        assert!(issues(vec![square(0.0, 0.0, 4.0), hole]).is_empty());
+    }
+
+    #[test]
+    fn each_ring_issue() {
+        let unclosed = ring(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        assert_eq!(issues(vec![unclosed]), [(0, Issue::UnclosedRing)]);
+
+        let short = ring(&[(0.0, 0.0), (1.0, 0.0), (0.0, 0.0)]);
+        assert_eq!(issues(vec![short]), [(0, Issue::TooFewPositions(3))]);
+
+        let repeated = ring(&[(0.0, 0.0), (1.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+        assert_eq!(issues(vec![repeated]), [(0, Issue::DuplicateVertex)]);
+
+        let bowtie = ring(&[(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0), (0.0, 0.0)]);
+        assert_eq!(issues(vec![bowtie]), [(0, Issue::SelfIntersection)]);
+
+        assert_eq!(issues(vec![ring(&CW)]), [(0, Issue::WrongWinding)]);
+        let ccw_hole = square(1.0, 1.0, 1.0);
+        assert_eq!(
+            issues(vec![square(0.0, 0.0, 4.0), ccw_hole]),
+            [(1, Issue::WrongWinding)]
+        );
+    }
+
+    #[test]
+    fn rows_parts_and_undecodable_geometries() {
+        let column = geometries(
+            "geometry",
+            vec![
+                Some((
+                    "MultiPolygon",
+                    list(vec![list(vec![square(0.0, 0.0, 1.0)])]),
+                )),
+                None,
+                Some((
+                    "MultiPolygon",
+                    list(vec![
+                        list(vec![square(0.0, 0.0, 1.0)]),
+                        list(vec![ring(&CW)]),
+                    ]),
+                )),
+                // positions need at least two coordinates
+                Some((
+                    "MultiPolygon",
+                    list(vec![list(vec![list(vec![Series::new(
+                        PlSmallStr::EMPTY,
+                        &[1.0],
+                    )])])]),
+                )),
+            ],
+        );
+        let found = validate_geometries(&column).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].row, found[0].part, found[0].ring), (2, 1, 0));
+        assert_eq!(found[0].issue, Issue::WrongWinding);
+        assert_eq!(found[1].row, 3);
+        assert!(matches!(found[1].issue, Issue::Undecodable(_)));
+    }
+
+    #[test]
+    fn invalid_features_by_objectid() {
+        let df = DataFrame::new(vec![
+            Column::new("OBJECTID".into(), [10i64, 20]),
+            polygons(vec![
+                Some(list(vec![square(0.0, 0.0, 1.0)])),
+                Some(list(vec![ring(&CW)])),
+            ]),
+        ])
+        .unwrap();
+        let out = invalid_features(df.lazy()).unwrap();
+        assert_eq!(out.height(), 1);
+        assert_eq!(
+            out.column("OBJECTID").unwrap().i64().unwrap().get(0),
+            Some(20)
+        );
+        assert_eq!(
+            out.column("reason").unwrap().str().unwrap().get(0),
+            Some("wrong winding order")
+        );
+    }
+
+    #[test]
+    fn repair_fixes_all_but_self_intersections() {
+        let unclosed = ring(&[(0.0, 0.0), (0.0, 1.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]);
+        let bowtie = ring(&[(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0), (0.0, 0.0)]);
+        let df = DataFrame::new(vec![polygons(vec![
+            Some(list(vec![unclosed])),
+            Some(list(vec![bowtie])),
+            None,
+        ])])
+        .unwrap();
+        let repaired = df
+            .lazy()
+            .select([repair(col("geometry"))])
+            .collect()
+            .unwrap();
+        let found = validate_geometries(repaired.column("geometry").unwrap()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            (found[0].row, &found[0].issue),
+            (1, &Issue::SelfIntersection)
+        );
+        let decoded = decode_geometries(repaired.column("geometry").unwrap()).unwrap();
+        assert!(decoded.geometries[2].is_none());
+    }
+
+    #[test]
+    fn repair_declares_the_type_it_returns() {
+        let int_coords = |dtype: &DataType| -> DataType {
+            let DataType::Struct(fields) = dtype else {
+                unreachable!()
+            };
+            let list = |dt| DataType::List(Box::new(dt));
+            DataType::Struct(vec![
+                fields[0].clone(),
+                Field::new("coordinates".into(), list(list(list(DataType::Int64)))),
+            ])
+        };
+        let polygon = polygons(vec![Some(list(vec![square(0.0, 0.0, 2.0)])), None]);
+        let ints = polygon.cast(&int_coords(polygon.dtype())).unwrap();
+        let multi = geometries(
+            "geometry",
+            vec![
+                Some((
+                    "MultiPolygon",
+                    list(vec![list(vec![square(2.0, 0.0, 1.0)])]),
+                )),
+                None,
+            ],
+        );
+        for (column, kinds) in [
+            (ints, vec![Some("Polygon"), None]),
+            (multi, vec![Some("MultiPolygon"), None]),
+        ] {
+            let lf = DataFrame::new(vec![column])
+                .unwrap()
+                .lazy()
+                .select([repair(col("geometry"))]);
+            let declared = lf.clone().collect_schema().unwrap();
+            let df = lf.collect().unwrap();
+            let geometry = df.column("geometry").unwrap();
+            assert_eq!(declared.get("geometry"), Some(geometry.dtype()));
+            let types = geometry.struct_().unwrap().field_by_name("type").unwrap();
+            assert_eq!(types.str().unwrap().into_iter().collect::<Vec<_>>(), kinds);
+        }
+    }
+}